@@ -148,6 +148,51 @@ describe('VocaClient', () => {
             const [url, options] = (fetchMock as any).mock.calls[0];
             expect(url).toContain('password=testpassword');
         });
+
+        it('should request an SFU room when topology is sfu', async () => {
+            const fetchMock = mock(() => Promise.resolve({
+                ok: true,
+                json: () => Promise.resolve({ room: 'big-room', topology: 'sfu' }),
+            } as Response));
+            globalThis.fetch = fetchMock;
+
+            await VocaClient.createRoom({ topology: 'sfu' });
+
+            const [url] = (fetchMock as any).mock.calls[0];
+            expect(url).toContain('topology=sfu');
+        });
+
+        it('should reserve a vanity room and keep its host key', async () => {
+            const fetchMock = mock(() => Promise.resolve({
+                ok: true,
+                json: () => Promise.resolve({ room: 'standup-backend', topology: 'mesh', host_key: 'secret-key' }),
+            } as Response));
+            globalThis.fetch = fetchMock;
+
+            const client = await VocaClient.createRoom({ room: 'standup-backend', persistent: true });
+
+            const [url] = (fetchMock as any).mock.calls[0];
+            expect(url).toContain('room=standup-backend');
+            expect(url).toContain('persistent=true');
+            expect(client.roomId).toBe('standup-backend');
+            expect(client.hostKey).toBe('secret-key');
+
+            await client.connect();
+            // @ts-ignore - access private ws to check URL
+            expect(client.ws.url).toContain('hostKey=secret-key');
+        });
+
+        it('should delete a reserved room with its host key', async () => {
+            const fetchMock = mock(() => Promise.resolve({ ok: true } as Response));
+            globalThis.fetch = fetchMock;
+
+            await VocaClient.deleteRoom('standup-backend', { hostKey: 'secret-key' });
+
+            const [url, options] = (fetchMock as any).mock.calls[0];
+            expect(url).toContain('/api/room/standup-backend');
+            expect(options.method).toBe('DELETE');
+            expect(options.headers['x-host-key']).toBe('secret-key');
+        });
     });
 
     describe('validatePassword', () => {
@@ -229,6 +274,554 @@ describe('VocaClient', () => {
             const [error] = errorHandler.mock.calls[0];
             expect(error.code).toBe(VocaErrorCode.INVALID_PASSWORD);
         });
+
+        it('should pass through the retryable hint from the server', async () => {
+            const client = new VocaClient('test-room');
+            const errorHandler = mock();
+            client.on('error', errorHandler);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'server',
+                    type: 'error',
+                    code: 'room_full',
+                    message: 'Room is at maximum capacity',
+                    retryable: true
+                })
+            });
+
+            const [error] = errorHandler.mock.calls[0];
+            expect(error.code).toBe(VocaErrorCode.ROOM_FULL);
+            expect(error.retryable).toBe(true);
+        });
+    });
+
+    describe('app messages', () => {
+        it('should emit app-message events for relayed app payloads', async () => {
+            const client = new VocaClient('test-room');
+            const handler = mock();
+            client.on('app-message', handler);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'peer1', type: 'app', kind: 'reaction', data: { emoji: '👍' } })
+            });
+
+            expect(handler).toHaveBeenCalledWith('peer1', 'reaction', { emoji: '👍' });
+        });
+
+        it('should treat rejected app messages as warnings', async () => {
+            const client = new VocaClient('test-room');
+            const warning = mock();
+            const error = mock();
+            client.on('warning', warning);
+            client.on('error', error);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'server',
+                    type: 'error',
+                    code: 'payload_too_large',
+                    message: 'too big',
+                    retryable: false
+                })
+            });
+
+            expect(warning).toHaveBeenCalled();
+            expect(error).not.toHaveBeenCalled();
+        });
+    });
+
+    describe('chat', () => {
+        it('should deliver history from welcome and live messages', async () => {
+            const client = new VocaClient('test-room');
+            const history = mock();
+            const chat = mock();
+            client.on('chat-history', history);
+            client.on('chat', chat);
+
+            await client.connect();
+
+            const earlier = { id: 'm1', from: 'peer1', text: 'hi', ts: 1, edited: false };
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me', host: 'peer1', chat: [earlier] })
+            });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'peer1', type: 'chat', id: 'm2', text: 'link', ts: 2 })
+            });
+
+            expect(history).toHaveBeenCalledWith([earlier]);
+            expect(chat).toHaveBeenCalledWith({ id: 'm2', from: 'peer1', text: 'link', ts: 2, edited: false });
+            expect(client.hostId).toBe('peer1');
+        });
+    });
+
+    describe('peer state', () => {
+        it('should track roster state and merge updates', async () => {
+            const client = new VocaClient('test-room');
+            const handler = mock();
+            client.on('peer-state', handler);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'server',
+                    type: 'welcome',
+                    peer_id: 'me',
+                    states: { peer1: { name: 'Ann', muted: true, metadata: { role: 'dj' }, joined_at: 1 } }
+                })
+            });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'peer1',
+                    type: 'peer_update',
+                    peer_id: 'peer1',
+                    changes: { name: '', metadata: { role: null, seat: 2 } }
+                })
+            });
+
+            expect(handler).toHaveBeenCalledTimes(2);
+            expect(client.peerStates.get('peer1')).toEqual({
+                name: undefined,
+                muted: true,
+                metadata: { seat: 2 },
+                joined_at: 1
+            });
+        });
+    });
+
+    describe('end-to-end encryption', () => {
+        it('should track key epochs and surface key packages', async () => {
+            const client = new VocaClient('test-room');
+            const epoch = mock();
+            const pkg = mock();
+            client.on('key-epoch', epoch);
+            client.on('key-package', pkg);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'server', type: 'key_epoch', epoch: 3, distributor: 'peer1' })
+            });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'peer1', type: 'key_package', to: 'me', epoch: 3, ciphertext: 'abc' })
+            });
+
+            expect(client.keyEpoch).toBe(3);
+            expect(epoch).toHaveBeenCalledWith(3, 'peer1');
+            expect(pkg).toHaveBeenCalledWith('peer1', 3, 'abc');
+        });
+    });
+
+    describe('sfu topology', () => {
+        it('should list peers from welcome and share one connection', async () => {
+            const created: any[] = [];
+            (globalThis as any).RTCPeerConnection = class {
+                close = mock();
+                addTrack = mock();
+                constructor() { created.push(this); }
+            };
+            const client = new VocaClient('test-room');
+            const joined = mock();
+            client.on('peer-joined', joined);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me', peers: ['peer1'], topology: 'sfu' })
+            });
+            // @ts-ignore - trigger onmessage
+            await client.ws.onmessage({
+                data: JSON.stringify({ from: 'peer2', type: 'join', peer_id: 'peer2' })
+            });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'peer1', type: 'leave', peer_id: 'peer1' })
+            });
+
+            expect(client.topology).toBe('sfu');
+            expect(joined).toHaveBeenCalledWith('peer1');
+            expect(joined).toHaveBeenCalledWith('peer2');
+            expect(created.length).toBe(1);
+            expect(created[0].close).not.toHaveBeenCalled();
+            expect(client.peers.has('peer2')).toBe(true);
+        });
+
+        it('should move peers between mesh and sfu on topology change', async () => {
+            const created: any[] = [];
+            (globalThis as any).RTCPeerConnection = class {
+                close = mock();
+                addTrack = mock();
+                createOffer = mock(() => Promise.resolve({ type: 'offer', sdp: 'offer' }));
+                setLocalDescription = mock(() => Promise.resolve());
+                constructor() { created.push(this); }
+            };
+            const client = new VocaClient('test-room');
+            const joined = mock();
+            client.on('peer-joined', joined);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me', peers: [] }) });
+            // @ts-ignore - trigger onmessage
+            await client.ws.onmessage({ data: JSON.stringify({ from: 'peer1', type: 'join', peer_id: 'peer1', role: 'impolite' }) });
+            // @ts-ignore - trigger onmessage
+            await client.ws.onmessage({
+                data: JSON.stringify({ from: 'server', type: 'topology_change', topology: 'sfu', peers: ['me', 'peer1'] })
+            });
+
+            expect(client.topology).toBe('sfu');
+            expect(created[0].close).toHaveBeenCalled();
+            expect(client.peers.get('peer1')?.connection).toBe(created[1]);
+
+            // @ts-ignore - trigger onmessage
+            await client.ws.onmessage({
+                data: JSON.stringify({ from: 'server', type: 'topology_change', topology: 'mesh', peers: ['me', 'peer1'] })
+            });
+
+            expect(client.topology).toBe('mesh');
+            expect(created[1].close).toHaveBeenCalled();
+            // We joined first, so we make the mesh offer again
+            expect(created[2].createOffer).toHaveBeenCalled();
+            expect(joined).toHaveBeenCalledTimes(1);
+        });
+    });
+
+    describe('recording', () => {
+        it('should track the recording and peer consent', async () => {
+            const client = new VocaClient('test-room');
+            const started = mock();
+            const stopped = mock();
+            const consent = mock();
+            client.on('recording-started', started);
+            client.on('recording-stopped', stopped);
+            client.on('recording-consent', consent);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'server',
+                    type: 'welcome',
+                    peer_id: 'me',
+                    recording: 'rec1',
+                    states: { peer1: { muted: false, recording_consent: true, joined_at: 1 } }
+                })
+            });
+            expect(client.recordingId).toBe('rec1');
+            expect(started).toHaveBeenCalledWith('rec1', null);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'peer1', type: 'recording_stopped', id: 'rec1' }) });
+            expect(client.recordingId).toBeNull();
+            expect(stopped).toHaveBeenCalledWith('rec1');
+            expect(client.peerStates.get('peer1')?.recording_consent).toBe(false);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'peer1', type: 'recording_started', id: 'rec2', by: 'peer1' }) });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'peer1', type: 'recording_consent', peer_id: 'peer1', consent: true }) });
+
+            expect(started).toHaveBeenCalledWith('rec2', 'peer1');
+            expect(consent).toHaveBeenCalledWith('peer1', true);
+            expect(client.peerStates.get('peer1')?.recording_consent).toBe(true);
+        });
+
+        it('should treat an unavailable recorder as a warning', async () => {
+            const client = new VocaClient('test-room');
+            const warning = mock();
+            const error = mock();
+            client.on('warning', warning);
+            client.on('error', error);
+
+            await client.connect();
+            client.startRecording();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({ from: 'server', type: 'error', code: 'recording_unavailable', message: 'Recording is not enabled on this server' })
+            });
+
+            expect(warning).toHaveBeenCalledWith({ code: 'recording_unavailable', message: 'Recording is not enabled on this server' });
+            expect(error).not.toHaveBeenCalled();
+        });
+    });
+
+    describe('stage', () => {
+        it('should connect fan-out listeners only to speakers', async () => {
+            const created: any[] = [];
+            (globalThis as any).RTCPeerConnection = class {
+                close = mock();
+                addTrack = mock();
+                addTransceiver = mock();
+                createOffer = mock(() => Promise.resolve({ type: 'offer', sdp: 'offer' }));
+                setLocalDescription = mock(() => Promise.resolve());
+                constructor() { created.push(this); }
+            };
+            const client = new VocaClient('test-room');
+            const roleChange = mock();
+            client.on('stage-role', roleChange);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'server',
+                    type: 'welcome',
+                    peer_id: 'me',
+                    topology: 'fanout',
+                    stage_role: 'listener',
+                    roles: { host1: 'polite' },
+                    states: { host1: { muted: false, stage_role: 'speaker', joined_at: 1 } }
+                })
+            });
+            expect(client.stageRole).toBe('listener');
+            expect(client.localStream!.getAudioTracks()[0].enabled).toBe(false);
+
+            // @ts-ignore - trigger onmessage
+            await client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'peer2',
+                    type: 'join',
+                    peer_id: 'peer2',
+                    role: 'impolite',
+                    state: { muted: false, stage_role: 'listener', joined_at: 2 }
+                })
+            });
+            expect(created).toHaveLength(0);
+            expect(client.peers.has('peer2')).toBe(false);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'host1', type: 'set_role', peer_id: 'me', stage_role: 'speaker' }) });
+            await new Promise((resolve) => setTimeout(resolve, 0));
+
+            expect(roleChange).toHaveBeenCalledWith('me', 'speaker');
+            expect(client.localStream!.getAudioTracks()[0].enabled).toBe(true);
+            // Now a speaker: wait for the host's offer, make our own to the listener
+            expect(created).toHaveLength(2);
+            expect(created[0].createOffer).not.toHaveBeenCalled();
+            expect(created[1].createOffer).toHaveBeenCalled();
+            expect(created[1].addTrack).toHaveBeenCalled();
+        });
+
+        it('should track requests to speak and role changes', async () => {
+            (globalThis as any).RTCPeerConnection = class {
+                close = mock();
+                addTrack = mock();
+            };
+            const client = new VocaClient('test-room');
+            const request = mock();
+            client.on('speak-request', request);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({
+                data: JSON.stringify({
+                    from: 'server',
+                    type: 'welcome',
+                    peer_id: 'me',
+                    topology: 'sfu',
+                    stage_role: 'speaker',
+                    peers: ['peer1'],
+                    states: { peer1: { muted: false, stage_role: 'listener', joined_at: 1 } }
+                })
+            });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'peer1', type: 'speak_request', peer_id: 'peer1' }) });
+
+            expect(request).toHaveBeenCalledWith('peer1');
+            expect(client.peerStates.get('peer1')?.speak_requested).toBe(true);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'me', type: 'set_role', peer_id: 'peer1', stage_role: 'speaker' }) });
+
+            expect(client.peerStates.get('peer1')).toEqual({ muted: false, stage_role: 'speaker', speak_requested: false, joined_at: 1 });
+            expect(client.peers.has('peer1')).toBe(true);
+        });
+    });
+
+    describe('waitlist', () => {
+        it('should ask to wait when configured', async () => {
+            const client = new VocaClient('test-room', { waitlist: true });
+            await client.connect();
+
+            // @ts-ignore - access private ws to check URL
+            expect(client.ws.url).toContain('wait=true');
+        });
+
+        it('should report queue position until admitted', async () => {
+            const client = new VocaClient('test-room', { waitlist: true });
+            const statuses: string[] = [];
+            const positions: number[] = [];
+            client.on('status', (status) => statuses.push(status));
+            client.on('waiting', (position) => positions.push(position));
+
+            await client.connect();
+            await new Promise((resolve) => setTimeout(resolve, 0));
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'waiting', position: 2 }) });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'waiting', position: 1 }) });
+            expect(client.status).toBe('waiting');
+            expect(client.waitPosition).toBe(1);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me' }) });
+
+            expect(positions).toEqual([2, 1]);
+            expect(statuses.slice(-2)).toEqual(['waiting', 'connected']);
+            expect(client.status).toBe('connected');
+            expect(client.waitPosition).toBeNull();
+        });
+    });
+
+    describe('scheduled rooms', () => {
+        it('should send start and end times when creating a room', async () => {
+            const fetchMock = mock(() => Promise.resolve({
+                ok: true,
+                json: () => Promise.resolve({ room: 'standup', topology: 'mesh', opens_at: 1800000000000, closes_at: 1800003600000 }),
+            } as Response));
+            globalThis.fetch = fetchMock;
+
+            await VocaClient.createRoom({ startsAt: new Date(1800000000000), endsAt: 1800003600000 });
+
+            const [url] = (fetchMock as any).mock.calls[0];
+            expect(url).toContain('starts_at=1800000000000');
+            expect(url).toContain('ends_at=1800003600000');
+        });
+
+        it('should warn before the room closes and stop when it does', async () => {
+            const client = new VocaClient('test-room');
+            const warnings: number[] = [];
+            const errors: string[] = [];
+            client.on('closing', (secondsLeft) => warnings.push(secondsLeft));
+            client.on('error', (error) => errors.push(error.code));
+
+            await client.connect();
+            await new Promise((resolve) => setTimeout(resolve, 0));
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me', closes_at: 1800003600000 }) });
+            expect(client.closesAt).toBe(1800003600000);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'closing', closes_at: 1800003600000, seconds_left: 300 }) });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'closing', closes_at: 1800003600000, seconds_left: 60 }) });
+            expect(warnings).toEqual([300, 60]);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'error', code: 'room_closed', message: 'Room has closed', retryable: false }) });
+            expect(errors).toEqual(['room_closed']);
+            expect(client.status).toBe('error');
+        });
+    });
+
+    describe('floor', () => {
+        it('should track raised hands and the floor holder', async () => {
+            const client = new VocaClient('test-room');
+            const floor = mock();
+            client.on('floor', floor);
+
+            await client.connect();
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me' }) });
+            expect(floor).not.toHaveBeenCalled();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'me', type: 'floor', queue: ['me', 'peer1'], single_speaker: false }) });
+            expect(floor).toHaveBeenCalledWith({ queue: ['me', 'peer1'], holder: null, single_speaker: false });
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'host1', type: 'floor', queue: ['peer1'], holder: 'me', single_speaker: false }) });
+            expect(client.floor).toEqual({ queue: ['peer1'], holder: 'me', single_speaker: false });
+        });
+
+        it('should keep non-holders muted in single-speaker mode', async () => {
+            const client = new VocaClient('test-room');
+            const forceMute = mock();
+            client.on('force-mute', forceMute);
+
+            await client.connect();
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me' }) });
+            expect(client.isMuted).toBe(false);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'force_mute', peer_id: 'me' }) });
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'host1', type: 'floor', queue: [], single_speaker: true }) });
+
+            expect(forceMute).toHaveBeenCalledWith('me');
+            expect(client.isMuted).toBe(true);
+            expect(client.localStream!.getAudioTracks()[0].enabled).toBe(false);
+
+            // Not holding the floor, so unmuting is refused locally
+            expect(client.toggleMute()).toBe(true);
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'host1', type: 'floor', queue: [], holder: 'me', single_speaker: true }) });
+            expect(client.toggleMute()).toBe(false);
+        });
+    });
+
+    describe('bots', () => {
+        it('should list bots in the roster without connecting to them', async () => {
+            const created: any[] = [];
+            (globalThis as any).RTCPeerConnection = class {
+                close = mock();
+                addTrack = mock();
+                constructor() { created.push(this); }
+            };
+            const client = new VocaClient('test-room');
+            const states = mock();
+            client.on('peer-state', states);
+
+            await client.connect();
+
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'server', type: 'welcome', peer_id: 'me', peers: [] }) });
+            const bot = { name: 'Notetaker', muted: false, joined_at: 1, bot: true };
+            // @ts-ignore - trigger onmessage
+            await client.ws.onmessage({ data: JSON.stringify({ from: 'bot1', type: 'join', peer_id: 'bot1', state: bot }) });
+
+            expect(states).toHaveBeenCalledWith('bot1', bot);
+            expect(client.peers.has('bot1')).toBe(false);
+            expect(created.length).toBe(0);
+        });
+
+        it('should emit announcements', async () => {
+            const client = new VocaClient('test-room');
+            const announcement = mock();
+            client.on('announcement', announcement);
+
+            await client.connect();
+            // @ts-ignore - trigger onmessage
+            client.ws.onmessage({ data: JSON.stringify({ from: 'bot1', type: 'announcement', text: 'Recording is on' }) });
+
+            expect(announcement).toHaveBeenCalledWith('bot1', 'Recording is on');
+        });
     });
 
     describe('connect', () => {
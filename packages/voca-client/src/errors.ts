@@ -2,14 +2,36 @@
  * Voca Error Codes
  * 
  * Unified error codes used across all Voca SDKs and the signaling server.
+ *
+ * Server codes mirror `ErrorCode` in services/voca-protocol/src/error.rs.
+ * Run `cargo run -- error-codes` there to print the authoritative list.
  */
 
 export const VocaErrorCode = {
     // Room errors
     ROOM_NOT_FOUND: 'room_not_found',
     ROOM_FULL: 'room_full',
+    ROOM_ID_TAKEN: 'room_id_taken',
+    ROOM_NOT_OPEN: 'room_not_open',
+    ROOM_CLOSED: 'room_closed',
+    INVALID_SCHEDULE: 'invalid_schedule',
     MAX_ROOMS_REACHED: 'max_rooms_reached',
     INVALID_ROOM_ID: 'invalid_room_id',
+    INVALID_ROOM_ID_LENGTH: 'invalid_room_id_length',
+    INVALID_ROOM_ID_CHARS: 'invalid_room_id_chars',
+    SLUG_GENERATION_FAILED: 'slug_generation_failed',
+
+    // Auth errors
+    INVALID_API_KEY: 'invalid_api_key',
+    UNAUTHORIZED: 'unauthorized',
+    ORIGIN_NOT_ALLOWED: 'origin_not_allowed',
+    BANNED: 'banned',
+    RATE_LIMITED: 'rate_limited',
+
+    // Server state
+    SERVER_DRAINING: 'server_draining',
+    SFU_UNAVAILABLE: 'sfu_unavailable',
+    RECORDING_UNAVAILABLE: 'recording_unavailable',
 
     // Connection errors
     CONNECTION_FAILED: 'connection_failed',
@@ -23,11 +45,36 @@ export const VocaErrorCode = {
 
     // Signaling errors
     INVALID_MESSAGE: 'invalid_message',
+    HANDSHAKE_REQUIRED: 'handshake_required',
+    HANDSHAKE_TIMEOUT: 'handshake_timeout',
+    WAITLIST_TIMEOUT: 'waitlist_timeout',
+    UNSUPPORTED_VERSION: 'unsupported_version',
+    PAYLOAD_TOO_LARGE: 'payload_too_large',
+    MESSAGE_KIND_NOT_ALLOWED: 'message_kind_not_allowed',
     PEER_NOT_FOUND: 'peer_not_found',
 
+    // Room feature errors
+    CHAT_MESSAGE_NOT_FOUND: 'chat_message_not_found',
+    NOT_PERMITTED: 'not_permitted',
+
+    // End-to-end encryption errors
+    STALE_KEY_EPOCH: 'stale_key_epoch',
+
+    // Streaming errors
+    STREAM_NOT_FOUND: 'stream_not_found',
+
+    // Stage errors
+    STAGE_ROLE_FULL: 'stage_role_full',
+
+    // Bot API errors
+    BOT_NOT_FOUND: 'bot_not_found',
+
     // Password errors
     INVALID_PASSWORD: 'invalid_password',
     PASSWORD_REQUIRED: 'password_required',
+
+    // Topology errors
+    INVALID_TOPOLOGY: 'invalid_topology',
 } as const;
 
 export type VocaErrorCode = typeof VocaErrorCode[keyof typeof VocaErrorCode];
@@ -38,26 +85,68 @@ export type VocaErrorCode = typeof VocaErrorCode[keyof typeof VocaErrorCode];
 export const VocaErrorMessages: Record<VocaErrorCode, string> = {
     [VocaErrorCode.ROOM_NOT_FOUND]: 'Room not found',
     [VocaErrorCode.ROOM_FULL]: 'Room is at maximum capacity',
+    [VocaErrorCode.ROOM_ID_TAKEN]: 'Room ID is already in use',
+    [VocaErrorCode.ROOM_NOT_OPEN]: 'Room is not open yet',
+    [VocaErrorCode.ROOM_CLOSED]: 'Room has closed',
+    [VocaErrorCode.INVALID_SCHEDULE]: 'Room end time must be in the future and after its start',
     [VocaErrorCode.MAX_ROOMS_REACHED]: 'Maximum number of rooms reached',
     [VocaErrorCode.INVALID_ROOM_ID]: 'Invalid room ID format',
+    [VocaErrorCode.INVALID_ROOM_ID_LENGTH]: 'Room ID must be 4-32 characters',
+    [VocaErrorCode.INVALID_ROOM_ID_CHARS]: 'Room ID may only contain letters, numbers and hyphens',
+    [VocaErrorCode.SLUG_GENERATION_FAILED]: 'Failed to generate unique room ID',
+    [VocaErrorCode.INVALID_API_KEY]: 'Invalid API key',
+    [VocaErrorCode.UNAUTHORIZED]: 'Invalid token',
+    [VocaErrorCode.ORIGIN_NOT_ALLOWED]: 'Origin is not allowed for this app',
+    [VocaErrorCode.BANNED]: 'This address has been banned',
+    [VocaErrorCode.SERVER_DRAINING]: 'Server is shutting down and not accepting new rooms',
+    [VocaErrorCode.SFU_UNAVAILABLE]: 'SFU rooms are not enabled on this server',
+    [VocaErrorCode.RECORDING_UNAVAILABLE]: 'Recording is not enabled on this server',
     [VocaErrorCode.CONNECTION_FAILED]: 'Failed to connect to signaling server',
     [VocaErrorCode.WEBSOCKET_ERROR]: 'WebSocket connection error',
     [VocaErrorCode.HEARTBEAT_TIMEOUT]: 'Connection lost due to heartbeat timeout',
     [VocaErrorCode.MICROPHONE_NOT_FOUND]: 'No microphone found. Please connect a microphone and try again.',
     [VocaErrorCode.MICROPHONE_PERMISSION_DENIED]: 'Microphone permission denied. Please allow microphone access.',
     [VocaErrorCode.INSECURE_CONTEXT]: 'HTTPS is required for microphone access',
-    [VocaErrorCode.INVALID_MESSAGE]: 'Invalid signaling message received',
+    [VocaErrorCode.INVALID_MESSAGE]: 'Invalid signaling message',
+    [VocaErrorCode.HANDSHAKE_REQUIRED]: 'Client must send hello before other messages',
+    [VocaErrorCode.HANDSHAKE_TIMEOUT]: 'No hello received before the handshake timeout',
+    [VocaErrorCode.WAITLIST_TIMEOUT]: 'No slot freed up before the waitlist timeout',
+    [VocaErrorCode.UNSUPPORTED_VERSION]: 'Protocol version not supported by the server',
+    [VocaErrorCode.PAYLOAD_TOO_LARGE]: 'Message payload exceeds the size limit',
+    [VocaErrorCode.MESSAGE_KIND_NOT_ALLOWED]: 'Message kind is not allowed for this app',
     [VocaErrorCode.PEER_NOT_FOUND]: 'Peer not found in room',
+    [VocaErrorCode.CHAT_MESSAGE_NOT_FOUND]: 'Chat message not found',
+    [VocaErrorCode.NOT_PERMITTED]: 'Not permitted in this room',
+    [VocaErrorCode.STALE_KEY_EPOCH]: 'Key package is not for the current key epoch',
+    [VocaErrorCode.STREAM_NOT_FOUND]: 'Stream session not found',
+    [VocaErrorCode.STAGE_ROLE_FULL]: 'No free slot for that stage role',
+    [VocaErrorCode.BOT_NOT_FOUND]: 'Bot not found in this room',
     [VocaErrorCode.INVALID_PASSWORD]: 'Incorrect password',
     [VocaErrorCode.PASSWORD_REQUIRED]: 'This room requires a password',
+    [VocaErrorCode.INVALID_TOPOLOGY]: 'Topology must be mesh or sfu',
 };
 
 /**
- * Voca error with code and message
+ * Codes where retrying the same request later may succeed
+ */
+export const VocaRetryableErrors: ReadonlySet<VocaErrorCode> = new Set<VocaErrorCode>([
+    VocaErrorCode.ROOM_FULL,
+    VocaErrorCode.ROOM_NOT_OPEN,
+    VocaErrorCode.MAX_ROOMS_REACHED,
+    VocaErrorCode.SLUG_GENERATION_FAILED,
+    VocaErrorCode.SERVER_DRAINING,
+    VocaErrorCode.HANDSHAKE_TIMEOUT,
+    VocaErrorCode.WAITLIST_TIMEOUT,
+]);
+
+/**
+ * Voca error with code and message.
+ * Matches the server's error envelope for both REST and WebSocket errors.
  */
 export interface VocaError {
     code: VocaErrorCode;
     message: string;
+    retryable?: boolean;
 }
 
 /**
@@ -67,5 +156,6 @@ export function createVocaError(code: VocaErrorCode, customMessage?: string): Vo
     return {
         code,
         message: customMessage ?? VocaErrorMessages[code],
+        retryable: VocaRetryableErrors.has(code),
     };
 }
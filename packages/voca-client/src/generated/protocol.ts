@@ -0,0 +1,720 @@
+// Generated by `signaling codegen` from services/signaling. Do not edit.
+// Server version 0.4.1
+
+export type AdminAppsResponse = {
+  apps: AppRecord[];
+};
+
+export type AdminBansResponse = {
+  bans: Ban[];
+};
+
+export type AdminLogsResponse = {
+  logs: string[];
+};
+
+export type AdminRoomsResponse = {
+  max_rooms: number;
+  rooms: RoomInfo[];
+  total_rooms: number;
+};
+
+export type AdminUsageResponse = {
+  /**
+   * Oldest first; days without activity are left out
+   */
+  days: DailyUsage[];
+};
+
+export type AdminWebhooksResponse = {
+  /**
+   * Events that ran out of attempts, oldest first
+   */
+  dead_letters: DeadLetter[];
+  /**
+   * Events queued or waiting to be retried
+   */
+  pending: number;
+};
+
+/**
+ * Error envelope used for WebSocket `error` messages, flattened, and for
+ * REST responses as [`RestError`]: `{ "code": "...", "message": "...", "retryable": false }`
+ */
+export type ApiError = {
+  code: ErrorCode;
+  message: string;
+  retryable: boolean;
+};
+
+/**
+ * An application namespace that has created rooms
+ */
+export type AppRecord = {
+  app_id: string;
+  /**
+   * Unix milliseconds of its first room
+   */
+  first_seen: number;
+  /**
+   * Unix milliseconds of its latest room
+   */
+  last_seen: number;
+  rooms_created: number;
+};
+
+/**
+ * An address the admin has shut out
+ */
+export type Ban = {
+  /**
+   * Unix milliseconds
+   */
+  created_at: number;
+  ip: string;
+  reason?: string | null;
+};
+
+/**
+ * Body of `POST /api/admin/bans`
+ */
+export type BanRequest = {
+  ip: string;
+  reason?: string | null;
+};
+
+/**
+ * Reply to a long-poll of `GET /api/room/{room}/bots/{id}/events`
+ */
+export type BotEventsResponse = {
+  /**
+   * Oldest first; empty if nothing happened while waiting
+   */
+  events: SignalMessage[];
+  /**
+   * Events dropped because the bot fell behind
+   */
+  missed: number;
+};
+
+/**
+ * Reply to `POST /api/room/{room}/bots`
+ */
+export type BotJoinResponse = {
+  /**
+   * Recent chat history, oldest first
+   */
+  chat: ChatMessage[];
+  /**
+   * Unix milliseconds when the room closes, if it has an end
+   */
+  closes_at?: number | null;
+  host?: string | null;
+  /**
+   * The bot's peer id, used in the rest of the bot API
+   */
+  peer_id: string;
+  /**
+   * Everyone already in the room, by peer id
+   */
+  peers: Record<string, unknown>;
+};
+
+/**
+ * Optional protocol features agreed during the handshake
+ */
+export type Capability =
+  | "binary"
+  | "roster"
+  | "unknown";
+
+/**
+ * One chat message as stored in room history and delivered in Welcome
+ */
+export type ChatMessage = {
+  edited?: boolean;
+  /**
+   * Author peer_id
+   */
+  from: string;
+  /**
+   * Server-assigned id, used to edit or delete the message
+   */
+  id: string;
+  text: string;
+  /**
+   * Server time the message was posted, in Unix milliseconds
+   */
+  ts: number;
+};
+
+export type CheckRoomResponse = {
+  capacity: number;
+  /**
+   * Unix milliseconds when the room closes, if it has an end
+   */
+  closes_at?: number | null;
+  exists: boolean;
+  full: boolean;
+  max_listeners?: number | null;
+  /**
+   * Speakers and listeners allowed in a stage room
+   */
+  max_speakers?: number | null;
+  /**
+   * False until a scheduled room's start time
+   */
+  open: boolean;
+  /**
+   * Unix milliseconds when a room that isn't open yet opens
+   */
+  opens_at?: number | null;
+  password_required: boolean;
+  peers: number;
+  /**
+   * Kept while empty until deleted or idle for the reserved-room TTL
+   */
+  reserved: boolean;
+  topology: Topology;
+};
+
+export type CreateRoomResponse = {
+  /**
+   * Unix milliseconds when the room closes, if it has an end
+   */
+  closes_at?: number | null;
+  /**
+   * Reserved rooms only: connect with it to take the host role, or pass it
+   * to delete the room
+   */
+  host_key?: string | null;
+  /**
+   * Unix milliseconds before which joins are refused, for scheduled rooms
+   */
+  opens_at?: number | null;
+  password?: string | null;
+  room: string;
+  topology: Topology;
+};
+
+/**
+ * Usage for one UTC day
+ */
+export type DailyUsage = {
+  connections: number;
+  /**
+   * `YYYY-MM-DD`
+   */
+  day: string;
+  origin_rejections: number;
+  rooms_created: number;
+};
+
+/**
+ * An event that couldn't be delivered
+ */
+export type DeadLetter = {
+  attempts: number;
+  /**
+   * Why the last attempt failed
+   */
+  error: string;
+  event: WebhookEvent;
+  /**
+   * Unix milliseconds
+   */
+  failed_at: number;
+  url: string;
+};
+
+/**
+ * Stable error codes shared by the REST API, the WebSocket protocol and the SDKs.
+ *
+ * The serialized names are part of the public protocol: never rename a
+ * variant, only add new ones.
+ */
+export type ErrorCode =
+  | "room_not_found"
+  | "room_full"
+  | "room_id_taken"
+  | "room_not_open"
+  | "room_closed"
+  | "invalid_schedule"
+  | "max_rooms_reached"
+  | "invalid_room_id_length"
+  | "invalid_room_id_chars"
+  | "slug_generation_failed"
+  | "invalid_api_key"
+  | "unauthorized"
+  | "origin_not_allowed"
+  | "banned"
+  | "invalid_password"
+  | "password_required"
+  | "invalid_topology"
+  | "rate_limited"
+  | "server_draining"
+  | "sfu_unavailable"
+  | "recording_unavailable"
+  | "handshake_required"
+  | "handshake_timeout"
+  | "waitlist_timeout"
+  | "unsupported_version"
+  | "invalid_message"
+  | "payload_too_large"
+  | "message_kind_not_allowed"
+  | "chat_message_not_found"
+  | "not_permitted"
+  | "stale_key_epoch"
+  | "stream_not_found"
+  | "stage_role_full"
+  | "bot_not_found"
+  | "unknown";
+
+/**
+ * Raised hands and the current floor holder
+ */
+export type Floor = {
+  /**
+   * Peer the host gave the floor to
+   */
+  holder?: string | null;
+  /**
+   * Peers with a raised hand, earliest first
+   */
+  queue?: string[];
+  /**
+   * Only the holder may be unmuted
+   */
+  single_speaker?: boolean;
+};
+
+export type HealthResponse = {
+  status: string;
+  uptime_seconds: number;
+  version: string;
+};
+
+export type MetricsResponse = {
+  active_connections: number;
+  active_rooms: number;
+  connections_today: number;
+  origin_rejections: number;
+  rooms_created_today: number;
+  uptime_seconds: number;
+};
+
+/**
+ * A peer's perfect-negotiation role toward one other peer.
+ *
+ * Roles follow join order: the peer that was in the room first is impolite,
+ * sends the initial offer and wins offer collisions; the newer peer is
+ * polite, waits for that offer and rolls back its own on glare.
+ */
+export type NegotiationRole = "polite" | "impolite";
+
+/**
+ * What the room knows about one connected peer
+ */
+export type PeerState = {
+  avatar?: string | null;
+  /**
+   * Backend service taking part over the bot API; it has no audio
+   */
+  bot?: boolean;
+  /**
+   * Media source streaming in over WHIP; it never receives room audio
+   */
+  ingest?: boolean;
+  /**
+   * Server time the peer joined, in Unix milliseconds
+   */
+  joined_at?: number;
+  /**
+   * Small application-defined JSON object
+   */
+  metadata?: Record<string, unknown>;
+  muted?: boolean;
+  name?: string | null;
+  /**
+   * Public key other peers use to encrypt key packages for this peer
+   */
+  public_key?: string | null;
+  /**
+   * Agreed to be captured by the room's current recording
+   */
+  recording_consent?: boolean;
+  /**
+   * Listener waiting for the host to let them speak
+   */
+  speak_requested?: boolean;
+  /**
+   * Speaker or listener, in stage rooms only
+   */
+  stage_role?: 
+    | StageRole
+    | null;
+};
+
+/**
+ * Partial update to a peer's state. Omitted fields are left unchanged; an
+ * empty `name` or `avatar` clears it, and `metadata` keys are merged with
+ * `null` values removing a key.
+ */
+export type PeerUpdate = {
+  avatar?: string | null;
+  metadata?: Record<string, unknown> | null;
+  muted?: boolean | null;
+  name?: string | null;
+  public_key?: string | null;
+};
+
+export type ReadinessResponse = {
+  accepting_rooms: boolean;
+  active_rooms: number;
+  draining: boolean;
+  max_rooms: number;
+  status: string;
+  /**
+   * Whether the storage backend (the SQLite database, or memory when none
+   * is configured) answered a ping. This is the only dependency checked.
+   */
+  storage_ok: boolean;
+  uptime_seconds: number;
+  version: string;
+};
+
+/**
+ * REST form of [`ApiError`]. `error` repeats `code` under the name REST
+ * responses used before the envelope had `code`; it will be removed in the
+ * next release.
+ */
+export type RestError = {
+  code: ErrorCode;
+  /**
+   * Deprecated alias of `code`
+   */
+  error: ErrorCode;
+  message: string;
+  retryable: boolean;
+};
+
+/**
+ * Why a room ended
+ */
+export type RoomEndReason =
+  | "empty"
+  | "closed"
+  | "deleted"
+  | "idle";
+
+export type RoomInfo = {
+  app_id: string;
+  capacity: number;
+  id: string;
+  peers: number;
+  reserved: boolean;
+  topology: Topology;
+};
+
+export type SignalMessage = {
+  /**
+   * Sender peer_id or "server"; overwritten by the server on relay
+   */
+  from?: string;
+} & (
+  | {
+      capabilities?: Capability[];
+      client: string;
+      protocol?: number;
+      /**
+       * Initial display name, avatar, mute and metadata
+       */
+      state?: 
+        | PeerUpdate
+        | null;
+      type: "hello";
+      version: string;
+    }
+  | {
+      /**
+       * 1-based place in the queue
+       */
+      position: number;
+      type: "waiting";
+    }
+  | {
+      capabilities: Capability[];
+      /**
+       * Recent chat history, oldest first
+       */
+      chat?: ChatMessage[];
+      /**
+       * Unix milliseconds when the room closes, if it has an end
+       */
+      closes_at?: number | null;
+      /**
+       * Raised hands and floor holder, omitted when nobody uses the floor
+       */
+      floor?: Floor;
+      /**
+       * Current room host, who may moderate chat
+       */
+      host?: string | null;
+      /**
+       * Current end-to-end encryption key epoch
+       */
+      key_epoch?: number;
+      peer_id: string;
+      /**
+       * Peers already in the room, present when `roster` was negotiated
+       */
+      peers?: string[] | null;
+      protocol: number;
+      /**
+       * Id of the recording in progress; answer with `RecordingConsent`
+       */
+      recording?: string | null;
+      /**
+       * Your negotiation role toward each peer in `peers`
+       */
+      roles?: Record<string, unknown> | null;
+      /**
+       * Your role in a stage room; listeners don't send audio
+       */
+      stage_role?: 
+        | StageRole
+        | null;
+      /**
+       * State of each peer in `peers`, present when `roster` was negotiated
+       */
+      states?: Record<string, unknown> | null;
+      /**
+       * With `sfu`, negotiate only with the `sfu` peer instead of each peer
+       */
+      topology?: Topology;
+      type: "welcome";
+      version: string;
+    }
+  | {
+      peer_id: string;
+      /**
+       * The recipient's negotiation role toward the joining peer
+       */
+      role?: 
+        | NegotiationRole
+        | null;
+      /**
+       * The joining peer's state as set in Hello
+       */
+      state?: 
+        | PeerState
+        | null;
+      type: "join";
+    }
+  | {
+      peer_id: string;
+      type: "leave";
+    }
+  | {
+      sdp: string;
+      to: string;
+      type: "offer";
+    }
+  | {
+      sdp: string;
+      to: string;
+      type: "answer";
+    }
+  | {
+      candidate: string;
+      to: string;
+      type: "ice";
+    }
+  | {
+      muted: boolean;
+      type: "mute";
+    }
+  | {
+      changes: PeerUpdate;
+      peer_id?: string;
+      type: "peer_update";
+    }
+  | {
+      peer_id: string;
+      type: "host";
+    }
+  | {
+      id?: string;
+      text: string;
+      ts?: number;
+      type: "chat";
+    }
+  | {
+      id: string;
+      text: string;
+      type: "chat_edit";
+    }
+  | {
+      id: string;
+      type: "chat_delete";
+    }
+  | {
+      ciphertext: string;
+      epoch: number;
+      to: string;
+      type: "key_package";
+    }
+  | {
+      distributor?: string | null;
+      epoch: number;
+      type: "key_epoch";
+    }
+  | {
+      type: "rekey";
+    }
+  | {
+      peers: string[];
+      topology: Topology;
+      type: "topology_change";
+    }
+  | {
+      type: "recording_start";
+    }
+  | {
+      type: "recording_stop";
+    }
+  | {
+      by: string;
+      id: string;
+      type: "recording_started";
+    }
+  | {
+      id: string;
+      type: "recording_stopped";
+    }
+  | {
+      consent: boolean;
+      peer_id?: string;
+      type: "recording_consent";
+    }
+  | {
+      peer_id?: string;
+      type: "speak_request";
+    }
+  | {
+      peer_id: string;
+      stage_role: StageRole;
+      type: "set_role";
+    }
+  | {
+      type: "raise_hand";
+    }
+  | {
+      peer_id?: string | null;
+      type: "lower_hand";
+    }
+  | {
+      peer_id?: string | null;
+      type: "grant_floor";
+    }
+  | {
+      enabled: boolean;
+      type: "single_speaker";
+    }
+  | Floor & {
+      type: "floor";
+    }
+  | {
+      peer_id: string;
+      type: "force_mute";
+    }
+  | {
+      /**
+       * Unix milliseconds
+       */
+      closes_at: number;
+      seconds_left: number;
+      type: "closing";
+    }
+  | {
+      data: unknown;
+      kind: string;
+      to?: string | null;
+      type: "app";
+    }
+  | {
+      text: string;
+      type: "announcement";
+    }
+  | {
+      type: "ping";
+    }
+  | {
+      type: "pong";
+    }
+  | ApiError & {
+      type: "error";
+    }
+);
+
+/**
+ * What a peer may do in a stage room
+ */
+export type StageRole =
+  | "speaker"
+  | "listener";
+
+/**
+ * How media flows in a room
+ */
+export type Topology =
+  | "mesh"
+  | "sfu"
+  | "fanout";
+
+/**
+ * Body of every webhook request
+ */
+export type WebhookEvent = {
+  app_id: string;
+  /**
+   * Unique per event and kept across retries, for deduplication
+   */
+  id: string;
+  /**
+   * The peer that joined, left or was turned away
+   */
+  peer_id?: string | null;
+  /**
+   * Set on `room.ended`
+   */
+  reason?: 
+    | RoomEndReason
+    | null;
+  room_id: string;
+  /**
+   * Unix milliseconds when it happened
+   */
+  timestamp: number;
+  type: WebhookEventType;
+};
+
+/**
+ * What happened
+ */
+export type WebhookEventType =
+  | "room.created"
+  | "room.ended"
+  | "peer.joined"
+  | "peer.left"
+  | "room.full";
+
+/**
+ * Reply to `POST /api/admin/webhooks/retry`
+ */
+export type WebhookRetryResponse = {
+  /**
+   * Dead letters queued for delivery again
+   */
+  queued: number;
+};
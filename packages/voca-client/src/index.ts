@@ -1,8 +1,10 @@
 import { createNanoEvents } from 'nanoevents';
-import { VocaErrorCode, VocaErrorMessages, type VocaError, createVocaError } from './errors';
-export { VocaErrorCode, VocaErrorMessages, type VocaError, createVocaError } from './errors';
+import { VocaErrorCode, VocaErrorMessages, VocaRetryableErrors, type VocaError, createVocaError } from './errors';
+export { VocaErrorCode, VocaErrorMessages, VocaRetryableErrors, type VocaError, createVocaError } from './errors';
+import type { ChatMessage, Floor, NegotiationRole, PeerState, PeerUpdate, SignalMessage as WireMessage, StageRole, Topology } from './generated/protocol';
+export type * as Protocol from './generated/protocol';
 
-export type ConnectionStatus = 'connecting' | 'connected' | 'reconnecting' | 'full' | 'error' | 'disconnected';
+export type ConnectionStatus = 'connecting' | 'connected' | 'waiting' | 'reconnecting' | 'full' | 'error' | 'disconnected';
 
 export interface VocaConfig {
     debug?: boolean;
@@ -10,6 +12,18 @@ export interface VocaConfig {
     serverUrl?: string; // e.g. "ws://localhost:3001" or "wss://voca.vc"
     apiKey?: string; // optional API key for signaling server auth
     password?: string; // optional room password for protected rooms
+    displayName?: string; // shown to other peers instead of the peer ID
+    avatar?: string; // http(s) URL of an avatar image
+    metadata?: Record<string, unknown>; // small app-defined state shared with the room
+    publicKey?: string; // published so peers can encrypt E2EE key packages for you
+    topology?: Topology; // 'sfu' for rooms beyond the mesh limit; used by createRoom
+    stage?: boolean; // speakers and listeners instead of everyone speaking; used by createRoom
+    waitlist?: boolean; // queue for a slot when the room is full instead of failing with 'full'
+    room?: string; // vanity room ID instead of a random one; used by createRoom
+    persistent?: boolean; // reserve the room so it outlives its peers; used by createRoom
+    hostKey?: string; // host key of a reserved room; join as its host
+    startsAt?: Date | number; // joins before this fail with 'room_not_open'; used by createRoom
+    endsAt?: Date | number; // when the room closes, after 'closing' warnings; used by createRoom
     /**
      * Reconnection options. Enabled by default.
      */
@@ -30,23 +44,89 @@ export interface Peer {
     stream?: MediaStream;
     remoteMuted?: boolean;
     localMuted?: boolean;
+    /** Our negotiation role toward this peer, assigned by the server */
+    role: NegotiationRole;
 }
 
+// Loose view of the wire format; message types come from the generated protocol
 type SignalMessage = {
     from: string;
-    type: 'hello' | 'welcome' | 'join' | 'leave' | 'offer' | 'answer' | 'ice' | 'ping' | 'pong' | 'error' | 'mute';
+    type: WireMessage['type'];
     peer_id?: string;
     to?: string;
     sdp?: string;
     candidate?: string;
     code?: string;
     message?: string;
+    retryable?: boolean;
     muted?: boolean;
+    // Application messages
+    kind?: string;
+    data?: unknown;
     // Protocol versioning
     version?: string;
     client?: string;
+    protocol?: number;
+    capabilities?: string[];
+    peers?: string[];
+    // Chat
+    id?: string;
+    text?: string;
+    ts?: number;
+    host?: string;
+    chat?: ChatMessage[];
+    // Peer state
+    state?: PeerState;
+    states?: Record<string, PeerState>;
+    changes?: PeerUpdate;
+    // Negotiation roles
+    role?: NegotiationRole;
+    roles?: Record<string, NegotiationRole>;
+    // End-to-end encryption
+    epoch?: number;
+    key_epoch?: number;
+    distributor?: string;
+    ciphertext?: string;
+    // Room topology
+    topology?: Topology;
+    // Recording
+    recording?: string | null;
+    by?: string;
+    consent?: boolean;
+    // Stage rooms
+    stage_role?: StageRole;
+    // Waitlist
+    position?: number;
+    // Floor control
+    floor?: Floor;
+    queue?: string[];
+    holder?: string | null;
+    single_speaker?: boolean;
+    enabled?: boolean;
+    // Scheduled rooms
+    closes_at?: number | null;
+    seconds_left?: number;
 };
 
+/** Peer id the server's forwarder uses in SFU rooms */
+const SFU_PEER_ID = 'sfu';
+
+/** Signaling protocol version spoken by this SDK */
+const PROTOCOL_VERSION = 2;
+
+/** Server errors about one rejected message; the connection stays open */
+const NON_FATAL_ERRORS: ReadonlySet<string> = new Set([
+    VocaErrorCode.INVALID_MESSAGE,
+    VocaErrorCode.PAYLOAD_TOO_LARGE,
+    VocaErrorCode.MESSAGE_KIND_NOT_ALLOWED,
+    VocaErrorCode.CHAT_MESSAGE_NOT_FOUND,
+    VocaErrorCode.NOT_PERMITTED,
+    VocaErrorCode.STALE_KEY_EPOCH,
+    VocaErrorCode.RECORDING_UNAVAILABLE,
+    VocaErrorCode.INVALID_TOPOLOGY,
+    VocaErrorCode.STAGE_ROLE_FULL,
+]);
+
 interface VocaEvents {
     'status': (status: ConnectionStatus) => void;
     'error': (error: VocaError) => void;
@@ -58,6 +138,25 @@ interface VocaEvents {
     'track': (peerId: string, track: MediaStreamTrack, stream: MediaStream) => void;
     'peer-mute': (peerId: string, isMuted: boolean) => void;
     'peer-local-mute': (peerId: string, isMuted: boolean) => void;
+    'app-message': (peerId: string, kind: string, data: unknown) => void;
+    'chat-history': (messages: ChatMessage[]) => void;
+    'chat': (message: ChatMessage) => void;
+    'chat-edit': (id: string, text: string) => void;
+    'chat-delete': (id: string) => void;
+    'host': (peerId: string) => void;
+    'peer-state': (peerId: string, state: PeerState) => void;
+    'key-epoch': (epoch: number, distributor: string | null) => void;
+    'key-package': (peerId: string, epoch: number, ciphertext: string) => void;
+    'recording-started': (id: string, by: string | null) => void;
+    'recording-stopped': (id: string) => void;
+    'recording-consent': (peerId: string, consent: boolean) => void;
+    'stage-role': (peerId: string, role: StageRole) => void;
+    'speak-request': (peerId: string) => void;
+    'floor': (floor: Floor) => void;
+    'force-mute': (peerId: string) => void;
+    'waiting': (position: number) => void;
+    'closing': (secondsLeft: number, closesAt: number) => void;
+    'announcement': (peerId: string, text: string) => void;
 }
 
 /**
@@ -89,6 +188,28 @@ export class VocaClient {
     public isMuted: boolean = false;
     public status: ConnectionStatus = 'connecting';
     public roomId: string;
+    /** Our server-assigned peer ID, known once connected */
+    public peerId: string | null = null;
+    /** Peer that may moderate the room, known once connected */
+    public hostId: string | null = null;
+    /** Name, avatar, mute and metadata of every other peer, kept by the server */
+    public peerStates: Map<string, PeerState> = new Map();
+    /** Current E2EE key epoch; key packages must be for this epoch */
+    public keyEpoch = 0;
+    /** 'mesh' connects to every peer directly; 'sfu' uses one connection to the server */
+    public topology: Topology = 'mesh';
+    /** Id of the room's running recording, or null when nothing is recorded */
+    public recordingId: string | null = null;
+    /** Our role in a stage room; null in rooms where everyone speaks */
+    public stageRole: StageRole | null = null;
+    /** Raised hands, the floor holder and whether only the holder may talk */
+    public floor: Floor = { queue: [], single_speaker: false };
+    /** Our place in a full room's waitlist, or null when not waiting */
+    public waitPosition: number | null = null;
+    /** Key that makes us host of a reserved room; keep it to rejoin as host or delete the room */
+    public hostKey: string | null;
+    /** Unix milliseconds when the room closes, or null if it has no end */
+    public closesAt: number | null = null;
 
     private events = createNanoEvents<VocaEvents>();
     private ws: WebSocket | null = null;
@@ -103,6 +224,12 @@ export class VocaClient {
     private reconnectTimeout: ReturnType<typeof setTimeout> | null = null;
     private shouldReconnect = true;
 
+    // Server-assigned negotiation roles, learned before the peer connection exists
+    private roles: Map<string, NegotiationRole> = new Map();
+
+    // Single upstream connection shared by every peer in SFU rooms
+    private sfuConnection: RTCPeerConnection | null = null;
+
     // Audio analysis nodes per peer (for cleanup)
     private peerAnalysers: Map<string, { source: MediaStreamAudioSourceNode; analyser: AnalyserNode; gainNode: GainNode }> = new Map();
 
@@ -134,6 +261,24 @@ export class VocaClient {
         if (config.password) {
             params.append('password', config.password);
         }
+        if (config.topology) {
+            params.append('topology', config.topology);
+        }
+        if (config.stage) {
+            params.append('stage', 'true');
+        }
+        if (config.room) {
+            params.append('room', config.room);
+        }
+        if (config.persistent) {
+            params.append('persistent', 'true');
+        }
+        if (config.startsAt !== undefined) {
+            params.append('starts_at', String(new Date(config.startsAt).getTime()));
+        }
+        if (config.endsAt !== undefined) {
+            params.append('ends_at', String(new Date(config.endsAt).getTime()));
+        }
         if (params.toString()) {
             url += `?${params.toString()}`;
         }
@@ -144,16 +289,48 @@ export class VocaClient {
         });
 
         if (!response.ok) {
-            const error = await response.json().catch(() => ({ error: 'unknown', message: 'Failed to create room' }));
+            const error = await response.json().catch(() => ({ code: 'unknown', message: 'Failed to create room' }));
             throw new Error(error.message || 'Failed to create room');
         }
 
-        const { room, password } = await response.json();
+        const { room, password, host_key } = await response.json();
         // Use the password from response (in case server modified it) or from config
-        const roomConfig = { ...config, password: password || config.password };
+        const roomConfig = { ...config, password: password || config.password, hostKey: host_key ?? config.hostKey };
         return new VocaClient(room, roomConfig);
     }
 
+    /**
+     * Delete a reserved room. It closes now if empty, otherwise once its
+     * last peer leaves.
+     *
+     * @param roomId - The reserved room's ID
+     * @param config - Needs the `hostKey` returned when the room was reserved
+     */
+    static async deleteRoom(roomId: string, config: VocaConfig = {}): Promise<void> {
+        const httpUrl = VocaClient.getHttpUrl(config.serverUrl);
+
+        if (!httpUrl) {
+            throw new Error('VocaConfig.serverUrl is required in non-browser environments');
+        }
+
+        const headers: HeadersInit = {
+            'x-host-key': config.hostKey ?? '',
+        };
+        if (config.apiKey) {
+            headers['x-api-key'] = config.apiKey;
+        }
+
+        const response = await fetch(`${httpUrl}/api/room/${encodeURIComponent(roomId)}`, {
+            method: 'DELETE',
+            headers,
+        });
+
+        if (!response.ok) {
+            const error = await response.json().catch(() => ({ code: 'unknown', message: 'Failed to delete room' }));
+            throw new Error(error.message || 'Failed to delete room');
+        }
+    }
+
     /**
      * Derive an HTTP/HTTPS URL from any input format.
      * Accepts: https://, http://, wss://, ws://
@@ -194,6 +371,7 @@ export class VocaClient {
     constructor(roomId: string, config: VocaConfig = {}) {
         this.roomId = roomId;
         this.config = config;
+        this.hostKey = config.hostKey ?? null;
         if (config.iceServers) this.iceServers = config.iceServers;
     }
 
@@ -226,6 +404,10 @@ export class VocaClient {
         if (this.animationFrame) cancelAnimationFrame(this.animationFrame);
         this.peers.forEach((p) => p.connection.close());
         this.peers.clear();
+        this.peerStates.clear();
+        this.roles.clear();
+        this.sfuConnection?.close();
+        this.sfuConnection = null;
         this.ws?.close();
         this.localStream?.getTracks().forEach((t) => t.stop());
         if (this.audioContext && this.audioContext.state !== 'closed') {
@@ -237,15 +419,124 @@ export class VocaClient {
 
     public toggleMute() {
         const track = this.localStream?.getAudioTracks()[0];
-        if (track) {
-            track.enabled = !track.enabled;
-            this.isMuted = !track.enabled;
+        // The server refuses to unmute anyone but the holder in single-speaker mode
+        if (track && !(this.isMuted && !this.mayUnmute())) {
+            this.isMuted = !this.isMuted;
+            this.updateLocalTrack();
             // Broadcast our mute state to everyone else
             this.send({ type: 'mute', muted: this.isMuted });
         }
         return this.isMuted;
     }
 
+    /**
+     * Send a custom application event through the room.
+     * The server relays it without interpreting `data`.
+     *
+     * @param kind - Event name, e.g. 'reaction' or 'raise-hand'
+     * @param data - Any JSON-serializable payload
+     * @param to - Optional peer ID to target; broadcasts to the room when omitted
+     */
+    public sendAppMessage(kind: string, data: unknown, to?: string) {
+        this.send({ type: 'app', kind, data, ...(to ? { to } : {}) });
+    }
+
+    /**
+     * Post a chat message to the room. The server assigns its id and
+     * timestamp and echoes it back through the 'chat' event.
+     */
+    public sendChat(text: string) {
+        this.send({ type: 'chat', text });
+    }
+
+    /** Replace the text of one of your own chat messages */
+    public editChat(id: string, text: string) {
+        this.send({ type: 'chat_edit', id, text });
+    }
+
+    /** Delete one of your own chat messages, or any message as the host */
+    public deleteChat(id: string) {
+        this.send({ type: 'chat_delete', id });
+    }
+
+    /**
+     * Change your display name, avatar, mute flag or metadata. Only the given
+     * fields change; an empty string clears name or avatar and a `null`
+     * metadata value removes that key.
+     */
+    public updateState(changes: PeerUpdate) {
+        this.send({ type: 'peer_update', changes });
+    }
+
+    /**
+     * Relay the room key to one peer for end-to-end encryption. Encrypt it
+     * with the recipient's `public_key` first; the server only sees ciphertext.
+     */
+    public sendKeyPackage(to: string, ciphertext: string, epoch: number = this.keyEpoch) {
+        this.send({ type: 'key_package', to, epoch, ciphertext });
+    }
+
+    /** Start a new key epoch (host only); answered by a 'key-epoch' event */
+    public requestRekey() {
+        this.send({ type: 'rekey' });
+    }
+
+    /**
+     * Start recording the room (host only). Every peer gets a
+     * 'recording-started' event, and nobody is captured until they call
+     * `setRecordingConsent(true)`.
+     */
+    public startRecording() {
+        this.send({ type: 'recording_start' });
+    }
+
+    /** Stop the running recording (host only) */
+    public stopRecording() {
+        this.send({ type: 'recording_stop' });
+    }
+
+    /** Agree to, or stop, being captured by the running recording */
+    public setRecordingConsent(consent: boolean) {
+        this.send({ type: 'recording_consent', consent });
+    }
+
+    /** Ask the host to let you speak (stage listeners only) */
+    public requestToSpeak() {
+        this.send({ type: 'speak_request' });
+    }
+
+    /**
+     * Move a peer between speaker and listener (host only). Anyone may step
+     * down with their own peer ID; setting a listener's current role turns
+     * down their request to speak.
+     */
+    public setStageRole(peerId: string, role: StageRole) {
+        this.send({ type: 'set_role', peer_id: peerId, stage_role: role });
+    }
+
+    /** Join the queue of peers waiting for the floor */
+    public raiseHand() {
+        this.send({ type: 'raise_hand' });
+    }
+
+    /**
+     * Take a hand down, giving up the floor if it's held. Lowering someone
+     * else's hand is host only.
+     */
+    public lowerHand(peerId?: string) {
+        this.send({ type: 'lower_hand', peer_id: peerId });
+    }
+
+    /** Give the floor to a peer, or take it back with null (host only) */
+    public grantFloor(peerId: string | null) {
+        this.send({ type: 'grant_floor', peer_id: peerId });
+    }
+
+    /** Turn push-to-talk on or off: only the floor holder may unmute (host only) */
+    public setSingleSpeaker(enabled: boolean) {
+        this.send({ type: 'single_speaker', enabled });
+    }
+
     public togglePeerMute(peerId: string) {
         const peer = this.peers.get(peerId);
         if (!peer) return false;
@@ -342,6 +633,14 @@ export class VocaClient {
             params.append('password', this.config.password);
         }
 
+        if (this.config.waitlist) {
+            params.append('wait', 'true');
+        }
+
+        if (this.config.hostKey) {
+            params.append('hostKey', this.config.hostKey);
+        }
+
         if (params.toString()) {
             fullUrl += `?${params.toString()}`;
         }
@@ -356,7 +655,20 @@ export class VocaClient {
 
         this.ws.onopen = () => {
             // Send hello message with version info
-            this.send({ type: 'hello', version: '0.4.1', client: '@treyorr/voca-client' });
+            this.send({
+                type: 'hello',
+                version: '0.4.1',
+                client: '@treyorr/voca-client',
+                protocol: PROTOCOL_VERSION,
+                capabilities: ['roster'],
+                state: {
+                    name: this.config.displayName,
+                    avatar: this.config.avatar,
+                    metadata: this.config.metadata,
+                    public_key: this.config.publicKey,
+                    muted: this.isMuted,
+                },
+            });
             this.status = 'connected';
             this.events.emit('status', 'connected');
             // Reset reconnect attempts on successful connection
@@ -400,19 +712,94 @@ export class VocaClient {
 
     private async handleSignal(msg: SignalMessage) {
         switch (msg.type) {
+            case 'waiting':
+                this.waitPosition = msg.position ?? null;
+                if (this.status !== 'waiting') {
+                    this.status = 'waiting';
+                    this.events.emit('status', 'waiting');
+                }
+                this.events.emit('waiting', msg.position ?? 0);
+                break;
             case 'welcome':
                 // Protocol handshake complete - peer_id is managed server-side
-                console.debug('[Voca] Protocol version:', msg.version, 'Peer ID:', msg.peer_id);
+                console.debug('[Voca] Server version:', msg.version, 'Protocol:', msg.protocol, 'Peer ID:', msg.peer_id, 'Capabilities:', msg.capabilities);
+                this.waitPosition = null;
+                if (this.status === 'waiting') {
+                    // Admitted from the waitlist
+                    this.status = 'connected';
+                    this.events.emit('status', 'connected');
+                }
+                this.peerId = msg.peer_id ?? null;
+                this.hostId = msg.host ?? null;
+                this.keyEpoch = msg.key_epoch ?? 0;
+                this.topology = msg.topology ?? 'mesh';
+                this.stageRole = msg.stage_role ?? null;
+                this.closesAt = msg.closes_at ?? null;
+                this.updateLocalTrack();
+                for (const [peerId, role] of Object.entries(msg.roles ?? {})) {
+                    this.roles.set(peerId, role);
+                }
+                for (const [peerId, state] of Object.entries(msg.states ?? {})) {
+                    this.setPeerState(peerId, state);
+                }
+                if (msg.chat?.length) {
+                    this.events.emit('chat-history', msg.chat);
+                }
+                this.recordingId = msg.recording ?? null;
+                if (this.recordingId) {
+                    this.events.emit('recording-started', this.recordingId, null);
+                }
+                this.floor = msg.floor ?? { queue: [], single_speaker: false };
+                if (!this.mayUnmute()) {
+                    // The server joined us muted
+                    this.isMuted = true;
+                    this.updateLocalTrack();
+                }
+                if (this.floor.queue?.length || this.floor.holder || this.floor.single_speaker) {
+                    this.events.emit('floor', this.floor);
+                }
+                // In SFU rooms nobody offers to us directly, so list existing peers now
+                if (this.topology === 'sfu') {
+                    msg.peers?.filter((peerId) => !msg.states?.[peerId]?.bot).forEach((peerId) => this.addSfuPeer(peerId));
+                }
                 break;
             case 'join':
-                await this.createPeer(msg.from, true);
+                if (msg.state) this.setPeerState(msg.from, msg.state);
+                // Bots are in the roster but have no audio to connect to
+                if (msg.state?.bot) break;
+                if (this.topology === 'sfu') {
+                    this.addSfuPeer(msg.from);
+                    if (this.isMuted) this.send({ type: 'mute', to: msg.from, muted: true });
+                    break;
+                }
+                // Servers without roles expect existing peers to make the offer
+                this.roles.set(msg.from, msg.role ?? 'impolite');
+                // Fan-out stage listeners don't connect to each other
+                if (!this.isLinked(msg.from)) break;
+                await this.createPeer(msg.from, this.roles.get(msg.from) === 'impolite');
                 if (this.isMuted) {
                     // Send our mute state specifically to the joined peer
                     this.send({ type: 'mute', to: msg.from, muted: true });
                 }
                 break;
             case 'offer':
-                await this.createPeer(msg.from, false, msg.sdp);
+                if (msg.from === SFU_PEER_ID) {
+                    await this.answerSfu(msg.sdp!);
+                    break;
+                }
+                const existing = this.peers.get(msg.from);
+                if (!existing) {
+                    await this.createPeer(msg.from, false, msg.sdp);
+                    break;
+                }
+                // Perfect negotiation: on glare the impolite side keeps its own
+                // offer and the polite side rolls back and answers
+                const collision = existing.connection.signalingState !== 'stable';
+                if (collision && existing.role === 'impolite') break;
+                await existing.connection.setRemoteDescription({ type: 'offer', sdp: msg.sdp! });
+                const answer = await existing.connection.createAnswer();
+                await existing.connection.setLocalDescription(answer);
+                this.send({ type: 'answer', to: msg.from, sdp: answer.sdp });
                 break;
             case 'answer':
                 const peer = this.peers.get(msg.from);
@@ -421,10 +808,10 @@ export class VocaClient {
                 }
                 break;
             case 'ice':
-                const p = this.peers.get(msg.from);
+                const p = msg.from === SFU_PEER_ID ? this.sfuConnection : this.peers.get(msg.from)?.connection;
                 if (p) {
                     try {
-                        await p.connection.addIceCandidate(JSON.parse(msg.candidate!));
+                        await p.addIceCandidate(JSON.parse(msg.candidate!));
                     } catch (e) {
                         console.warn('[Voca] Invalid ICE candidate:', e);
                     }
@@ -442,9 +829,108 @@ export class VocaClient {
                     mutePeer.remoteMuted = msg.muted ?? false;
                     this.events.emit('peer-mute', msg.from, mutePeer.remoteMuted);
                 }
+                const muteState = this.peerStates.get(msg.from);
+                if (muteState) this.setPeerState(msg.from, { ...muteState, muted: msg.muted ?? false });
+                break;
+            case 'peer_update':
+                this.applyPeerUpdate(msg.from, msg.changes ?? {});
+                break;
+            case 'app':
+                this.events.emit('app-message', msg.from, msg.kind ?? '', msg.data);
+                break;
+            case 'chat':
+                this.events.emit('chat', { id: msg.id!, from: msg.from, text: msg.text ?? '', ts: msg.ts ?? 0, edited: false });
+                break;
+            case 'chat_edit':
+                this.events.emit('chat-edit', msg.id!, msg.text ?? '');
+                break;
+            case 'chat_delete':
+                this.events.emit('chat-delete', msg.id!);
+                break;
+            case 'host':
+                this.hostId = msg.peer_id ?? null;
+                this.events.emit('host', msg.peer_id!);
+                break;
+            case 'key_epoch':
+                this.keyEpoch = msg.epoch ?? this.keyEpoch;
+                this.events.emit('key-epoch', this.keyEpoch, msg.distributor ?? null);
+                break;
+            case 'topology_change':
+                await this.switchTopology(msg.topology ?? 'mesh', msg.peers ?? []);
+                break;
+            case 'recording_started':
+                this.recordingId = msg.id!;
+                this.resetRecordingConsent();
+                this.events.emit('recording-started', msg.id!, msg.by ?? null);
+                break;
+            case 'recording_stopped':
+                this.recordingId = null;
+                this.resetRecordingConsent();
+                this.events.emit('recording-stopped', msg.id!);
+                break;
+            case 'recording_consent': {
+                const consent = msg.consent ?? false;
+                const state = this.peerStates.get(msg.peer_id!);
+                if (state) this.setPeerState(msg.peer_id!, { ...state, recording_consent: consent });
+                this.events.emit('recording-consent', msg.peer_id!, consent);
+                break;
+            }
+            case 'speak_request': {
+                const state = this.peerStates.get(msg.peer_id!);
+                if (state) this.setPeerState(msg.peer_id!, { ...state, speak_requested: true });
+                this.events.emit('speak-request', msg.peer_id!);
+                break;
+            }
+            case 'set_role': {
+                const peerId = msg.peer_id!;
+                const role = msg.stage_role!;
+                const state = this.peerStates.get(peerId);
+                const previous = peerId === this.peerId ? this.stageRole : state?.stage_role;
+                if (state) this.setPeerState(peerId, { ...state, stage_role: role, speak_requested: false });
+                if (peerId === this.peerId) {
+                    this.stageRole = role;
+                    this.updateLocalTrack();
+                }
+                this.events.emit('stage-role', peerId, role);
+                if (this.topology === 'fanout' && previous !== role) await this.relinkStage(peerId);
+                break;
+            }
+            case 'floor':
+                this.floor = { queue: msg.queue ?? [], holder: msg.holder ?? null, single_speaker: msg.single_speaker ?? false };
+                this.events.emit('floor', this.floor);
+                break;
+            case 'force_mute': {
+                const peerId = msg.peer_id!;
+                if (peerId === this.peerId) {
+                    this.isMuted = true;
+                    this.updateLocalTrack();
+                } else {
+                    const mutedPeer = this.peers.get(peerId);
+                    if (mutedPeer) mutedPeer.remoteMuted = true;
+                    const state = this.peerStates.get(peerId);
+                    if (state) this.setPeerState(peerId, { ...state, muted: true });
+                    this.events.emit('peer-mute', peerId, true);
+                }
+                this.events.emit('force-mute', peerId);
+                break;
+            }
+            case 'closing':
+                this.closesAt = msg.closes_at ?? null;
+                this.events.emit('closing', msg.seconds_left ?? 0, msg.closes_at ?? 0);
+                break;
+            case 'announcement':
+                this.events.emit('announcement', msg.from, msg.text ?? '');
+                break;
+            case 'key_package':
+                this.events.emit('key-package', msg.from, msg.epoch ?? 0, msg.ciphertext ?? '');
                 break;
             case 'error':
-                this.handleError(msg.code ?? 'unknown', msg.message ?? 'Unknown error');
+                // Rejections of a single message don't end the session
+                if (NON_FATAL_ERRORS.has(msg.code ?? '')) {
+                    this.events.emit('warning', { code: msg.code!, message: msg.message ?? '' });
+                    break;
+                }
+                this.handleError(msg.code ?? 'unknown', msg.message ?? 'Unknown error', msg.retryable);
                 break;
         }
     }
@@ -464,11 +950,26 @@ export class VocaClient {
             if (peer) peer.stream = e.streams[0];
         };
 
-        // Add local tracks
-        this.localStream?.getTracks().forEach((track) => pc.addTrack(track, this.localStream!));
+        // Add local tracks; stage listeners only receive, but an offer still
+        // needs an audio section
+        if (this.stageRole === 'listener') {
+            if (isInitiator) pc.addTransceiver('audio', { direction: 'recvonly' });
+        } else {
+            this.localStream?.getTracks().forEach((track) => pc.addTrack(track, this.localStream!));
+        }
 
-        this.peers.set(peerId, { id: peerId, connection: pc, audioLevel: 0, remoteMuted: false, localMuted: false });
-        this.events.emit('peer-joined', peerId);
+        const role = this.roles.get(peerId) ?? (isInitiator ? 'impolite' : 'polite');
+        // Reconnecting a known peer (e.g. after a topology change) keeps its mute state
+        const previous = this.peers.get(peerId);
+        this.peers.set(peerId, {
+            id: peerId,
+            connection: pc,
+            audioLevel: 0,
+            remoteMuted: previous?.remoteMuted ?? false,
+            localMuted: previous?.localMuted ?? false,
+            role,
+        });
+        if (!previous) this.events.emit('peer-joined', peerId);
 
         if (isInitiator) {
             const offer = await pc.createOffer();
@@ -482,12 +983,172 @@ export class VocaClient {
         }
     }
 
+    /** Track a peer whose audio arrives through the SFU connection */
+    private addSfuPeer(peerId: string) {
+        if (this.peers.has(peerId)) return;
+        const connection = this.getSfuConnection();
+        this.peers.set(peerId, { id: peerId, connection, audioLevel: 0, remoteMuted: false, localMuted: false, role: 'polite' });
+        this.events.emit('peer-joined', peerId);
+    }
+
+    private getSfuConnection(): RTCPeerConnection {
+        if (this.sfuConnection) return this.sfuConnection;
+        const pc = new RTCPeerConnection({ iceServers: this.iceServers });
+
+        pc.onicecandidate = (e) => {
+            if (e.candidate) this.send({ type: 'ice', to: SFU_PEER_ID, candidate: JSON.stringify(e.candidate) });
+        };
+
+        // The SFU labels each forwarded stream with its sender's peer ID
+        pc.ontrack = (e) => {
+            const stream = e.streams[0];
+            if (!stream) return;
+            const peerId = stream.id;
+            this.events.emit('track', peerId, e.track, stream);
+            this.setupRemoteAudio(peerId, stream);
+            const peer = this.peers.get(peerId);
+            if (peer) peer.stream = stream;
+        };
+
+        this.localStream?.getTracks().forEach((track) => pc.addTrack(track, this.localStream!));
+        this.sfuConnection = pc;
+        return pc;
+    }
+
+    /** The SFU makes every offer; we always answer */
+    private async answerSfu(sdp: string) {
+        const pc = this.getSfuConnection();
+        await pc.setRemoteDescription({ type: 'offer', sdp });
+        const answer = await pc.createAnswer();
+        await pc.setLocalDescription(answer);
+        this.send({ type: 'answer', to: SFU_PEER_ID, sdp: answer.sdp });
+    }
+
+    /**
+     * Rebuild audio connections after the server moves the room between mesh
+     * and SFU. Peers stay in the room; only their connections are replaced.
+     * `order` lists every peer by join time, and in mesh the earlier joiner offers.
+     */
+    private async switchTopology(topology: Topology, order: string[]) {
+        if (topology === this.topology) return;
+        this.topology = topology;
+
+        const peerIds = [...this.peers.keys()];
+        for (const peerId of peerIds) {
+            const peer = this.peers.get(peerId)!;
+            if (peer.connection !== this.sfuConnection) peer.connection.close();
+            this.releaseRemoteAudio(peerId);
+            peer.stream = undefined;
+        }
+
+        if (topology === 'sfu') {
+            const connection = this.getSfuConnection();
+            for (const peerId of peerIds) {
+                const peer = this.peers.get(peerId)!;
+                peer.connection = connection;
+                peer.role = 'polite';
+            }
+            return;
+        }
+
+        this.sfuConnection?.close();
+        this.sfuConnection = null;
+        const self = order.indexOf(this.peerId ?? '');
+        for (const peerId of peerIds) {
+            this.roles.set(peerId, self < order.indexOf(peerId) ? 'impolite' : 'polite');
+        }
+        // Be ready for incoming offers before awaiting any of our own
+        const polite = peerIds.filter((peerId) => this.roles.get(peerId) === 'polite');
+        const impolite = peerIds.filter((peerId) => this.roles.get(peerId) === 'impolite');
+        for (const peerId of polite) await this.createPeer(peerId, false);
+        for (const peerId of impolite) await this.createPeer(peerId, true);
+    }
+
+    /** Whether we exchange audio with a peer; fan-out listeners only hear speakers */
+    private isLinked(peerId: string): boolean {
+        if (this.topology !== 'fanout') return true;
+        return this.stageRole === 'speaker' || this.peerStates.get(peerId)?.stage_role === 'speaker';
+    }
+
+    /**
+     * Rebuild fan-out connections after a stage role change, since audio
+     * direction is fixed per connection. Our own change affects every link;
+     * anyone else's only the link to them.
+     */
+    private async relinkStage(changed: string) {
+        const affected = changed === this.peerId ? [...this.peerStates.keys()] : [changed];
+        for (const peerId of affected) {
+            const peer = this.peers.get(peerId);
+            if (!peer) continue;
+            peer.connection.close();
+            this.releaseRemoteAudio(peerId);
+            peer.stream = undefined;
+            if (!this.isLinked(peerId)) {
+                this.peers.delete(peerId);
+                this.events.emit('peer-left', peerId);
+            }
+        }
+
+        const linked = affected.filter((peerId) => this.isLinked(peerId));
+        // Be ready for incoming offers before awaiting any of our own
+        const polite = linked.filter((peerId) => this.roles.get(peerId) === 'polite');
+        const impolite = linked.filter((peerId) => this.roles.get(peerId) === 'impolite');
+        for (const peerId of polite) await this.createPeer(peerId, false);
+        for (const peerId of impolite) await this.createPeer(peerId, true);
+    }
+
+    /** Listeners stay silent whatever their mute state */
+    private mayUnmute() {
+        return !this.floor.single_speaker || this.floor.holder === this.peerId;
+    }
+
+    private updateLocalTrack() {
+        const track = this.localStream?.getAudioTracks()[0];
+        if (track) track.enabled = !this.isMuted && this.stageRole !== 'listener';
+    }
+
+    private setPeerState(peerId: string, state: PeerState) {
+        this.peerStates.set(peerId, state);
+        this.events.emit('peer-state', peerId, state);
+    }
+
+    // Consent only covers one recording
+    private resetRecordingConsent() {
+        for (const [peerId, state] of this.peerStates) {
+            if (state.recording_consent) this.setPeerState(peerId, { ...state, recording_consent: false });
+        }
+    }
+
+    private applyPeerUpdate(peerId: string, changes: PeerUpdate) {
+        const state: PeerState = { muted: false, ...this.peerStates.get(peerId) };
+        if (changes.name !== undefined && changes.name !== null) state.name = changes.name || undefined;
+        if (changes.avatar !== undefined && changes.avatar !== null) state.avatar = changes.avatar || undefined;
+        if (typeof changes.muted === 'boolean') state.muted = changes.muted;
+        if (changes.public_key !== undefined && changes.public_key !== null) state.public_key = changes.public_key || undefined;
+        if (changes.metadata) {
+            const metadata = { ...state.metadata };
+            for (const [key, value] of Object.entries(changes.metadata)) {
+                if (value === null) delete metadata[key];
+                else metadata[key] = value;
+            }
+            state.metadata = metadata;
+        }
+        this.setPeerState(peerId, state);
+    }
+
     private removePeer(peerId: string) {
         const peer = this.peers.get(peerId);
-        peer?.connection.close();
+        // The SFU connection is shared and outlives individual peers
+        if (peer && peer.connection !== this.sfuConnection) peer.connection.close();
         this.peers.delete(peerId);
+        this.peerStates.delete(peerId);
+        this.roles.delete(peerId);
+        this.releaseRemoteAudio(peerId);
+        this.events.emit('peer-left', peerId);
+    }
 
-        // Clean up audio analysis nodes
+    /** Clean up a peer's audio analysis nodes */
+    private releaseRemoteAudio(peerId: string) {
         const audio = this.peerAnalysers.get(peerId);
         if (audio) {
             audio.source.disconnect();
@@ -495,8 +1156,6 @@ export class VocaClient {
             audio.gainNode.disconnect();
             this.peerAnalysers.delete(peerId);
         }
-
-        this.events.emit('peer-left', peerId);
     }
 
     private setupRemoteAudio(peerId: string, stream: MediaStream) {
@@ -554,9 +1213,13 @@ export class VocaClient {
         this.ws.send(JSON.stringify({ from: '', ...msg }));
     }
 
-    private handleError(code: VocaErrorCode | string, message: string) {
+    private handleError(code: VocaErrorCode | string, message: string, retryable?: boolean) {
         this.status = code === VocaErrorCode.ROOM_FULL || code === 'room_full' ? 'full' : 'error';
         this.events.emit('status', this.status);
-        this.events.emit('error', { code: code as VocaErrorCode, message });
+        this.events.emit('error', {
+            code: code as VocaErrorCode,
+            message,
+            retryable: retryable ?? VocaRetryableErrors.has(code as VocaErrorCode),
+        });
     }
 }
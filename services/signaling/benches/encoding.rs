@@ -0,0 +1,105 @@
+//! Compares per-recipient JSON serialization (the old fan-out path) with
+//! encode-once shared frames in JSON and MessagePack, for SDP- and
+//! ICE-heavy room traffic. Run with `cargo bench --bench encoding`.
+
+use axum::extract::ws::Message;
+use std::{hint::black_box, time::Instant};
+
+use signaling::codec::{Encoding, Frame};
+use signaling::types::{SignalMessage, SignalPayload};
+
+/// Peers per room; each broadcast reaches `ROOM_SIZE - 1` recipients
+const ROOM_SIZE: usize = 6;
+const ITERATIONS: usize = 20_000;
+
+fn sdp() -> String {
+    let mut sdp = String::from(
+        "v=0\r\no=- 4611731400430051336 2 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n\
+         a=group:BUNDLE 0\r\na=extmap-allow-mixed\r\na=msid-semantic: WMS stream\r\n\
+         m=audio 9 UDP/TLS/RTP/SAVPF 111 63 9 0 8 13 110 126\r\nc=IN IP4 0.0.0.0\r\n\
+         a=rtcp:9 IN IP4 0.0.0.0\r\na=ice-ufrag:4ZcD\r\na=ice-pwd:2/1muCWoOi3uLifh0NuRHlFr\r\n\
+         a=ice-options:trickle\r\na=fingerprint:sha-256 75:74:5A:A6:A4:E5:52:F4:A7:67:4C:01:C7:EE:91:3F:21:3D:A2:E3:53:7B:6F:30:86:F2:30:AA:65:FB:04:24\r\n\
+         a=setup:actpass\r\na=mid:0\r\na=sendrecv\r\na=rtcp-mux\r\n\
+         a=rtpmap:111 opus/48000/2\r\na=rtcp-fb:111 transport-cc\r\na=fmtp:111 minptime=10;useinbandfec=1\r\n",
+    );
+    for i in 0..12 {
+        sdp.push_str(&format!("a=extmap:{} urn:ietf:params:rtp-hdrext:example-{}\r\n", i + 1, i));
+    }
+    sdp
+}
+
+fn ice() -> String {
+    r#"{"candidate":"candidate:842163049 1 udp 1677729535 203.0.113.7 53412 typ srflx raddr 0.0.0.0 rport 0 generation 0 ufrag 4ZcD network-cost 999","sdpMid":"0","sdpMLineIndex":0,"usernameFragment":"4ZcD"}"#.to_string()
+}
+
+fn workload(kind: &str) -> Vec<SignalMessage> {
+    let msg = |payload| SignalMessage { from: "a1b2c3d4".to_string(), payload };
+    match kind {
+        "sdp" => (0..10)
+            .map(|i| {
+                if i % 2 == 0 {
+                    msg(SignalPayload::Offer { to: "e5f6g7h8".into(), sdp: sdp() })
+                } else {
+                    msg(SignalPayload::Answer { to: "e5f6g7h8".into(), sdp: sdp() })
+                }
+            })
+            .collect(),
+        _ => (0..10)
+            .map(|_| msg(SignalPayload::Ice { to: "e5f6g7h8".into(), candidate: ice() }))
+            .collect(),
+    }
+}
+
+fn message_len(msg: &Message) -> usize {
+    match msg {
+        Message::Text(t) => t.len(),
+        Message::Binary(b) => b.len(),
+        _ => 0,
+    }
+}
+
+/// Returns (nanoseconds per broadcast, bytes per recipient)
+fn run(messages: &[SignalMessage], strategy: &str) -> (f64, usize) {
+    let mut bytes = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for msg in messages {
+            bytes = 0;
+            match strategy {
+                "json-per-recipient" => {
+                    for _ in 1..ROOM_SIZE {
+                        let json = serde_json::to_string(black_box(msg)).unwrap();
+                        bytes = json.len();
+                        black_box(Message::Text(json.into()));
+                    }
+                }
+                "json-shared" | "msgpack-shared" => {
+                    let encoding = if strategy == "json-shared" { Encoding::Json } else { Encoding::MessagePack };
+                    let frame = Frame::new(black_box(msg.clone()));
+                    for _ in 1..ROOM_SIZE {
+                        let out = frame.to_message(encoding).unwrap();
+                        bytes = message_len(&out);
+                        black_box(out);
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+    let per_broadcast = start.elapsed().as_nanos() as f64 / (ITERATIONS * messages.len()) as f64;
+    (per_broadcast, bytes)
+}
+
+fn main() {
+    println!(
+        "{:<6} {:<20} {:>14} {:>16}",
+        "load", "strategy", "ns/broadcast", "bytes/recipient"
+    );
+    for kind in ["sdp", "ice"] {
+        let messages = workload(kind);
+        for strategy in ["json-per-recipient", "json-shared", "msgpack-shared"] {
+            let (ns, bytes) = run(&messages, strategy);
+            println!("{:<6} {:<20} {:>14.0} {:>16}", kind, strategy, ns, bytes);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Bot peers: backend services taking part in a room over HTTP.
+//!
+//! A bot joins through the REST API and shows up in the roster like anyone
+//! else, flagged `bot`. It posts chat, app messages and announcements with
+//! plain requests, and reads the room by long-polling or over server-sent
+//! events. Its subscription to the room is held between requests, so nothing
+//! is missed from one poll to the next unless it falls further behind than
+//! the room's channel holds. Bots have no audio and nobody connects to them.
+//! One that stops polling, streaming and posting for `BOT_IDLE_TIMEOUT` is
+//! taken out of the room, like a WebSocket peer that stops answering pings.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{watch, OwnedMutexGuard};
+
+use crate::codec::Frame;
+use crate::state::RoomKey;
+use crate::types::SignalMessage;
+
+/// How long a bot may go without polling, streaming or posting
+pub const BOT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a long-poll waits for the first event, by default
+pub const DEFAULT_POLL_WAIT: Duration = Duration::from_secs(25);
+/// Longest wait a long-poll may ask for
+pub const MAX_POLL_WAIT: Duration = Duration::from_secs(30);
+/// Most events returned by one long-poll
+pub const MAX_POLL_EVENTS: usize = 100;
+/// Bots a stage room takes. They hold no stage role, so they don't count
+/// against its speakers or listeners.
+pub const MAX_STAGE_BOTS: usize = 4;
+
+/// The room's traffic as seen by one bot; one request reads it at a time
+pub type BotEvents = OwnedMutexGuard<broadcast::Receiver<Arc<Frame>>>;
+
+/// One bot in one room
+pub struct BotSession {
+    pub key: RoomKey,
+    pub peer_id: String,
+    events: Arc<tokio::sync::Mutex<broadcast::Receiver<Arc<Frame>>>>,
+    last_seen: Mutex<Instant>,
+    /// Open event streams; a bot with one is never idle
+    streams: AtomicUsize,
+    /// Set once the bot is out of the room, ending its reads
+    left: watch::Sender<bool>,
+}
+
+/// What a read of the room returned
+#[derive(Default)]
+pub struct BotRead {
+    pub events: Vec<SignalMessage>,
+    /// Events dropped because the bot fell behind
+    pub missed: u64,
+}
+
+impl BotSession {
+    pub fn new(key: RoomKey, peer_id: String, events: broadcast::Receiver<Arc<Frame>>) -> Self {
+        Self {
+            key,
+            peer_id,
+            events: Arc::new(tokio::sync::Mutex::new(events)),
+            last_seen: Mutex::new(Instant::now()),
+            streams: AtomicUsize::new(0),
+            left: watch::channel(false).0,
+        }
+    }
+
+    /// End the bot's open stream and poll; call when it's taken out of the room
+    pub fn leave(&self) {
+        self.left.send_replace(true);
+    }
+
+    /// Note that the bot is still there
+    pub fn touch(&self) {
+        *self.last_seen.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.streams.load(Ordering::Relaxed) == 0
+            && self.last_seen.lock().unwrap_or_else(|e| e.into_inner()).elapsed() > BOT_IDLE_TIMEOUT
+    }
+
+    /// Take the bot's subscription for one poll or stream; `None` while
+    /// another request is reading it
+    pub fn reader(&self) -> Option<BotEvents> {
+        self.events.clone().try_lock_owned().ok()
+    }
+
+    /// Count an open event stream until the returned guard is dropped
+    pub fn stream(self: &Arc<Self>) -> StreamGuard {
+        self.streams.fetch_add(1, Ordering::Relaxed);
+        StreamGuard(self.clone())
+    }
+
+    /// Whatever is waiting, or if nothing is, the first events to arrive
+    /// within `wait`
+    pub async fn poll(&self, events: &mut BotEvents, wait: Duration) -> BotRead {
+        let deadline = tokio::time::Instant::now() + wait;
+        let mut left = self.left.subscribe();
+        let mut read = BotRead::default();
+        while read.events.len() < MAX_POLL_EVENTS {
+            let next = if read.events.is_empty() {
+                tokio::select! {
+                    next = tokio::time::timeout_at(deadline, events.recv()) => match next {
+                        Ok(next) => next,
+                        Err(_) => break,
+                    },
+                    _ = left.wait_for(|&left| left) => break,
+                }
+            } else {
+                match events.try_recv() {
+                    Ok(frame) => Ok(frame),
+                    Err(broadcast::error::TryRecvError::Lagged(n)) => Err(RecvError::Lagged(n)),
+                    Err(_) => break,
+                }
+            };
+            match next {
+                Ok(frame) if frame.msg.reaches(&self.peer_id) => read.events.push(frame.msg.clone()),
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => read.missed += n,
+                Err(RecvError::Closed) => break,
+            }
+        }
+        self.touch();
+        read
+    }
+
+    /// The next event for this bot, or how many it missed by falling behind.
+    /// `None` once the bot has left or the room is gone.
+    pub async fn next(&self, events: &mut BotEvents) -> Option<Result<SignalMessage, u64>> {
+        let mut left = self.left.subscribe();
+        loop {
+            let next = tokio::select! {
+                next = events.recv() => next,
+                _ = left.wait_for(|&left| left) => return None,
+            };
+            match next {
+                Ok(frame) if frame.msg.reaches(&self.peer_id) => return Some(Ok(frame.msg.clone())),
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => return Some(Err(n)),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Keeps a bot alive while its event stream is open
+pub struct StreamGuard(Arc<BotSession>);
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.streams.fetch_sub(1, Ordering::Relaxed);
+        // The idle clock starts when the stream closes
+        self.0.touch();
+    }
+}
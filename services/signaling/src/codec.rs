@@ -0,0 +1,276 @@
+use axum::{
+    body::Bytes,
+    extract::ws::{Message, Utf8Bytes},
+};
+use std::sync::{Arc, OnceLock};
+
+use crate::types::SignalMessage;
+
+/// WebSocket subprotocol that selects MessagePack frames from the first message
+pub const MSGPACK_SUBPROTOCOL: &str = "voca.msgpack";
+/// WebSocket subprotocol for plain JSON text frames (the default)
+pub const JSON_SUBPROTOCOL: &str = "voca.json";
+
+/// Wire encoding used for one connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+}
+
+/// A message broadcast to a room, encoded at most once per wire format and
+/// shared by every recipient instead of re-serialized per peer.
+#[derive(Debug)]
+pub struct Frame {
+    pub msg: SignalMessage,
+    json: OnceLock<Option<Utf8Bytes>>,
+    msgpack: OnceLock<Option<Bytes>>,
+}
+
+impl Frame {
+    pub fn new(msg: SignalMessage) -> Arc<Self> {
+        Arc::new(Self {
+            msg,
+            json: OnceLock::new(),
+            msgpack: OnceLock::new(),
+        })
+    }
+
+    /// WebSocket message for this frame in the given encoding
+    pub fn to_message(&self, encoding: Encoding) -> Option<Message> {
+        match encoding {
+            Encoding::Json => self
+                .json
+                .get_or_init(|| serde_json::to_string(&self.msg).ok().map(Utf8Bytes::from))
+                .clone()
+                .map(Message::Text),
+            Encoding::MessagePack => self
+                .msgpack
+                .get_or_init(|| rmp_serde::to_vec_named(&self.msg).ok().map(Bytes::from))
+                .clone()
+                .map(Message::Binary),
+        }
+    }
+}
+
+/// Encode a one-off message that isn't broadcast (welcome, errors, pings)
+pub fn encode(msg: &SignalMessage, encoding: Encoding) -> Option<Message> {
+    match encoding {
+        Encoding::Json => serde_json::to_string(msg).ok().map(|s| Message::Text(s.into())),
+        Encoding::MessagePack => rmp_serde::to_vec_named(msg)
+            .ok()
+            .map(|v| Message::Binary(v.into())),
+    }
+}
+
+/// Decode an incoming data frame. Text is always JSON; binary frames are
+/// MessagePack and only accepted once that encoding was negotiated.
+pub fn decode(message: &Message, encoding: Encoding) -> Option<Result<SignalMessage, String>> {
+    match message {
+        Message::Text(text) => Some(serde_json::from_str(text).map_err(|e| e.to_string())),
+        Message::Binary(bytes) if encoding == Encoding::MessagePack => {
+            Some(rmp_serde::from_slice(bytes).map_err(|e| e.to_string()))
+        }
+        Message::Binary(_) => Some(Err("binary frames were not negotiated".to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::chat::ChatMessage;
+    use crate::error::{ApiError, ErrorCode};
+    use crate::floor::Floor;
+    use crate::peer::{PeerState, PeerUpdate};
+    use crate::protocol::{Capability, NegotiationRole, Topology};
+    use crate::stage::StageRole;
+    use crate::types::SignalPayload;
+
+    /// Every variant, with its optional fields filled in
+    fn every_payload() -> Vec<SignalPayload> {
+        let state = PeerState {
+            name: Some("ada".to_string()),
+            metadata: json!({ "seat": 3 }).as_object().unwrap().clone(),
+            joined_at: 1_700_000_000_000,
+            stage_role: Some(StageRole::Speaker),
+            ..PeerState::default()
+        };
+        let update = PeerUpdate {
+            muted: Some(true),
+            metadata: Some(json!({ "seat": null }).as_object().unwrap().clone()),
+            ..PeerUpdate::default()
+        };
+        let floor = Floor {
+            queue: vec!["b".to_string(), "c".to_string()],
+            holder: Some("a".to_string()),
+            single_speaker: true,
+        };
+        let chat = ChatMessage {
+            id: "m1".to_string(),
+            from: "a".to_string(),
+            text: "hi".to_string(),
+            ts: 42,
+            edited: true,
+        };
+        vec![
+            SignalPayload::Hello {
+                version: "1.2.3".to_string(),
+                client: "test".to_string(),
+                protocol: 2,
+                capabilities: vec![Capability::Binary, Capability::Roster],
+                state: Some(update.clone()),
+            },
+            SignalPayload::Waiting { position: 2 },
+            SignalPayload::Welcome {
+                version: "1.2.3".to_string(),
+                peer_id: "a".to_string(),
+                protocol: 2,
+                capabilities: vec![Capability::Roster],
+                peers: Some(vec!["b".to_string()]),
+                states: Some(BTreeMap::from([("b".to_string(), state.clone())])),
+                roles: Some(BTreeMap::from([("b".to_string(), NegotiationRole::Polite)])),
+                host: Some("b".to_string()),
+                chat: vec![chat],
+                key_epoch: u64::MAX,
+                topology: Topology::Sfu,
+                recording: Some("r1".to_string()),
+                stage_role: Some(StageRole::Listener),
+                floor: floor.clone(),
+                closes_at: Some(1_700_000_600_000),
+            },
+            SignalPayload::Join {
+                peer_id: "b".to_string(),
+                state: Some(state),
+                role: Some(NegotiationRole::Impolite),
+            },
+            SignalPayload::Leave { peer_id: "b".to_string() },
+            SignalPayload::Offer { to: "b".to_string(), sdp: "v=0\r\n".to_string() },
+            SignalPayload::Answer { to: "a".to_string(), sdp: "v=0\r\n".to_string() },
+            SignalPayload::Ice { to: "b".to_string(), candidate: "candidate:1".to_string() },
+            SignalPayload::Mute { muted: true },
+            SignalPayload::PeerUpdate { peer_id: "a".to_string(), changes: update },
+            SignalPayload::Host { peer_id: "b".to_string() },
+            SignalPayload::Chat { id: "m1".to_string(), text: "hi".to_string(), ts: 42 },
+            SignalPayload::ChatEdit { id: "m1".to_string(), text: "hello".to_string() },
+            SignalPayload::ChatDelete { id: "m1".to_string() },
+            SignalPayload::KeyPackage { to: "b".to_string(), epoch: 7, ciphertext: "c2VhbGVk".to_string() },
+            SignalPayload::KeyEpoch { epoch: 8, distributor: Some("a".to_string()) },
+            SignalPayload::Rekey,
+            SignalPayload::TopologyChange { topology: Topology::Fanout, peers: vec!["a".to_string(), "b".to_string()] },
+            SignalPayload::RecordingStart,
+            SignalPayload::RecordingStop,
+            SignalPayload::RecordingStarted { id: "r1".to_string(), by: "a".to_string() },
+            SignalPayload::RecordingStopped { id: "r1".to_string() },
+            SignalPayload::RecordingConsent { peer_id: "b".to_string(), consent: true },
+            SignalPayload::SpeakRequest { peer_id: "b".to_string() },
+            SignalPayload::SetRole { peer_id: "b".to_string(), stage_role: StageRole::Speaker },
+            SignalPayload::RaiseHand,
+            SignalPayload::LowerHand { peer_id: Some("b".to_string()) },
+            SignalPayload::GrantFloor { peer_id: Some("b".to_string()) },
+            SignalPayload::SingleSpeaker { enabled: true },
+            SignalPayload::Floor(floor),
+            SignalPayload::ForceMute { peer_id: "b".to_string() },
+            SignalPayload::Closing { closes_at: 1_700_000_600_000, seconds_left: 60 },
+            SignalPayload::App {
+                kind: "reaction".to_string(),
+                data: json!({ "emoji": "👍", "n": [1, -2, 3.5], "nested": { "ok": true, "none": null } }),
+                to: Some("b".to_string()),
+            },
+            SignalPayload::Announcement { text: "welcome".to_string() },
+            SignalPayload::Ping,
+            SignalPayload::Pong,
+            SignalPayload::Error(ApiError::with_message(ErrorCode::RoomFull, "full")),
+        ]
+    }
+
+    /// Name of a payload's variant. The match has no wildcard, so a new
+    /// variant doesn't compile until it has a sample in `every_payload`.
+    fn variant(payload: &SignalPayload) -> &'static str {
+        match payload {
+            SignalPayload::Hello { .. } => "hello",
+            SignalPayload::Waiting { .. } => "waiting",
+            SignalPayload::Welcome { .. } => "welcome",
+            SignalPayload::Join { .. } => "join",
+            SignalPayload::Leave { .. } => "leave",
+            SignalPayload::Offer { .. } => "offer",
+            SignalPayload::Answer { .. } => "answer",
+            SignalPayload::Ice { .. } => "ice",
+            SignalPayload::Mute { .. } => "mute",
+            SignalPayload::PeerUpdate { .. } => "peer_update",
+            SignalPayload::Host { .. } => "host",
+            SignalPayload::Chat { .. } => "chat",
+            SignalPayload::ChatEdit { .. } => "chat_edit",
+            SignalPayload::ChatDelete { .. } => "chat_delete",
+            SignalPayload::KeyPackage { .. } => "key_package",
+            SignalPayload::KeyEpoch { .. } => "key_epoch",
+            SignalPayload::Rekey => "rekey",
+            SignalPayload::TopologyChange { .. } => "topology_change",
+            SignalPayload::RecordingStart => "recording_start",
+            SignalPayload::RecordingStop => "recording_stop",
+            SignalPayload::RecordingStarted { .. } => "recording_started",
+            SignalPayload::RecordingStopped { .. } => "recording_stopped",
+            SignalPayload::RecordingConsent { .. } => "recording_consent",
+            SignalPayload::SpeakRequest { .. } => "speak_request",
+            SignalPayload::SetRole { .. } => "set_role",
+            SignalPayload::RaiseHand => "raise_hand",
+            SignalPayload::LowerHand { .. } => "lower_hand",
+            SignalPayload::GrantFloor { .. } => "grant_floor",
+            SignalPayload::SingleSpeaker { .. } => "single_speaker",
+            SignalPayload::Floor(_) => "floor",
+            SignalPayload::ForceMute { .. } => "force_mute",
+            SignalPayload::Closing { .. } => "closing",
+            SignalPayload::App { .. } => "app",
+            SignalPayload::Announcement { .. } => "announcement",
+            SignalPayload::Ping => "ping",
+            SignalPayload::Pong => "pong",
+            SignalPayload::Error(_) => "error",
+        }
+    }
+
+    #[test]
+    fn every_payload_survives_messagepack() {
+        let payloads = every_payload();
+        let mut seen: Vec<&str> = payloads.iter().map(variant).collect();
+        seen.sort_unstable();
+        seen.dedup();
+        assert_eq!(seen.len(), payloads.len(), "a variant is sampled twice");
+
+        for payload in payloads {
+            let msg = SignalMessage {
+                from: "server".to_string(),
+                payload,
+            };
+            let expected = serde_json::to_value(&msg).unwrap();
+            // The tag is the wire name the variant's match arm promises
+            assert_eq!(expected["type"], variant(&msg.payload));
+
+            let wire = encode(&msg, Encoding::MessagePack).expect("encode failed");
+            assert!(matches!(wire, Message::Binary(_)));
+            let decoded = decode(&wire, Encoding::MessagePack)
+                .unwrap()
+                .unwrap_or_else(|e| panic!("{} did not decode: {}", expected["type"], e));
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), expected);
+
+            // Broadcast frames take the same path
+            let frame = Frame::new(msg).to_message(Encoding::MessagePack).unwrap();
+            let decoded = decode(&frame, Encoding::MessagePack).unwrap().unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn binary_frames_need_messagepack() {
+        let msg = SignalMessage {
+            from: String::new(),
+            payload: SignalPayload::Ping,
+        };
+        let wire = encode(&msg, Encoding::MessagePack).unwrap();
+        assert!(decode(&wire, Encoding::Json).unwrap().is_err());
+        let text = encode(&msg, Encoding::Json).unwrap();
+        assert!(decode(&text, Encoding::MessagePack).unwrap().is_ok());
+    }
+}
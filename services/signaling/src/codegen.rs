@@ -0,0 +1,580 @@
+//! Generates JSON Schema, TypeScript declarations and an OpenAPI document
+//! from the protocol and REST types, so the SDKs and docs can't drift from
+//! the server. Run with `signaling codegen <out-dir>`.
+
+use schemars::{generate::SchemaSettings, SchemaGenerator};
+use serde_json::{json, Map, Value};
+use std::{fmt::Write as _, path::Path};
+
+use crate::error::{ApiError, RestError};
+use crate::state::SERVER_VERSION;
+use crate::types::{
+    AdminAppsResponse, AdminBansResponse, AdminLogsResponse, AdminRoomsResponse, AdminUsageResponse,
+    AdminWebhooksResponse, BanRequest, BotEventsResponse, BotJoinResponse, CheckRoomResponse, CreateRoomResponse,
+    HealthResponse, MetricsResponse, ReadinessResponse, SignalMessage, WebhookRetryResponse,
+};
+use crate::webhooks::WebhookEvent;
+
+/// Collect every public protocol and REST type into one set of definitions
+fn definitions() -> Map<String, Value> {
+    let mut generator: SchemaGenerator = SchemaSettings::draft2020_12().into_generator();
+    generator.subschema_for::<SignalMessage>();
+    generator.subschema_for::<ApiError>();
+    generator.subschema_for::<RestError>();
+    generator.subschema_for::<CreateRoomResponse>();
+    generator.subschema_for::<CheckRoomResponse>();
+    generator.subschema_for::<AdminRoomsResponse>();
+    generator.subschema_for::<AdminLogsResponse>();
+    generator.subschema_for::<MetricsResponse>();
+    generator.subschema_for::<AdminBansResponse>();
+    generator.subschema_for::<BanRequest>();
+    generator.subschema_for::<AdminAppsResponse>();
+    generator.subschema_for::<AdminUsageResponse>();
+    generator.subschema_for::<AdminWebhooksResponse>();
+    generator.subschema_for::<WebhookRetryResponse>();
+    generator.subschema_for::<WebhookEvent>();
+    generator.subschema_for::<BotJoinResponse>();
+    generator.subschema_for::<BotEventsResponse>();
+    generator.subschema_for::<HealthResponse>();
+    generator.subschema_for::<ReadinessResponse>();
+    generator.take_definitions(true)
+}
+
+/// JSON Schema (draft 2020-12) bundling all types under `$defs`
+pub fn json_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": "https://voca.vc/schema/protocol.json",
+        "title": "Voca signaling protocol",
+        "version": SERVER_VERSION,
+        "$defs": definitions(),
+    })
+}
+
+/// TypeScript type declarations for every definition
+pub fn typescript() -> String {
+    let mut out = String::new();
+    out.push_str("// Generated by `signaling codegen` from services/signaling. Do not edit.\n");
+    let _ = writeln!(out, "// Server version {}", SERVER_VERSION);
+
+    for (name, schema) in definitions() {
+        out.push('\n');
+        write_doc(&mut out, &schema, "");
+        let ty = ts_type(&schema, "");
+        let sep = if ty.starts_with('\n') { "" } else { " " };
+        let _ = writeln!(out, "export type {} ={}{};", name, sep, ty);
+    }
+    out
+}
+
+/// OpenAPI 3.1 document for the HTTP routes
+pub fn openapi() -> Value {
+    let schemas = Value::Object(definitions());
+    let schemas = rewrite_refs(schemas);
+
+    let error = json!({ "$ref": "#/components/schemas/RestError" });
+    let ok = |schema: &str, description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } }
+        })
+    };
+    let err = |description: &str| {
+        json!({ "description": description, "content": { "application/json": { "schema": error.clone() } } })
+    };
+    let query = |name: &str, description: &str| {
+        json!({ "name": name, "in": "query", "required": false, "schema": { "type": "string" }, "description": description })
+    };
+    let header = |name: &str, description: &str| {
+        json!({ "name": name, "in": "header", "required": false, "schema": { "type": "string" }, "description": description })
+    };
+    let room_path = json!({ "name": "room", "in": "path", "required": true, "schema": { "type": "string" } });
+    let stream_path = json!({ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } });
+    let sdp = json!({ "required": true, "content": { "application/sdp": { "schema": { "type": "string" } } } });
+    let sdp_answer = json!({
+        "description": "SDP answer; the Location header, relative to the request URL, is the session to DELETE",
+        "content": { "application/sdp": { "schema": { "type": "string" } } }
+    });
+    let admin_security = json!([{ "adminToken": [] }]);
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Voca signaling API",
+            "version": SERVER_VERSION,
+        },
+        "paths": {
+            "/api/room": {
+                "post": {
+                    "summary": "Create a room",
+                    "parameters": [
+                        query("appId", "Application namespace, defaults to `public`"),
+                        query("max_peers", "Room capacity, capped by the server limit"),
+                        query("password", "Optional 4-12 character alphanumeric password"),
+                        query("topology", "`mesh` or `sfu`, or `sfu` or `fanout` for stage rooms; omit to switch automatically as the room grows"),
+                        query("stage", "`true` for a stage room with speakers and listeners"),
+                        query("max_speakers", "Stage speaker capacity, capped by the server limit"),
+                        query("max_listeners", "Stage listener capacity, capped by the server limit"),
+                        query("room", "Vanity room ID (4-32 letters, numbers or hyphens) instead of a random one"),
+                        query("persistent", "`true` to reserve the room: it is kept while empty and the response carries a host key"),
+                        query("starts_at", "Unix milliseconds before which joins are refused with `room_not_open`"),
+                        query("ends_at", "Unix milliseconds at which the room closes, after `closing` warnings"),
+                    ],
+                    "security": [{ "apiKey": [] }, {}],
+                    "responses": {
+                        "200": ok("CreateRoomResponse", "Room created"),
+                        "400": err("Invalid password format, room ID or schedule"),
+                        "401": err("Invalid API key"),
+                        "403": err("Origin not allowed or address banned"),
+                        "409": err("Room ID already in use"),
+                        "429": err("Global room limit reached or rate limited"),
+                        "503": err("Server draining or SFU unavailable"),
+                    }
+                }
+            },
+            "/api/room/{room}": {
+                "get": {
+                    "summary": "Check whether a room exists",
+                    "parameters": [room_path.clone(), query("appId", "Application namespace")],
+                    "responses": {
+                        "200": ok("CheckRoomResponse", "Room status"),
+                        "400": err("Invalid room ID"),
+                        "403": err("Origin not allowed"),
+                        "404": err("Room not found"),
+                    }
+                },
+                "delete": {
+                    "summary": "Delete a reserved room; it closes once its last peer leaves",
+                    "parameters": [
+                        room_path.clone(),
+                        query("appId", "Application namespace"),
+                        { "name": "x-host-key", "in": "header", "required": true, "schema": { "type": "string" } },
+                    ],
+                    "security": [{ "apiKey": [] }, {}],
+                    "responses": {
+                        "204": { "description": "Room released" },
+                        "401": err("Invalid API key or host key"),
+                        "403": err("Room is not reserved, or origin not allowed"),
+                        "404": err("Room not found"),
+                    }
+                }
+            },
+            "/api/room/{room}/whip": {
+                "post": {
+                    "summary": "Stream a source into an SFU room (WHIP); the body is an SDP offer",
+                    "parameters": [
+                        room_path.clone(),
+                        query("appId", "Application namespace"),
+                        query("name", "Display name for the source"),
+                    ],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "requestBody": sdp.clone(),
+                    "responses": {
+                        "201": sdp_answer.clone(),
+                        "400": err("Invalid offer, or the room can't use the SFU"),
+                        "401": err("Invalid API key"),
+                        "403": err("Room not open yet"),
+                        "404": err("Room not found"),
+                        "409": err("Room full"),
+                        "503": err("SFU unavailable or server draining"),
+                    }
+                }
+            },
+            "/api/room/{room}/whip/{id}": {
+                "delete": {
+                    "summary": "End a WHIP session",
+                    "parameters": [room_path.clone(), stream_path.clone(), query("appId", "Application namespace")],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "responses": { "200": { "description": "Session ended" }, "404": err("Stream not found") }
+                }
+            },
+            "/api/room/{room}/whep": {
+                "post": {
+                    "summary": "Receive an SFU room's audio (WHEP); the body is an SDP offer",
+                    "parameters": [room_path.clone(), query("appId", "Application namespace")],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "requestBody": sdp,
+                    "responses": {
+                        "201": sdp_answer,
+                        "400": err("Invalid offer, or the room can't use the SFU"),
+                        "401": err("Invalid API key"),
+                        "404": err("Room not found"),
+                        "503": err("SFU unavailable or server draining"),
+                    }
+                }
+            },
+            "/api/room/{room}/whep/{id}": {
+                "delete": {
+                    "summary": "End a WHEP session",
+                    "parameters": [room_path.clone(), stream_path.clone(), query("appId", "Application namespace")],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "responses": { "200": { "description": "Session ended" }, "404": err("Stream not found") }
+                }
+            },
+            "/api/room/{room}/bots": {
+                "post": {
+                    "summary": "Join a room as a bot peer; the body is its profile",
+                    "parameters": [room_path.clone(), query("appId", "Application namespace")],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/PeerUpdate" } } }
+                    },
+                    "responses": {
+                        "201": ok("BotJoinResponse", "Joined; the roster and recent chat"),
+                        "400": err("Invalid profile"),
+                        "401": err("Invalid API key"),
+                        "403": err("Room not open yet, or address banned"),
+                        "404": err("Room not found"),
+                        "409": err("Room full"),
+                    }
+                }
+            },
+            "/api/room/{room}/bots/{id}": {
+                "delete": {
+                    "summary": "Take a bot out of the room",
+                    "parameters": [room_path.clone(), stream_path.clone(), query("appId", "Application namespace")],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "responses": { "204": { "description": "Bot left" }, "404": err("Bot not found") }
+                }
+            },
+            "/api/room/{room}/bots/{id}/messages": {
+                "post": {
+                    "summary": "Send chat, an app message or an announcement as the bot",
+                    "description": "The body is a message's `type` and fields; `from` is set by the server.",
+                    "parameters": [room_path.clone(), stream_path.clone(), query("appId", "Application namespace")],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SignalMessage" } } }
+                    },
+                    "responses": {
+                        "200": ok("SignalMessage", "The message as the room received it"),
+                        "400": err("Message type not allowed for bots, or invalid"),
+                        "404": err("Bot not found"),
+                    }
+                }
+            },
+            "/api/room/{room}/bots/{id}/events": {
+                "get": {
+                    "summary": "Read the room's events, by long-poll or as server-sent events",
+                    "description": "Send `Accept: text/event-stream` for a stream; otherwise the request waits up to \
+                        `wait` seconds for the first events.",
+                    "parameters": [
+                        room_path.clone(),
+                        stream_path,
+                        query("appId", "Application namespace"),
+                        query("wait", "Seconds to wait for events when long-polling (default 25, at most 30)"),
+                    ],
+                    "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+                    "responses": {
+                        "200": {
+                            "description": "Events since the last read",
+                            "content": {
+                                "application/json": { "schema": { "$ref": "#/components/schemas/BotEventsResponse" } },
+                                "text/event-stream": { "schema": { "type": "string" } }
+                            }
+                        },
+                        "403": err("Another request is already reading this bot's events"),
+                        "404": err("Bot not found"),
+                    }
+                }
+            },
+            "/ws/{room}": {
+                "get": {
+                    "summary": "WebSocket signaling; messages follow the SignalMessage schema",
+                    "parameters": [
+                        room_path,
+                        query("appId", "Application namespace"),
+                        query("apiKey", "API key, if the server requires one"),
+                        query("password", "Room password"),
+                        query("wait", "`true` to queue for a slot when the room is full instead of getting `room_full`"),
+                        query("hostKey", "Host key of a reserved room, to join as its host"),
+                    ],
+                    "responses": {
+                        "101": { "description": "Switching protocols" },
+                        "400": err("Invalid room ID"),
+                        "403": err("Origin not allowed"),
+                        "404": err("Room not found"),
+                    }
+                }
+            },
+            "/api/admin/rooms": {
+                "get": {
+                    "summary": "List active rooms",
+                    "security": admin_security.clone(),
+                    "responses": { "200": ok("AdminRoomsResponse", "Active rooms"), "401": err("Invalid token") }
+                }
+            },
+            "/api/admin/metrics": {
+                "get": {
+                    "summary": "Usage metrics",
+                    "security": admin_security.clone(),
+                    "responses": { "200": ok("MetricsResponse", "Metrics"), "401": err("Invalid token") }
+                }
+            },
+            "/api/admin/logs": {
+                "get": {
+                    "summary": "Recent log lines",
+                    "security": admin_security.clone(),
+                    "responses": { "200": ok("AdminLogsResponse", "Log lines"), "401": err("Invalid token") }
+                }
+            },
+            "/api/admin/bans": {
+                "get": {
+                    "summary": "List banned addresses",
+                    "security": admin_security.clone(),
+                    "responses": { "200": ok("AdminBansResponse", "Banned addresses"), "401": err("Invalid token") }
+                },
+                "post": {
+                    "summary": "Ban an address from creating or joining rooms",
+                    "security": admin_security.clone(),
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/BanRequest" } } }
+                    },
+                    "responses": {
+                        "201": ok("Ban", "Address banned"),
+                        "400": err("Invalid IP address"),
+                        "401": err("Invalid token"),
+                    }
+                }
+            },
+            "/api/admin/bans/{ip}": {
+                "delete": {
+                    "summary": "Lift a ban",
+                    "parameters": [{ "name": "ip", "in": "path", "required": true, "schema": { "type": "string" } }],
+                    "security": admin_security.clone(),
+                    "responses": {
+                        "204": { "description": "Ban lifted" },
+                        "401": err("Invalid token"),
+                        "404": { "description": "Address wasn't banned" },
+                    }
+                }
+            },
+            "/api/admin/apps": {
+                "get": {
+                    "summary": "Apps that have created rooms",
+                    "security": admin_security.clone(),
+                    "responses": {
+                        "200": ok("AdminAppsResponse", "Known apps"),
+                        "401": err("Invalid token"),
+                        "500": err("Storage unavailable"),
+                    }
+                }
+            },
+            "/api/admin/usage": {
+                "get": {
+                    "summary": "Daily usage counters, today included",
+                    "parameters": [query("days", "How many days back to report, including today (default 30)")],
+                    "security": admin_security.clone(),
+                    "responses": {
+                        "200": ok("AdminUsageResponse", "Usage by UTC day"),
+                        "401": err("Invalid token"),
+                        "500": err("Storage unavailable"),
+                    }
+                }
+            },
+            "/api/admin/webhooks": {
+                "get": {
+                    "summary": "Pending webhook deliveries and dead letters",
+                    "security": admin_security.clone(),
+                    "responses": { "200": ok("AdminWebhooksResponse", "Delivery status"), "401": err("Invalid token") }
+                }
+            },
+            "/api/admin/webhooks/retry": {
+                "post": {
+                    "summary": "Queue every dead letter for delivery again",
+                    "security": admin_security.clone(),
+                    "responses": { "202": ok("WebhookRetryResponse", "Dead letters queued"), "401": err("Invalid token") }
+                }
+            },
+            "/api/admin/webhooks/dead-letters": {
+                "delete": {
+                    "summary": "Drop every dead letter",
+                    "security": admin_security,
+                    "responses": { "204": { "description": "Dead letters dropped" }, "401": err("Invalid token") }
+                }
+            },
+            "/healthz": {
+                "get": {
+                    "summary": "Liveness probe",
+                    "responses": { "200": ok("HealthResponse", "Process is up") }
+                }
+            },
+            "/readyz": {
+                "get": {
+                    "summary": "Readiness probe",
+                    "responses": {
+                        "200": ok("ReadinessResponse", "Accepting new rooms"),
+                        "503": ok("ReadinessResponse", "Draining, at room capacity or unable to reach storage"),
+                    }
+                }
+            }
+        },
+        "webhooks": {
+            "lifecycle": {
+                "post": {
+                    "summary": "Room or peer lifecycle event, sent to the app's webhook URL",
+                    "parameters": [
+                        header("x-voca-event", "Event type, as in the body's `type`"),
+                        header("x-voca-delivery", "Event ID, the same on every retry"),
+                        header("x-voca-timestamp", "Unix milliseconds when this attempt was sent"),
+                        header("x-voca-signature", "`sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}`"),
+                    ],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/WebhookEvent" } } }
+                    },
+                    "responses": { "2XX": { "description": "Delivered; anything else is retried" } }
+                }
+            }
+        },
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "apiKey": { "type": "apiKey", "in": "header", "name": "x-api-key" },
+                "adminToken": { "type": "http", "scheme": "bearer" },
+                "bearer": { "type": "http", "scheme": "bearer", "description": "API key as a bearer token (WHIP/WHEP)" },
+            }
+        }
+    })
+}
+
+/// Write `protocol.schema.json`, `protocol.ts` and `openapi.json` into `dir`
+pub fn write_all(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(
+        dir.join("protocol.schema.json"),
+        serde_json::to_string_pretty(&json_schema())? + "\n",
+    )?;
+    std::fs::write(dir.join("protocol.ts"), typescript())?;
+    std::fs::write(
+        dir.join("openapi.json"),
+        serde_json::to_string_pretty(&openapi())? + "\n",
+    )?;
+    Ok(())
+}
+
+/// Point `$ref`s at OpenAPI components instead of JSON Schema `$defs`
+fn rewrite_refs(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| match (k.as_str(), v) {
+                    ("$ref", Value::String(r)) => {
+                        (k, Value::String(r.replace("#/$defs/", "#/components/schemas/")))
+                    }
+                    (_, v) => (k, rewrite_refs(v)),
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(rewrite_refs).collect()),
+        other => other,
+    }
+}
+
+fn write_doc(out: &mut String, schema: &Value, indent: &str) {
+    if let Some(desc) = schema.get("description").and_then(Value::as_str) {
+        let _ = writeln!(out, "{}/**", indent);
+        for line in desc.lines() {
+            let _ = writeln!(out, "{} *{}{}", indent, if line.is_empty() { "" } else { " " }, line);
+        }
+        let _ = writeln!(out, "{} */", indent);
+    }
+}
+
+/// Translate the subset of JSON Schema that schemars emits into a TS type
+fn ts_type(schema: &Value, indent: &str) -> String {
+    let Some(obj) = schema.as_object() else {
+        return "unknown".to_string();
+    };
+
+    let mut parts = Vec::new();
+
+    if let Some(r) = obj.get("$ref").and_then(Value::as_str) {
+        parts.push(r.trim_start_matches("#/$defs/").to_string());
+    }
+
+    if let Some(c) = obj.get("const") {
+        return c.to_string();
+    }
+    if let Some(values) = obj.get("enum").and_then(Value::as_array) {
+        return values.iter().map(Value::to_string).collect::<Vec<_>>().join(" | ");
+    }
+
+    if obj.contains_key("properties") {
+        parts.push(ts_object(obj, indent));
+    } else if let Some(ty) = obj.get("type") {
+        let types: Vec<&str> = match ty {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let mapped: Vec<String> = types
+            .iter()
+            .filter(|t| **t != "object" || parts.is_empty())
+            .map(|t| match *t {
+                "string" => "string".to_string(),
+                "integer" | "number" => "number".to_string(),
+                "boolean" => "boolean".to_string(),
+                "null" => "null".to_string(),
+                "array" => {
+                    let item = obj.get("items").map(|i| ts_type(i, indent)).unwrap_or_else(|| "unknown".into());
+                    if item.contains(' ') { format!("({})[]", item) } else { format!("{}[]", item) }
+                }
+                _ => "Record<string, unknown>".to_string(),
+            })
+            .collect();
+        if !mapped.is_empty() {
+            parts.push(mapped.join(" | "));
+        }
+    }
+
+    if let Some(variants) = obj.get("oneOf").or_else(|| obj.get("anyOf")).and_then(Value::as_array) {
+        let inner = format!("{}    ", indent);
+        // Plain string enums inside a union are spread out one literal per line
+        let union: Vec<String> = variants
+            .iter()
+            .flat_map(|v| match v.get("enum").and_then(Value::as_array) {
+                Some(values) => values.iter().map(Value::to_string).collect(),
+                None => vec![ts_type(v, &inner)],
+            })
+            .collect();
+        let union = union.join(&format!("\n{}  | ", indent));
+        if parts.is_empty() {
+            return if union.contains('\n') {
+                format!("\n{}  | {}", indent, union)
+            } else {
+                union
+            };
+        }
+        parts.push(format!("(\n{}  | {}\n{})", indent, union, indent));
+    }
+
+    match parts.len() {
+        0 => "unknown".to_string(),
+        _ => parts.join(" & "),
+    }
+}
+
+fn ts_object(obj: &Map<String, Value>, indent: &str) -> String {
+    let required: Vec<&str> = obj
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|r| r.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let inner = format!("{}  ", indent);
+    let mut out = String::from("{\n");
+    if let Some(props) = obj.get("properties").and_then(Value::as_object) {
+        for (name, prop) in props {
+            write_doc(&mut out, prop, &inner);
+            let optional = if required.contains(&name.as_str()) { "" } else { "?" };
+            let _ = writeln!(out, "{}{}{}: {};", inner, name, optional, ts_type(prop, &inner));
+        }
+    }
+    out.push_str(indent);
+    out.push('}');
+    out
+}
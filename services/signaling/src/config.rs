@@ -0,0 +1,32 @@
+//! Helpers for reading settings from the environment.
+
+use std::collections::HashMap;
+
+/// Parse a per-app setting of the form `app=value;other=value` from an
+/// environment variable. Apps and values are trimmed and entries without an
+/// app are skipped; an empty value is kept, so each setting decides what
+/// `app=` means. Unset variables give an empty map.
+pub fn parse_app_map(var: &str) -> HashMap<String, String> {
+    std::env::var(var).map(|s| app_map(&s)).unwrap_or_default()
+}
+
+fn app_map(s: &str) -> HashMap<String, String> {
+    s.split(';')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(app, value)| (app.trim().to_string(), value.trim().to_string()))
+        .filter(|(app, _)| !app.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_app_entries() {
+        let map = app_map(" acme = https://a.example,https://b.example ; ;other=;=orphan;broken");
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["acme"], "https://a.example,https://b.example");
+        assert_eq!(map["other"], "");
+    }
+}
@@ -1,16 +1,39 @@
 use axum::{
-    extract::{ws::{Message, WebSocket}, Path, Query, State, WebSocketUpgrade},
-    http::{StatusCode, HeaderMap},
-    response::{IntoResponse, Json},
+    extract::{ws::{Message, WebSocket}, ConnectInfo, Path, Query, State, WebSocketUpgrade},
+    http::{header, StatusCode, HeaderMap},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Json, Response},
 };
 use axum_extra::{headers::{authorization::Bearer, Authorization}, TypedHeader};
 use futures::{SinkExt, StreamExt};
-use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
-use tokio::sync::broadcast;
+use dashmap::mapref::entry::Entry;
+use std::{collections::{BTreeMap, HashMap}, net::{IpAddr, SocketAddr}, sync::Arc, time::{Duration, Instant}};
+use tokio::sync::{broadcast, mpsc};
 use tracing::{info, warn};
 
-use crate::state::{AppState, RoomKey, RoomState, generate_unique_slug, generate_peer_id};
-use crate::types::{AdminRoomsResponse, CreateRoomResponse, RoomInfo, SignalMessage, SignalPayload};
+use crate::bots::{BotSession, DEFAULT_POLL_WAIT, MAX_POLL_WAIT, MAX_STAGE_BOTS};
+use crate::chat::MAX_CHAT_LENGTH;
+use crate::codec::{self, Encoding, Frame, JSON_SUBPROTOCOL, MSGPACK_SUBPROTOCOL};
+use crate::error::{ApiError, ErrorCode};
+use crate::keys;
+use crate::origin::{request_host, request_origin};
+use crate::peer::{PeerState, PeerUpdate};
+use crate::recording::Recorder;
+use crate::schedule::{Schedule, ScheduleAction};
+use crate::secret::{hash_password, secret_matches, verify_password};
+use crate::sfu::{self, Sfu, SfuRoom, SFU_PEER_ID};
+use crate::stage::{self, Stage, StageRole, MAX_FANOUT_LISTENERS, MAX_STAGE_SPEAKERS};
+use crate::protocol::{
+    negotiate, Capability, Negotiated, NegotiationRole, Topology, HANDSHAKE_TIMEOUT, PROTOCOL_VERSION_MAX,
+    PROTOCOL_VERSION_MIN,
+};
+use crate::state::{AppState, RoomKey, RoomState, SERVER_VERSION, generate_unique_slug, generate_peer_id, now_millis};
+use crate::storage::{logged, utc_day, Ban, DailyUsage};
+use crate::types::{
+    AdminAppsResponse, AdminBansResponse, AdminLogsResponse, AdminRoomsResponse, AdminUsageResponse,
+    AdminWebhooksResponse, BanRequest, BotEventsResponse, BotJoinResponse, CheckRoomResponse, CreateRoomResponse,
+    HealthResponse, ReadinessResponse, RoomInfo, SignalMessage, SignalPayload, WebhookRetryResponse,
+};
+use crate::webhooks::{RoomEndReason, WebhookEvent, WebhookEventType};
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
 const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
@@ -20,12 +43,12 @@ fn get_app_id(params: &HashMap<String, String>) -> String {
 }
 
 /// Validate room ID format: 4-32 alphanumeric characters or hyphens
-fn validate_room_id(room_id: &str) -> Result<(), &'static str> {
+fn validate_room_id(room_id: &str) -> Result<(), ErrorCode> {
     if room_id.len() < 4 || room_id.len() > 32 {
-        return Err("invalid_room_id_length");
+        return Err(ErrorCode::InvalidRoomIdLength);
     }
     if !room_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
-        return Err("invalid_room_id_chars");
+        return Err(ErrorCode::InvalidRoomIdChars);
     }
     Ok(())
 }
@@ -41,102 +64,212 @@ fn validate_api_key(
     };
 
     // Check header (for HTTP)
-    if let Some(headers) = headers {
-        if let Some(val) = headers.get("x-api-key") {
-            if let Ok(val_str) = val.to_str() {
-                if val_str == server_key {
-                    return true;
-                }
-            }
-        }
+    if let Some(headers) = headers
+        && let Some(val) = headers.get("x-api-key")
+        && let Ok(val_str) = val.to_str()
+        && secret_matches(val_str, Some(server_key))
+    {
+        return true;
     }
 
     // Check query param (for WebSocket)
-    if let Some(q_key) = query_key {
-        if q_key == server_key {
-            return true;
-        }
+    if let Some(q_key) = query_key
+        && secret_matches(q_key, Some(server_key))
+    {
+        return true;
+    }
+
+    false
+}
+
+/// Enforce the origin allowlist for an app, logging and counting rejections
+fn check_origin(state: &AppState, headers: &HeaderMap, app_id: &str, route: &'static str) -> bool {
+    let origin = request_origin(headers);
+    if state.origins.is_allowed(app_id, origin, request_host(headers)) {
+        return true;
+    }
+
+    state.origin_rejections.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    warn!(
+        event = "origin_rejected",
+        route = route,
+        app_id = app_id,
+        origin = origin.unwrap_or_default(),
+        "Request from disallowed origin"
+    );
+    false
+}
+
+/// Turn away addresses the admin has banned, logging each attempt
+fn check_ban(state: &AppState, addr: &SocketAddr, route: &'static str) -> bool {
+    let ip = addr.ip().to_string();
+    if !state.bans.contains_key(&ip) {
+        return true;
     }
 
+    warn!(event = "banned_rejected", route = route, ip = ip, "Request from banned address");
     false
 }
 
+/// Write a reserved room's settings through to storage
+fn persist_room(state: &AppState, key: &RoomKey, room: &RoomState) {
+    if let Some(record) = room.record(key) {
+        state.room_writes.save(record);
+    }
+}
+
 pub async fn create_room(
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
     // Check API Key
     if !validate_api_key(&state.api_key, Some(&headers), None) {
-        return (StatusCode::UNAUTHORIZED, "Invalid API Key").into_response();
+        return ErrorCode::InvalidApiKey.into_response();
     }
 
     let app_id = get_app_id(&params);
 
+    if !check_origin(&state, &headers, &app_id, "create_room") {
+        return ErrorCode::OriginNotAllowed.into_response();
+    }
+    if !check_ban(&state, &addr, "create_room") {
+        return ErrorCode::Banned.into_response();
+    }
+
+    // Refuse new rooms while shutting down
+    if state.draining.load(std::sync::atomic::Ordering::Relaxed) {
+        return ErrorCode::ServerDraining.into_response();
+    }
+
     // Check global room limit
     if state.rooms.len() >= state.max_global_rooms {
-        return (
-            StatusCode::TOO_MANY_REQUESTS,
-            Json(serde_json::json!({
-                "error": "max_rooms_reached",
-                "message": "Maximum number of global rooms reached"
-            })),
-        )
-            .into_response();
+        return ErrorCode::MaxRoomsReached.into_response();
     }
 
-    // Generate unique slug with collision check
-    let slug = match generate_unique_slug(&state.rooms, &app_id) {
-        Some(s) => s,
-        None => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({
-                    "error": "slug_generation_failed",
-                    "message": "Failed to generate unique room ID"
-                })),
-            )
-                .into_response();
-    }
+    // Use the requested vanity ID, or generate unique slug with collision check
+    let slug = match params.get("room") {
+        Some(room) => {
+            if let Err(code) = validate_room_id(room) {
+                return code.into_response();
+            }
+            room.clone()
+        }
+        None => match generate_unique_slug(&state.rooms, &app_id) {
+            Some(s) => s,
+            None => return ErrorCode::SlugGenerationFailed.into_response(),
+        },
     };
+    let persistent = params.get("persistent").is_some_and(|s| s == "true" || s == "1");
 
     // Parse optional max_peers from query params
     let max_peers = params.get("max_peers")
         .and_then(|s| s.parse::<usize>().ok());
 
+    // Mesh by default; SFU rooms forward audio through the server. Without
+    // an explicit topology the room switches to the SFU when it outgrows the mesh.
+    // Stage rooms use the SFU when there is one and fan out otherwise.
+    let is_stage = params.get("stage").is_some_and(|s| s == "true" || s == "1");
+    let requested = params.get("topology").map(String::as_str);
+    let topology = match (requested, is_stage) {
+        (None | Some("mesh"), false) => Topology::Mesh,
+        (Some("sfu"), _) => Topology::Sfu,
+        (None, true) if state.sfu.is_some() => Topology::Sfu,
+        (None | Some("fanout"), true) => Topology::Fanout,
+        (Some("mesh"), true) => {
+            return ApiError::with_message(ErrorCode::InvalidTopology, "Stage rooms use sfu or fanout").into_response();
+        }
+        _ => return ErrorCode::InvalidTopology.into_response(),
+    };
+    if topology == Topology::Sfu && state.sfu.is_none() {
+        return ErrorCode::SfuUnavailable.into_response();
+    }
+
+    // Listeners cost the SFU as much as anyone; fanned out they cost every speaker
+    let stage = is_stage.then(|| {
+        let max_speakers = params
+            .get("max_speakers")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(MAX_STAGE_SPEAKERS)
+            .clamp(1, MAX_STAGE_SPEAKERS);
+        let listener_limit = match topology {
+            Topology::Sfu => state.max_sfu_peers_per_room.saturating_sub(max_speakers),
+            _ => MAX_FANOUT_LISTENERS,
+        };
+        let max_listeners = params
+            .get("max_listeners")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(listener_limit)
+            .min(listener_limit);
+        Stage { max_speakers, max_listeners }
+    });
+
     // Parse optional password from query params
     let password = params.get("password").cloned();
     
     // Validate password format if provided
     if let Some(ref pwd) = password {
         if pwd.len() < 4 || pwd.len() > 12 {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({
-                    "error": "invalid_password",
-                    "message": "Password must be 4-12 characters"
-                })),
+            return ApiError::with_message(
+                ErrorCode::InvalidPassword,
+                "Password must be 4-12 characters",
             )
-                .into_response();
+            .into_response();
         }
         if !pwd.chars().all(|c| c.is_ascii_alphanumeric()) {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({
-                    "error": "invalid_password",
-                    "message": "Password must contain only letters and numbers"
-                })),
+            return ApiError::with_message(
+                ErrorCode::InvalidPassword,
+                "Password must contain only letters and numbers",
             )
-                .into_response();
+            .into_response();
         }
     }
 
+    // Scheduled rooms refuse joins until `starts_at` and close at `ends_at`
+    let now = now_millis();
+    let time_param = |name: &str| params.get(name).map(|s| s.parse::<u64>()).transpose();
+    let (Ok(starts_at), Ok(ends_at)) = (time_param("starts_at"), time_param("ends_at")) else {
+        return ApiError::with_message(ErrorCode::InvalidSchedule, "starts_at and ends_at are Unix milliseconds")
+            .into_response();
+    };
+    let schedule = Schedule::new(starts_at.unwrap_or(now), ends_at);
+    if ends_at.is_some_and(|end| end <= schedule.opens_at.max(now)) {
+        return ErrorCode::InvalidSchedule.into_response();
+    }
+    let closes_at = schedule.closes_at(state.schedule.max_duration(&app_id));
+
     // Pre-create the room with capacity and password
     let key = RoomKey {
         app_id: app_id.clone(),
         room_id: slug.clone(),
     };
-    state.rooms.insert(key, RoomState::with_capacity(max_peers.unwrap_or(state.max_peers_per_room), password.clone()));
+    let sfu_capacity = max_peers.unwrap_or(state.max_sfu_peers_per_room).min(state.max_sfu_peers_per_room);
+    let password_hash = password.as_deref().map(hash_password);
+    let mut room_state = match (&state.sfu, stage) {
+        (sfu, Some(stage)) => RoomState::stage(
+            stage,
+            password_hash,
+            sfu.as_ref().filter(|_| topology == Topology::Sfu),
+        ),
+        (Some(sfu), None) if topology == Topology::Sfu => RoomState::with_sfu(sfu_capacity, password_hash, sfu),
+        (Some(_), None) if requested.is_none() && state.sfu_upgrade_at > 0 => {
+            RoomState::adaptive(sfu_capacity, password_hash)
+        }
+        _ => RoomState::with_capacity(max_peers.unwrap_or(state.max_peers_per_room), password_hash),
+    };
+    room_state.schedule = schedule;
+    let host_key = persistent.then(|| room_state.reserve());
+    // IDs are unique per app; a vanity ID may already be taken
+    match state.rooms.entry(key) {
+        Entry::Occupied(_) => return ErrorCode::RoomIdTaken.into_response(),
+        Entry::Vacant(entry) => {
+            let room = entry.insert(room_state);
+            persist_room(&state, room.key(), &room);
+            state.webhooks.emit(WebhookEvent::new(WebhookEventType::RoomCreated, room.key()));
+        }
+    }
+    logged("record_room_created", state.storage.record_room_created(&app_id, now_millis()));
 
     // Increment metrics counter
     state.rooms_created_today.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -146,31 +279,99 @@ pub async fn create_room(
         room_id = slug,
         app_id = app_id,
         max_peers = max_peers,
+        topology = ?topology,
+        stage = stage.is_some(),
         has_password = password.is_some(),
+        reserved = persistent,
+        opens_at = starts_at,
+        closes_at = closes_at,
         total_rooms = state.rooms.len(),
         "Room created"
     );
-    Json(CreateRoomResponse { room: slug, password }).into_response()
+    Json(CreateRoomResponse {
+        room: slug,
+        topology,
+        password,
+        host_key,
+        opens_at: starts_at.filter(|&start| start > now),
+        closes_at,
+    })
+    .into_response()
+}
+
+/// Release a reserved room. It closes now if empty, otherwise once its last
+/// peer leaves.
+pub async fn delete_room(
+    Path(room): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if !validate_api_key(&state.api_key, Some(&headers), None) {
+        return ErrorCode::InvalidApiKey.into_response();
+    }
+    if let Err(code) = validate_room_id(&room) {
+        return code.into_response();
+    }
+
+    let app_id = get_app_id(&params);
+    if !check_origin(&state, &headers, &app_id, "delete_room") {
+        return ErrorCode::OriginNotAllowed.into_response();
+    }
+
+    let key = RoomKey {
+        app_id,
+        room_id: room,
+    };
+    let empty = {
+        let Some(mut room_ref) = state.rooms.get_mut(&key) else {
+            return ErrorCode::RoomNotFound.into_response();
+        };
+        if !room_ref.reserved {
+            return ApiError::with_message(ErrorCode::NotPermitted, "Only reserved rooms can be deleted").into_response();
+        }
+        let host_key = headers.get("x-host-key").and_then(|v| v.to_str().ok());
+        if !host_key.is_some_and(|provided| secret_matches(provided, room_ref.host_key.as_deref())) {
+            return ApiError::with_message(ErrorCode::Unauthorized, "Invalid host key").into_response();
+        }
+        room_ref.reserved = false;
+        room_ref.peers.is_empty() && room_ref.waitlist.is_empty()
+    };
+    state.room_writes.delete(&key.app_id, &key.room_id);
+
+    if empty && let Some((_, room)) = state.rooms.remove(&key) {
+        state.webhooks.emit(WebhookEvent::ended(&key, RoomEndReason::Deleted));
+        if let Some(sfu) = room.sfu {
+            sfu.close().await;
+        }
+    }
+
+    info!(
+        event = "room_released",
+        room_id = key.room_id,
+        app_id = key.app_id,
+        closed = empty,
+        "Reserved room deleted"
+    );
+    StatusCode::NO_CONTENT.into_response()
 }
 
 pub async fn check_room(
     Path(room): Path<String>,
     Query(params): Query<HashMap<String, String>>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     // Validate room ID format
     if let Err(code) = validate_room_id(&room) {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({
-                "error": code,
-                "message": "Invalid room ID format"
-            })),
-        )
-            .into_response();
+        return code.into_response();
     }
     
     let app_id = get_app_id(&params);
+    if !check_origin(&state, &headers, &app_id, "check_room") {
+        return ErrorCode::OriginNotAllowed.into_response();
+    }
+
     let key = RoomKey {
         app_id,
         room_id: room,
@@ -180,27 +381,74 @@ pub async fn check_room(
         Some(room_state) => {
             let peer_count = room_state.peers.len();
             let max_peers = room_state.max_peers;
-            let is_full = peer_count >= max_peers;
-            let password_required = room_state.password.is_some();
-            Json(serde_json::json!({
-                "exists": true,
-                "peers": peer_count,
-                "capacity": max_peers,
-                "full": is_full,
-                "password_required": password_required
-            }))
+            let is_full = room_state.is_full();
+            let password_required = room_state.password_hash.is_some();
+            let now = now_millis();
+            let open = room_state.schedule.is_open(now);
+            Json(CheckRoomResponse {
+                exists: true,
+                peers: peer_count,
+                capacity: max_peers,
+                full: is_full,
+                password_required,
+                topology: room_state.topology,
+                reserved: room_state.reserved,
+                open,
+                opens_at: (!open).then_some(room_state.schedule.opens_at),
+                closes_at: room_state.schedule.closes_at(state.schedule.max_duration(&key.app_id)),
+                max_speakers: room_state.stage.map(|s| s.max_speakers),
+                max_listeners: room_state.stage.map(|s| s.max_listeners),
+            })
             .into_response()
         }
-        None => StatusCode::NOT_FOUND.into_response(),
+        None => ErrorCode::RoomNotFound.into_response(),
     }
 }
 
+/// Liveness probe: the process is up and serving requests
+pub async fn healthz(State(state): State<AppState>) -> impl IntoResponse {
+    Json(HealthResponse {
+        status: "ok",
+        version: SERVER_VERSION,
+        uptime_seconds: state.start_time.elapsed().as_secs(),
+    })
+}
+
+/// Readiness probe: whether the server currently accepts new rooms and can
+/// reach its storage
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let draining = state.draining.load(std::sync::atomic::Ordering::Relaxed);
+    let active_rooms = state.rooms.len();
+    let storage_ok = logged("ping", state.storage.ping()).is_some();
+    let accepting_rooms = !draining && storage_ok && active_rooms < state.max_global_rooms;
+
+    let status = if accepting_rooms {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(ReadinessResponse {
+            status: if accepting_rooms { "ready" } else { "not_ready" },
+            version: SERVER_VERSION,
+            uptime_seconds: state.start_time.elapsed().as_secs(),
+            accepting_rooms,
+            draining,
+            storage_ok,
+            active_rooms,
+            max_rooms: state.max_global_rooms,
+        }),
+    )
+}
+
 pub async fn admin_rooms(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    if auth.token() != state.admin_token {
-        return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
     }
 
     let rooms: Vec<RoomInfo> = state
@@ -210,7 +458,9 @@ pub async fn admin_rooms(
             id: entry.key().room_id.clone(),
             app_id: entry.key().app_id.clone(),
             peers: entry.value().peers.len(),
-            capacity: state.max_peers_per_room,
+            capacity: entry.value().max_peers,
+            topology: entry.value().topology,
+            reserved: entry.value().reserved,
         })
         .collect();
 
@@ -226,8 +476,8 @@ pub async fn admin_metrics(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    if auth.token() != state.admin_token {
-        return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
     }
 
     // Count total active connections across all rooms
@@ -238,23 +488,170 @@ pub async fn admin_metrics(
         active_connections,
         rooms_created_today: state.rooms_created_today.load(std::sync::atomic::Ordering::Relaxed),
         connections_today: state.connections_today.load(std::sync::atomic::Ordering::Relaxed),
+        origin_rejections: state.origin_rejections.load(std::sync::atomic::Ordering::Relaxed),
         uptime_seconds: state.start_time.elapsed().as_secs(),
     })
     .into_response()
 }
 
+/// Banned addresses
+pub async fn admin_bans(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    let mut bans: Vec<Ban> = state.bans.iter().map(|entry| entry.value().clone()).collect();
+    bans.sort_by(|a, b| a.ip.cmp(&b.ip));
+    Json(AdminBansResponse { bans }).into_response()
+}
+
+/// Ban an address from creating or joining rooms. Peers already connected
+/// from it stay until they leave.
+pub async fn admin_ban(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(state): State<AppState>,
+    Json(request): Json<BanRequest>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    // Stored in canonical form so lookups by peer address match
+    let Ok(ip) = request.ip.trim().parse::<IpAddr>() else {
+        return ApiError::with_message(ErrorCode::InvalidMessage, "Invalid IP address").into_response();
+    };
+    let ban = Ban {
+        ip: ip.to_string(),
+        reason: request.reason.filter(|r| !r.is_empty()),
+        created_at: now_millis(),
+    };
+    logged("save_ban", state.storage.save_ban(&ban));
+    state.bans.insert(ban.ip.clone(), ban.clone());
+
+    info!(event = "ip_banned", ip = ban.ip, reason = ban.reason.as_deref().unwrap_or_default(), "Address banned");
+    (StatusCode::CREATED, Json(ban)).into_response()
+}
+
+pub async fn admin_unban(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Path(ip): Path<String>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    let ip = ip.parse::<IpAddr>().map(|ip| ip.to_string()).unwrap_or(ip);
+    let stored = logged("delete_ban", state.storage.delete_ban(&ip)).unwrap_or(false);
+    if state.bans.remove(&ip).is_none() && !stored {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    info!(event = "ip_unbanned", ip = ip, "Address unbanned");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Every app that has created a room, with when it was first and last seen
+pub async fn admin_apps(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    match logged("apps", state.storage.apps()) {
+        Some(apps) => Json(AdminAppsResponse { apps }).into_response(),
+        None => ApiError::with_message(ErrorCode::Unknown, "Storage unavailable").into_response(),
+    }
+}
+
+/// Daily usage for the last `days` days (default 30), today included
+pub async fn admin_usage(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    let days = params
+        .get("days")
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(30)
+        .clamp(1, 366);
+    let now = now_millis();
+    let since = utc_day(now.saturating_sub((days - 1) * 86_400_000));
+    let Some(mut stored) = logged("usage", state.storage.usage(&since)) else {
+        return ApiError::with_message(ErrorCode::Unknown, "Storage unavailable").into_response();
+    };
+
+    // Today's row is only written periodically; the live counters are current
+    let today = utc_day(now);
+    stored.retain(|d| d.day != today);
+    stored.push(DailyUsage { day: today, usage: state.usage() });
+    Json(AdminUsageResponse { days: stored }).into_response()
+}
+
+/// Webhook deliveries in flight and those that gave up
+pub async fn admin_webhooks(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    Json(AdminWebhooksResponse {
+        pending: state.webhooks.pending(),
+        dead_letters: state.webhooks.dead_letters(),
+    })
+    .into_response()
+}
+
+/// Give every dead letter a fresh round of delivery attempts
+pub async fn admin_retry_webhooks(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    let queued = state.webhooks.retry_dead_letters();
+    info!(event = "webhooks_retried", queued = queued, "Dead letters queued again");
+    (StatusCode::ACCEPTED, Json(WebhookRetryResponse { queued })).into_response()
+}
+
+pub async fn admin_clear_dead_letters(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
+    }
+
+    let dropped = state.webhooks.clear_dead_letters();
+    info!(event = "dead_letters_cleared", dropped = dropped, "Dead letters cleared");
+    StatusCode::NO_CONTENT.into_response()
+}
+
 pub async fn admin_logs(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    if auth.token() != state.admin_token {
-        return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+    if !state.is_admin(auth.token()) {
+        return ErrorCode::Unauthorized.into_response();
     }
 
     // If no log_dir configured, return empty
     let log_dir = match &state.log_dir {
         Some(dir) => dir,
-        None => return Json(serde_json::json!({ "logs": [] })).into_response(),
+        None => return Json(AdminLogsResponse { logs: Vec::new() }).into_response(),
     };
 
     // Read the most recent log file
@@ -275,18 +672,18 @@ pub async fn admin_logs(
             files.reverse();
             files
         }
-        Err(_) => return Json(serde_json::json!({ "logs": [] })).into_response(),
+        Err(_) => return Json(AdminLogsResponse { logs: Vec::new() }).into_response(),
     };
 
     let log_file = match log_files.first() {
         Some(f) => f.path(),
-        None => return Json(serde_json::json!({ "logs": [] })).into_response(),
+        None => return Json(AdminLogsResponse { logs: Vec::new() }).into_response(),
     };
 
     // Read last 500 lines
     let content = match std::fs::read_to_string(&log_file) {
         Ok(c) => c,
-        Err(_) => return Json(serde_json::json!({ "logs": [] })).into_response(),
+        Err(_) => return Json(AdminLogsResponse { logs: Vec::new() }).into_response(),
     };
 
     let lines: Vec<&str> = content.lines().collect();
@@ -298,21 +695,113 @@ pub async fn admin_logs(
         .map(|s| s.to_string())
         .collect();
 
-    Json(serde_json::json!({ "logs": recent_lines })).into_response()
+    Json(AdminLogsResponse { logs: recent_lines }).into_response()
 }
 
 
-/// Helper to send an error message over WebSocket and close
-async fn send_error_and_close(mut socket: WebSocket, code: &str, message: &str) {
-    let error_msg = SignalMessage {
+/// Close rooms whose time is up, warn rooms closing soon, remove empty
+/// rooms past their grace period and take idle bots out of their rooms. Run
+/// every second by the sweep `ServerBuilder::build` starts.
+pub async fn sweep_rooms(state: &AppState, reserved_ttl: Duration) {
+    // Bots whose room closed leave with it
+    let idle: Vec<Arc<BotSession>> = state
+        .bots
+        .iter()
+        .filter(|bot| bot.is_idle() || !state.rooms.contains_key(&bot.key))
+        .map(|bot| bot.value().clone())
+        .collect();
+    for bot in idle {
+        state.bots.remove(&bot.peer_id);
+        bot.leave();
+        info!(event = "bot_timeout", peer_id = bot.peer_id, room_id = bot.key.room_id, "Removing idle bot");
+        leave_room(state, &bot.key, &bot.peer_id).await;
+    }
+
+    let now = now_millis();
+    let mut closing = Vec::new();
+    let mut stale = Vec::new();
+
+    for mut entry in state.rooms.iter_mut() {
+        let closes_at = entry.schedule.closes_at(state.schedule.max_duration(&entry.key().app_id));
+        match entry.schedule.due(now, closes_at, &state.schedule) {
+            Some(ScheduleAction::Close) => closing.push((entry.key().clone(), entry.schedule.has_ended(now))),
+            Some(ScheduleAction::Warn { closes_at, seconds_left }) => {
+                let warning = SignalMessage {
+                    from: "server".to_string(),
+                    payload: SignalPayload::Closing { closes_at, seconds_left },
+                };
+                let _ = entry.tx.send(Frame::new(warning));
+            }
+            None if entry.is_stale(reserved_ttl, now) => stale.push(entry.key().clone()),
+            None => {}
+        }
+    }
+
+    // Everyone still in the room is told why before their session ends
+    for (key, ended) in closing {
+        let Some((_, room)) = state.rooms.remove(&key) else {
+            continue;
+        };
+        info!(
+            event = "room_closed",
+            room_id = key.room_id,
+            app_id = key.app_id,
+            peers = room.peers.len(),
+            "Room reached its closing time"
+        );
+        state.webhooks.emit(WebhookEvent::ended(&key, RoomEndReason::Closed));
+        let _ = room.tx.send(Frame::new(server_error(ErrorCode::RoomClosed.into())));
+        match room.record(&key) {
+            // Only the session ran out; the reservation waits, empty, for the next
+            Some(record) if !ended => {
+                for peer_id in room.peers.keys() {
+                    state.webhooks.emit(WebhookEvent::peer(WebhookEventType::PeerLeft, &key, peer_id));
+                }
+                state.bots.retain(|_, bot| {
+                    let stays = bot.key != key;
+                    if !stays {
+                        bot.leave();
+                    }
+                    stays
+                });
+                let reopened = RoomState::restore(&record, state.sfu.as_ref());
+                persist_room(state, &key, &reopened);
+                state.rooms.insert(key, reopened);
+            }
+            Some(_) => state.room_writes.delete(&key.app_id, &key.room_id),
+            None => {}
+        }
+        if let Some(sfu) = room.sfu {
+            tokio::spawn(async move { sfu.close().await });
+        }
+    }
+
+    for key in stale {
+        let Some((_, room)) = state.rooms.remove_if(&key, |_, r| r.is_stale(reserved_ttl, now)) else {
+            continue;
+        };
+        info!(event = "room_cleanup", room_id = %key.room_id, app_id = %key.app_id, "Removing empty stale room");
+        state.webhooks.emit(WebhookEvent::ended(&key, RoomEndReason::Idle));
+        if room.reserved {
+            state.room_writes.delete(&key.app_id, &key.room_id);
+        }
+        if let Some(sfu) = room.sfu {
+            tokio::spawn(async move { sfu.close().await });
+        }
+    }
+}
+
+fn server_error(error: ApiError) -> SignalMessage {
+    SignalMessage {
         from: "server".to_string(),
-        payload: SignalPayload::Error {
-            code: code.to_string(),
-            message: message.to_string(),
-        },
-    };
-    if let Ok(json) = serde_json::to_string(&error_msg) {
-        let _ = socket.send(Message::Text(json.into())).await;
+        payload: SignalPayload::Error(error),
+    }
+}
+
+/// Helper to send an error message over WebSocket and close
+async fn send_error_and_close(mut socket: WebSocket, error: ApiError, encoding: Encoding) {
+    if let Some(msg) = codec::encode(&server_error(error), encoding) {
+        let _ = socket.send(msg).await;
     }
     let _ = socket.close().await;
 }
@@ -322,20 +811,22 @@ pub async fn ws_handler(
     Path(room): Path<String>,
     Query(params): Query<HashMap<String, String>>,
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     // Validate room ID format
     if let Err(code) = validate_room_id(&room) {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({
-                "error": code,
-                "message": "Invalid room ID format"
-            })),
-        )
-            .into_response();
+        return code.into_response();
     }
     
     let app_id = get_app_id(&params);
+
+    // Browsers always send Origin on WebSocket upgrades, so this is the
+    // only thing stopping other sites from embedding the server
+    if !check_origin(&state, &headers, &app_id, "ws") {
+        return ErrorCode::OriginNotAllowed.into_response();
+    }
+
     let key = RoomKey {
         app_id: app_id.clone(),
         room_id: room.clone(),
@@ -344,111 +835,430 @@ pub async fn ws_handler(
     // Check if room exists
     let room_ref = match state.rooms.get(&key) {
         Some(r) => r,
-        None => return StatusCode::NOT_FOUND.into_response(),
+        None => return ErrorCode::RoomNotFound.into_response(),
     };
 
-    // Check password and capacity, but accept connection to send proper error
-    let password_error: Option<(&str, &str)> = if let Some(room_password) = &room_ref.password {
+    // Check schedule, password and capacity, but accept connection to send proper error
+    let not_open = !room_ref.schedule.is_open(now_millis());
+    let password_error: Option<ErrorCode> = if let Some(password_hash) = &room_ref.password_hash {
         let provided_password = params.get("password");
         match provided_password {
-            Some(pwd) if pwd == room_password => None,
-            Some(_) => Some(("invalid_password", "Incorrect password")),
-            None => Some(("password_required", "This room requires a password")),
+            Some(pwd) if verify_password(pwd, password_hash) => None,
+            Some(_) => Some(ErrorCode::InvalidPassword),
+            None => Some(ErrorCode::PasswordRequired),
         }
     } else {
         None
     };
 
-    // Check capacity
-    let room_max_peers = room_ref.max_peers;
-    let is_full = room_ref.peers.len() >= room_max_peers;
+    // The key handed out when the room was reserved makes its holder host
+    let claim_host = params.get("hostKey").map(|provided| secret_matches(provided, room_ref.host_key.as_deref()));
+
+    // Check capacity; nobody jumps the waitlist
+    let is_full = room_ref.is_full() || !room_ref.waitlist.is_empty();
     drop(room_ref);
+    let join = JoinOptions {
+        wait: state.max_waitlist > 0 && params.get("wait").is_some_and(|w| w == "true"),
+        claim_host: claim_host == Some(true),
+    };
+
+    // Clients may pick MessagePack up front via the WebSocket subprotocol;
+    // otherwise frames are JSON until binary is negotiated in Hello
+    let ws = ws.protocols([MSGPACK_SUBPROTOCOL, JSON_SUBPROTOCOL]);
+    let encoding = match ws.selected_protocol().and_then(|p| p.to_str().ok()) {
+        Some(MSGPACK_SUBPROTOCOL) => Encoding::MessagePack,
+        _ => Encoding::Json,
+    };
 
     // Accept WebSocket connection and send any errors over the connection
     // This allows the SDK to properly receive and handle these errors
-    if let Some((code, message)) = password_error {
-        return ws.on_upgrade(move |socket| send_error_and_close(socket, code, message)).into_response();
+    if !check_ban(&state, &addr, "ws") {
+        return ws.on_upgrade(move |socket| send_error_and_close(socket, ErrorCode::Banned.into(), encoding)).into_response();
+    }
+
+    if not_open {
+        return ws.on_upgrade(move |socket| send_error_and_close(socket, ErrorCode::RoomNotOpen.into(), encoding)).into_response();
+    }
+
+    if let Some(code) = password_error {
+        return ws.on_upgrade(move |socket| send_error_and_close(socket, code.into(), encoding)).into_response();
+    }
+
+    if claim_host == Some(false) {
+        let error = ApiError::with_message(ErrorCode::Unauthorized, "Invalid host key");
+        return ws.on_upgrade(move |socket| send_error_and_close(socket, error, encoding)).into_response();
     }
 
-    if is_full {
-        return ws.on_upgrade(move |socket| send_error_and_close(socket, "room_full", "Room is at maximum capacity")).into_response();
+    if is_full && !join.wait {
+        state.webhooks.emit(WebhookEvent::new(WebhookEventType::RoomFull, &key));
+        return ws.on_upgrade(move |socket| send_error_and_close(socket, ErrorCode::RoomFull.into(), encoding)).into_response();
     }
 
-    ws.on_upgrade(move |socket| handle_socket(socket, key, state))
+    ws.on_upgrade(move |socket| handle_socket(socket, key, state, encoding, join))
 }
 
-async fn handle_socket(socket: WebSocket, key: RoomKey, state: AppState) {
-    let peer_id = generate_peer_id();
+/// What a connecting client asked for in the WebSocket URL
+struct JoinOptions {
+    /// Queue for a slot if the room is full
+    wait: bool,
+    /// Presented the room's host key
+    claim_host: bool,
+}
 
-    // Register peer in room
-    {
-        let mut room_ref = match state.rooms.get_mut(&key) {
-            Some(r) => r,
-            None => {
-                warn!(event = "room_missing", room_id = key.room_id, app_id = key.app_id, "Room disappeared");
-                return;
+/// Wait for the client's Hello, negotiate protocol version and features, and
+/// build the peer's initial state. On failure returns the error to send
+/// before closing, or `None` if the client already went away.
+async fn perform_handshake(
+    socket: &mut WebSocket,
+    peer_id: &str,
+    encoding: Encoding,
+) -> Result<(Negotiated, PeerState), Option<ApiError>> {
+    let deadline = tokio::time::sleep(HANDSHAKE_TIMEOUT);
+    tokio::pin!(deadline);
+
+    loop {
+        let msg = tokio::select! {
+            _ = &mut deadline => {
+                return Err(Some(ErrorCode::HandshakeTimeout.into()));
             }
+            msg = socket.recv() => msg,
         };
 
-        // Double-check capacity (race condition protection)
-        if room_ref.peers.len() >= state.max_peers_per_room {
-            warn!(
-                event = "room_full_race",
-                room_id = key.room_id,
-                app_id = key.app_id,
-                peer_id = peer_id,
-                "Room full (race condition)"
-            );
-            return;
+        let msg = match msg {
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                return Err(None);
+            }
+            Some(Ok(msg)) => msg,
+        };
+
+        let signal = match codec::decode(&msg, encoding) {
+            Some(Ok(signal)) => signal,
+            Some(Err(_)) => {
+                return Err(Some(ErrorCode::HandshakeRequired.into()));
+            }
+            // Control frames may arrive before the first data frame
+            None => continue,
+        };
+
+        let SignalPayload::Hello { version, client, protocol, capabilities, state } = signal.payload else {
+            return Err(Some(ErrorCode::HandshakeRequired.into()));
+        };
+
+        info!(
+            event = "client_hello",
+            peer_id = peer_id,
+            client_version = %version,
+            client_name = %client,
+            protocol = protocol,
+            "Client connected"
+        );
+
+        let negotiated = negotiate(protocol, &capabilities).ok_or_else(|| {
+            Some(ApiError::with_message(
+                ErrorCode::UnsupportedVersion,
+                format!(
+                    "Protocol version {} is not supported (supported: {}-{})",
+                    protocol, PROTOCOL_VERSION_MIN, PROTOCOL_VERSION_MAX
+                ),
+            ))
+        })?;
+
+        let mut peer_state = PeerState::new(now_millis());
+        if let Some(update) = state {
+            peer_state.apply(update).map_err(Some)?;
         }
 
-        room_ref.peers.insert(peer_id.clone());
+        return Ok((negotiated, peer_state));
     }
+}
 
-    // Increment connections counter
-    state.connections_today.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+/// Hold a connection until the room has a slot for it. Without `wait` a full
+/// room turns the peer away; with it the peer queues, hearing its position
+/// until it is first in line and someone leaves. The peer stays queued until
+/// registration so nobody can take its slot in between.
+async fn wait_for_slot(
+    socket: &mut WebSocket,
+    key: &RoomKey,
+    state: &AppState,
+    peer_id: &str,
+    wait: bool,
+    encoding: Encoding,
+) -> Result<(), Option<ApiError>> {
+    let mut queued: Option<tokio::sync::watch::Receiver<usize>> = None;
+    let deadline = tokio::time::sleep(state.waitlist_timeout);
+    tokio::pin!(deadline);
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+    let mut last_position = 0;
 
-    info!(
-        event = "peer_joining",
-        peer_id = peer_id,
-        room_id = key.room_id,
-        app_id = key.app_id,
-        "Peer joining room"
-    );
+    loop {
+        {
+            let mut room_ref = state.rooms.get_mut(key).ok_or(None)?;
+            if room_ref.can_admit(peer_id) {
+                return Ok(());
+            }
+            if queued.is_none() {
+                if !wait {
+                    return Err(Some(ErrorCode::RoomFull.into()));
+                }
+                if room_ref.waitlist.len() >= state.max_waitlist {
+                    return Err(Some(ApiError::with_message(ErrorCode::RoomFull, "Room and its waitlist are full")));
+                }
+                queued = Some(room_ref.waitlist.push(peer_id));
+                // Restart the clock from when the peer joined the queue
+                deadline.as_mut().reset(tokio::time::Instant::now() + state.waitlist_timeout);
+                info!(
+                    event = "peer_waiting",
+                    peer_id = peer_id,
+                    room_id = key.room_id,
+                    app_id = key.app_id,
+                    waiting = room_ref.waitlist.len(),
+                    "Peer queued for a full room"
+                );
+            }
+        }
+        let Some(position_rx) = queued.as_mut() else {
+            return Err(None);
+        };
+
+        let position = *position_rx.borrow_and_update();
+        if position != last_position {
+            last_position = position;
+            let waiting = SignalMessage {
+                from: "server".to_string(),
+                payload: SignalPayload::Waiting { position },
+            };
+            if let Some(msg) = codec::encode(&waiting, encoding) {
+                let _ = socket.send(msg).await;
+            }
+        }
 
-    // Get broadcast channel
-    let tx = {
-        let room_ref = match state.rooms.get(&key) {
+        let outcome = tokio::select! {
+            changed = position_rx.changed() => changed.map_err(|_| None),
+            _ = &mut deadline => Err(Some(ErrorCode::WaitlistTimeout.into())),
+            _ = heartbeat.tick() => {
+                let ping = SignalMessage { from: "server".to_string(), payload: SignalPayload::Ping };
+                if let Some(msg) = codec::encode(&ping, encoding) {
+                    let _ = socket.send(msg).await;
+                }
+                Ok(())
+            }
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => Err(None),
+                // Pongs and anything else sent early are ignored until admission
+                Some(Ok(_)) => Ok(()),
+            },
+        };
+        if let Err(error) = outcome {
+            if let Some(mut room_ref) = state.rooms.get_mut(key) {
+                room_ref.waitlist.remove(peer_id);
+                // The slot this peer was woken for goes to whoever is next
+                room_ref.waitlist.wake_next();
+            }
+            return Err(error);
+        }
+    }
+}
+
+async fn handle_socket(mut socket: WebSocket, key: RoomKey, state: AppState, encoding: Encoding, join: JoinOptions) {
+    let peer_id = generate_peer_id();
+
+    // The peer isn't registered or announced until the handshake succeeds
+    let (mut negotiated, mut peer_state) = match perform_handshake(&mut socket, &peer_id, encoding).await {
+        Ok(n) => n,
+        Err(error) => {
+            warn!(
+                event = "handshake_failed",
+                peer_id = peer_id,
+                room_id = key.room_id,
+                app_id = key.app_id,
+                code = error.as_ref().map(|e| e.code.as_str()).unwrap_or("connection_closed"),
+                "Handshake failed"
+            );
+            if let Some(error) = error {
+                send_error_and_close(socket, error, encoding).await;
+            }
+            return;
+        }
+    };
+
+    if let Err(error) = wait_for_slot(&mut socket, &key, &state, &peer_id, join.wait, encoding).await {
+        if let Some(error) = error {
+            info!(
+                event = "peer_turned_away",
+                peer_id = peer_id,
+                room_id = key.room_id,
+                app_id = key.app_id,
+                code = error.code.as_str(),
+                "No slot for peer"
+            );
+            if error.code == ErrorCode::RoomFull {
+                state.webhooks.emit(WebhookEvent::peer(WebhookEventType::RoomFull, &key, &peer_id));
+            }
+            send_error_and_close(socket, error, encoding).await;
+        }
+        return;
+    }
+
+    // Choosing the MessagePack subprotocol implies the binary capability
+    if encoding == Encoding::MessagePack && !negotiated.has(Capability::Binary) {
+        negotiated.capabilities.push(Capability::Binary);
+    }
+    let session_encoding = if negotiated.has(Capability::Binary) {
+        Encoding::MessagePack
+    } else {
+        Encoding::Json
+    };
+
+    // Register peer in room, capturing who was already there for the roster.
+    // Subscribing, registering and announcing happen under one room lock so
+    // every other peer sees this one either in its roster or as a Join, never
+    // both, and negotiation roles always agree.
+    let (tx, rx, existing_peers, host, chat, key_epoch, topology, recording, floor, closes_at, sfu, sfu_joins) = {
+        let mut room_ref = match state.rooms.get_mut(&key) {
             Some(r) => r,
-            None => return,
+            None => {
+                warn!(event = "room_missing", room_id = key.room_id, app_id = key.app_id, "Room disappeared");
+                return;
+            }
         };
-        room_ref.tx.clone()
+
+        // Double-check capacity (race condition protection)
+        if !room_ref.can_admit(&peer_id) {
+            room_ref.waitlist.remove(&peer_id);
+            room_ref.waitlist.wake_next();
+            drop(room_ref);
+            warn!(
+                event = "room_full_race",
+                room_id = key.room_id,
+                app_id = key.app_id,
+                peer_id = peer_id,
+                "Room full (race condition)"
+            );
+            state.webhooks.emit(WebhookEvent::peer(WebhookEventType::RoomFull, &key, &peer_id));
+            send_error_and_close(socket, ErrorCode::RoomFull.into(), encoding).await;
+            return;
+        }
+
+        let existing: BTreeMap<String, PeerState> = room_ref
+            .peers
+            .iter()
+            .map(|(id, state)| (id.clone(), state.clone()))
+            .collect();
+        let rx = room_ref.tx.subscribe();
+        peer_state.stage_role = room_ref.joining_role();
+        // Push-to-talk rooms only let the floor holder talk
+        if room_ref.floor.single_speaker {
+            peer_state.muted = true;
+        }
+        room_ref.schedule.begin_session(now_millis());
+        room_ref.peers.insert(peer_id.clone(), peer_state.clone());
+        // A reserved room in use isn't idle, even if the server restarts
+        if existing.is_empty() {
+            persist_room(&state, &key, &room_ref);
+        }
+        room_ref.waitlist.remove(&peer_id);
+        // More than one slot may have freed up
+        room_ref.waitlist.wake_next();
+        // The key holder takes the host role back from whoever has it
+        let took_host = join.claim_host && room_ref.host.is_some();
+        if join.claim_host || room_ref.host.is_none() {
+            room_ref.host = Some(peer_id.clone());
+        }
+
+        let join_msg = SignalMessage {
+            from: peer_id.clone(),
+            payload: SignalPayload::Join {
+                peer_id: peer_id.clone(),
+                state: Some(peer_state.clone()),
+                // Everyone already here was first, so they make the offer
+                role: Some(NegotiationRole::Impolite),
+            },
+        };
+        let _ = room_ref.tx.send(Frame::new(join_msg));
+        if took_host {
+            let host_msg = SignalMessage {
+                from: "server".to_string(),
+                payload: SignalPayload::Host {
+                    peer_id: peer_id.clone(),
+                },
+            };
+            let _ = room_ref.tx.send(Frame::new(host_msg));
+        }
+
+        // Outgrowing the mesh moves everyone onto the forwarder, not just us
+        let sfu_joins = match adapt_topology(&state, &key, &mut room_ref) {
+            Some(_) => room_ref.join_order(),
+            None if room_ref.sfu.is_some() => vec![peer_id.clone()],
+            None => Vec::new(),
+        };
+        sync_speaking(&room_ref);
+
+        (
+            room_ref.tx.clone(),
+            rx,
+            existing,
+            room_ref.host.clone(),
+            room_ref.chat.snapshot(),
+            room_ref.key_epoch,
+            room_ref.topology,
+            room_ref.recording.as_ref().map(|r| r.id.clone()),
+            room_ref.floor.clone(),
+            room_ref.schedule.closes_at(state.schedule.max_duration(&key.app_id)),
+            room_ref.sfu.clone(),
+            sfu_joins,
+        )
     };
-    let rx = tx.subscribe();
+
+    // Increment connections counter
+    state.connections_today.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    info!(
+        event = "peer_joining",
+        peer_id = peer_id,
+        room_id = key.room_id,
+        app_id = key.app_id,
+        "Peer joining room"
+    );
+    state.webhooks.emit(WebhookEvent::peer(WebhookEventType::PeerJoined, &key, &peer_id));
 
     let (mut ws_tx, ws_rx) = socket.split();
 
-    // Send Welcome message with protocol version and assigned peer_id
+    // Send Welcome message with negotiated protocol and assigned peer_id
     let welcome = SignalMessage {
         from: "server".to_string(),
         payload: SignalPayload::Welcome {
-            version: "0.4.1".to_string(),
+            version: SERVER_VERSION.to_string(),
             peer_id: peer_id.clone(),
+            protocol: negotiated.protocol,
+            peers: negotiated
+                .has(Capability::Roster)
+                .then(|| existing_peers.keys().cloned().collect()),
+            roles: negotiated.has(Capability::Roster).then(|| {
+                // Bots have no audio, so there's nothing to negotiate with them
+                existing_peers
+                    .iter()
+                    .filter(|(_, p)| !p.bot)
+                    .map(|(id, _)| (id.clone(), NegotiationRole::Polite))
+                    .collect()
+            }),
+            states: negotiated.has(Capability::Roster).then_some(existing_peers),
+            capabilities: negotiated.capabilities,
+            host,
+            chat,
+            key_epoch,
+            topology,
+            recording,
+            stage_role: peer_state.stage_role,
+            floor,
+            closes_at,
         },
     };
-    if let Ok(json) = serde_json::to_string(&welcome) {
-        let _ = ws_tx.send(Message::Text(json.into())).await;
+    // Welcome goes out in the handshake encoding; everything after it uses
+    // the negotiated one
+    if let Some(msg) = codec::encode(&welcome, encoding) {
+        let _ = ws_tx.send(msg).await;
     }
 
-    // Announce join to room
-    let join_msg = SignalMessage {
-        from: peer_id.clone(),
-        payload: SignalPayload::Join {
-            peer_id: peer_id.clone(),
-        },
-    };
-    let _ = tx.send(join_msg);
-
     // Track heartbeat state
     let last_pong = Arc::new(tokio::sync::Mutex::new(Instant::now()));
     
@@ -456,21 +1266,38 @@ async fn handle_socket(socket: WebSocket, key: RoomKey, state: AppState) {
     let (timeout_tx, timeout_rx) = tokio::sync::oneshot::channel::<()>();
     let timeout_tx = Arc::new(tokio::sync::Mutex::new(Some(timeout_tx)));
 
+    // Messages for this peer only (e.g. errors about its own frames)
+    let (direct_tx, direct_rx) = mpsc::channel::<SignalMessage>(16);
+
     // Spawn forwarding task (Heartbeats + Broadcasts)
     let forward_job = tokio::spawn(run_forward_task(
         ws_tx,
         rx,
+        direct_rx,
         peer_id.clone(),
         last_pong.clone(),
         timeout_tx,
+        session_encoding,
     ));
 
+    // SFU peers get their one connection from the server as soon as they join
+    if let Some(sfu) = sfu {
+        connect_to_sfu(&sfu, sfu_joins);
+    }
+
     // Run receive loop (Incoming WebSocket messages)
+    let session = PeerSession {
+        key: key.clone(),
+        peer_id: peer_id.clone(),
+        encoding: session_encoding,
+    };
     let receive_job = run_receive_loop(
         ws_rx,
         tx.clone(),
+        direct_tx,
         timeout_rx, 
-        peer_id.clone(),
+        &state,
+        &session,
         last_pong,
     );
     
@@ -479,28 +1306,118 @@ async fn handle_socket(socket: WebSocket, key: RoomKey, state: AppState) {
 
     // Cleanup
     forward_job.abort();
+    leave_room(&state, &key, &peer_id).await;
+}
 
-    // Remove peer from room
-    if let Some(mut room_ref) = state.rooms.get_mut(&key) {
-        room_ref.peers.remove(&peer_id);
+/// Take a peer out of its room and tell everyone left: hand on the host
+/// role, start a new key epoch, release the floor and close the room if it
+/// was the last one out. Used for WebSocket peers and bots alike.
+async fn leave_room(state: &AppState, key: &RoomKey, peer_id: &str) {
+    // The room may have changed topology since this peer joined
+    let sfu = state.rooms.get(key).and_then(|r| r.sfu.clone());
+    if let Some(sfu) = sfu {
+        sfu.remove_peer(peer_id).await;
     }
 
+    // Remove peer from room, handing the host role on if they held it. The
+    // leaver still has the room key, so whoever remains must rekey; bots and
+    // stream sources never get it.
+    let removed = state
+        .rooms
+        .get_mut(key)
+        .map(|mut room_ref| {
+            let peer = room_ref.peers.remove(peer_id)?;
+            // A stream source held the room on the SFU
+            if peer.ingest {
+                room_ref.sfu_holds = room_ref.sfu_holds.saturating_sub(1);
+            }
+            room_ref.waitlist.wake_next();
+            if let Some(recorder) = &room_ref.recording {
+                recorder.forget(peer_id);
+            }
+            let mut new_host = None;
+            if room_ref.host.as_deref() == Some(peer_id) {
+                // Stream sources and bots can't moderate
+                room_ref.host = room_ref
+                    .peers
+                    .iter()
+                    .filter(|(_, p)| !p.ingest && !p.bot)
+                    .min_by(|(a_id, a), (b_id, b)| a.joined_at.cmp(&b.joined_at).then(a_id.cmp(b_id)))
+                    .map(|(id, _)| id.clone());
+                new_host = room_ref.host.clone();
+            }
+            if room_ref.peers.is_empty() {
+                room_ref.idle_since = Instant::now();
+                room_ref.schedule.end_session();
+                persist_room(state, key, &room_ref);
+            }
+            let mut rekey = None;
+            if !room_ref.peers.is_empty() && !peer.bot && !peer.ingest {
+                room_ref.key_epoch += 1;
+                rekey = Some(SignalPayload::KeyEpoch {
+                    epoch: room_ref.key_epoch,
+                    distributor: room_ref.host.clone(),
+                });
+            }
+            let floor = room_ref
+                .floor
+                .lower(peer_id)
+                .then(|| SignalPayload::Floor(room_ref.floor.clone()));
+            Some((room_ref.tx.clone(), new_host, rekey, floor))
+        });
+    let (tx, new_host, rekey, floor) = match removed {
+        Some(Some(removed)) => removed,
+        // Already gone, e.g. a source deleted as its connection dropped
+        Some(None) => return,
+        // The room already closed; there is nobody left to tell
+        None => {
+            state.webhooks.emit(WebhookEvent::peer(WebhookEventType::PeerLeft, key, peer_id));
+            return;
+        }
+    };
+    state.webhooks.emit(WebhookEvent::peer(WebhookEventType::PeerLeft, key, peer_id));
+
     // Announce leave
     let leave_msg = SignalMessage {
-        from: peer_id.clone(),
+        from: peer_id.to_string(),
         payload: SignalPayload::Leave {
-            peer_id: peer_id.clone(),
+            peer_id: peer_id.to_string(),
         },
     };
-    let _ = tx.send(leave_msg);
+    let _ = tx.send(Frame::new(leave_msg));
+
+    if let Some(host) = new_host {
+        let host_msg = SignalMessage {
+            from: "server".to_string(),
+            payload: SignalPayload::Host { peer_id: host },
+        };
+        let _ = tx.send(Frame::new(host_msg));
+    }
+
+    for payload in [rekey, floor].into_iter().flatten() {
+        let update_msg = SignalMessage {
+            from: "server".to_string(),
+            payload,
+        };
+        let _ = tx.send(Frame::new(update_msg));
+    }
+
+    // Shrinking back into mesh range hands the audio back to the peers
+    let retired = state
+        .rooms
+        .get_mut(key)
+        .and_then(|mut room_ref| adapt_topology(state, key, &mut room_ref));
+    if let Some(sfu) = retired {
+        sfu.close().await;
+    }
 
     tokio::time::sleep(Duration::from_millis(50)).await;
 
-    // Cleanup empty room
+    // Cleanup empty room; reserved rooms wait to be deleted or go idle
     let should_remove = state
         .rooms
-        .get(&key)
-        .map(|r| r.peers.is_empty())
+        .get(key)
+        .map(|r| r.peers.is_empty() && r.waitlist.is_empty() && !r.reserved)
         .unwrap_or(false);
 
     if should_remove {
@@ -510,11 +1427,16 @@ async fn handle_socket(socket: WebSocket, key: RoomKey, state: AppState) {
             app_id = key.app_id,
             "Room removed (no peers left)"
         );
-        state.rooms.remove(&key);
+        if let Some((_, room)) = state.rooms.remove(key) {
+            state.webhooks.emit(WebhookEvent::ended(key, RoomEndReason::Empty));
+            if let Some(sfu) = room.sfu {
+                sfu.close().await;
+            }
+        }
     } else {
         let remaining = state
             .rooms
-            .get(&key)
+            .get(key)
             .map(|r| r.peers.len())
             .unwrap_or(0);
         info!(
@@ -532,14 +1454,23 @@ async fn handle_socket(socket: WebSocket, key: RoomKey, state: AppState) {
 // Helper Functions for handle_socket
 // ----------------------
 
+/// Identity of one connected peer, shared by its receive loop
+struct PeerSession {
+    key: RoomKey,
+    peer_id: String,
+    encoding: Encoding,
+}
+
 use futures::stream::{SplitSink, SplitStream};
 
 async fn run_forward_task(
     mut ws_tx: SplitSink<WebSocket, Message>,
-    mut rx: broadcast::Receiver<SignalMessage>,
+    mut rx: broadcast::Receiver<Arc<Frame>>,
+    mut direct_rx: mpsc::Receiver<SignalMessage>,
     peer_id: String,
     last_pong: Arc<tokio::sync::Mutex<Instant>>,
     timeout_tx: Arc<tokio::sync::Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
+    encoding: Encoding,
 ) {
     let mut ping_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
 
@@ -551,10 +1482,10 @@ async fn run_forward_task(
                     from: "server".to_string(),
                     payload: SignalPayload::Ping,
                 };
-                if let Ok(json) = serde_json::to_string(&ping) {
-                    if ws_tx.send(Message::Text(json.into())).await.is_err() {
-                        break;
-                    }
+                if let Some(msg) = codec::encode(&ping, encoding)
+                    && ws_tx.send(msg).await.is_err()
+                {
+                    break;
                 }
 
                 // Check timeout
@@ -571,28 +1502,35 @@ async fn run_forward_task(
                     break;
                 }
             }
+            Some(msg) = direct_rx.recv() => {
+                if let Some(out) = codec::encode(&msg, encoding)
+                    && ws_tx.send(out).await.is_err()
+                {
+                    break;
+                }
+            }
             msg = rx.recv() => {
                 match msg {
-                    Ok(msg) => {
-                        // Don't send messages back to sender
-                        if msg.from == peer_id { continue; }
-
-                        // Check targeting
-                        match &msg.payload {
-                            SignalPayload::Offer { to, .. } | 
-                            SignalPayload::Answer { to, .. } | 
-                            SignalPayload::Ice { to, .. } => {
-                                if to != &peer_id { continue; }
-                            }
-                            SignalPayload::Ping | SignalPayload::Pong => continue,
-                            SignalPayload::Mute { .. } => {} // Broadcast to everyone else
-                            _ => {}
+                    Ok(frame) => {
+                        let msg = &frame.msg;
+                        if !msg.reaches(&peer_id) { continue; }
+
+                        // Encoded once per format and shared across recipients
+                        if let Some(out) = frame.to_message(encoding)
+                            && ws_tx.send(out).await.is_err()
+                        {
+                            break;
                         }
 
-                        if let Ok(json) = serde_json::to_string(&msg) {
-                            if ws_tx.send(Message::Text(json.into())).await.is_err() {
-                                break;
+                        // The room has closed for everyone; end the session like a timeout
+                        if let SignalPayload::Error(error) = &msg.payload
+                            && error.code == ErrorCode::RoomClosed
+                        {
+                            if let Some(tx) = timeout_tx.lock().await.take() {
+                                let _ = tx.send(());
                             }
+                            let _ = ws_tx.close().await;
+                            break;
                         }
                     }
                     Err(_) => break,
@@ -604,11 +1542,14 @@ async fn run_forward_task(
 
 async fn run_receive_loop(
     mut ws_rx: SplitStream<WebSocket>,
-    tx: broadcast::Sender<SignalMessage>,
+    tx: broadcast::Sender<Arc<Frame>>,
+    direct_tx: mpsc::Sender<SignalMessage>,
     mut timeout_rx: tokio::sync::oneshot::Receiver<()>,
-    peer_id: String,
+    state: &AppState,
+    session: &PeerSession,
     last_pong: Arc<tokio::sync::Mutex<Instant>>,
 ) {
+    let PeerSession { key, peer_id, encoding } = session;
      loop {
         tokio::select! {
             // Heartbeat timeout signal
@@ -619,26 +1560,68 @@ async fn run_receive_loop(
             // Incoming WebSocket messages
             msg = ws_rx.next() => {
                 match msg {
-                    Some(Ok(Message::Text(text))) => {
-                        if let Ok(mut signal) = serde_json::from_str::<SignalMessage>(&text) {
-                            if matches!(signal.payload, SignalPayload::Pong) {
-                                *last_pong.lock().await = Instant::now();
+                    Some(Ok(msg @ (Message::Text(_) | Message::Binary(_)))) => {
+                        let mut signal = match codec::decode(&msg, *encoding) {
+                            Some(Ok(signal)) => signal,
+                            Some(Err(e)) => {
+                                // Tell the sender instead of silently dropping the frame
+                                let error = ApiError::with_message(ErrorCode::InvalidMessage, e);
+                                let _ = direct_tx.try_send(server_error(error));
                                 continue;
                             }
-                            // Handle Hello message - log client info, don't broadcast
-                            if let SignalPayload::Hello { ref version, ref client } = signal.payload {
-                                info!(
-                                    event = "client_hello",
-                                    peer_id = peer_id,
-                                    client_version = %version,
-                                    client_name = %client,
-                                    "Client connected"
-                                );
-                                continue;
+                            None => continue,
+                        };
+
+                        if matches!(signal.payload, SignalPayload::Pong) {
+                            *last_pong.lock().await = Instant::now();
+                            continue;
+                        }
+                        // Handshake already happened; never relay server-only messages
+                        if matches!(
+                            signal.payload,
+                            SignalPayload::Hello { .. }
+                                | SignalPayload::Join { .. }
+                                | SignalPayload::Leave { .. }
+                                | SignalPayload::Waiting { .. }
+                                | SignalPayload::Welcome { .. }
+                                | SignalPayload::Host { .. }
+                                | SignalPayload::KeyEpoch { .. }
+                                | SignalPayload::TopologyChange { .. }
+                                | SignalPayload::RecordingStarted { .. }
+                                | SignalPayload::RecordingStopped { .. }
+                                | SignalPayload::Floor(_)
+                                | SignalPayload::ForceMute { .. }
+                                | SignalPayload::Closing { .. }
+                                | SignalPayload::Announcement { .. }
+                                | SignalPayload::Error(_)
+                        ) {
+                            continue;
+                        }
+                        if let SignalPayload::App { ref kind, ref data, .. } = signal.payload
+                            && let Err(error) = state.app_messages.check(&key.app_id, kind, data)
+                        {
+                            let _ = direct_tx.try_send(server_error(error));
+                            continue;
+                        }
+                        // Negotiation with the forwarder never reaches other peers
+                        if let Some(result) = route_to_sfu(state, session, &signal.payload).await {
+                            if let Err(error) = result {
+                                let _ = direct_tx.try_send(server_error(error));
                             }
-                            signal.from = peer_id.clone();
-                            let _ = tx.send(signal);
+                            continue;
                         }
+                        let applied = match signal.payload {
+                            SignalPayload::RecordingStart => {
+                                start_recording(state, session).await.map(|payload| signal.payload = payload)
+                            }
+                            _ => apply_to_room(state, session, &mut signal.payload),
+                        };
+                        if let Err(error) = applied {
+                            let _ = direct_tx.try_send(server_error(error));
+                            continue;
+                        }
+                        signal.from = peer_id.clone();
+                        let _ = tx.send(Frame::new(signal));
                     }
                     Some(Ok(_)) => continue,
                     Some(Err(_)) | None => break,
@@ -647,3 +1630,978 @@ async fn run_receive_loop(
         }
     }
 }
+
+/// Start the host's recording, answering with `RecordingStarted`. Its
+/// directory is created before the room is locked, and the room only moves
+/// onto the SFU once that has worked.
+async fn start_recording(state: &AppState, session: &PeerSession) -> Result<SignalPayload, ApiError> {
+    let config = state.recording.as_ref().ok_or(ErrorCode::RecordingUnavailable)?;
+    {
+        let room_ref = state.rooms.get(&session.key).ok_or(ErrorCode::RoomNotFound)?;
+        check_recording_start(&room_ref, &session.peer_id)?;
+    }
+    let recorder = Recorder::start(config, &session.key).await.map_err(|e| {
+        warn!(event = "recording_start_failed", room_id = session.key.room_id, error = %e, "Failed to start recording");
+        ApiError::from(ErrorCode::RecordingUnavailable)
+    })?;
+
+    let pinned = match state.rooms.get_mut(&session.key) {
+        // Checked again, as the room may have changed while the directory was made
+        Some(mut room_ref) => check_recording_start(&room_ref, &session.peer_id).and_then(|()| {
+            let forwarder = pin_to_sfu(state, &session.key, &mut room_ref, "Recording")?;
+            // Consent is per recording
+            for peer in room_ref.peers.values_mut() {
+                peer.recording_consent = false;
+            }
+            room_ref.recording = Some(recorder.clone());
+            Ok(forwarder)
+        }),
+        None => Err(ErrorCode::RoomNotFound.into()),
+    };
+    let forwarder = match pinned {
+        Ok(forwarder) => forwarder,
+        Err(error) => {
+            recorder.discard().await;
+            return Err(error);
+        }
+    };
+
+    forwarder.set_recorder(Some(recorder.clone()));
+    info!(
+        event = "recording_started",
+        recording = recorder.id,
+        room_id = session.key.room_id,
+        app_id = session.key.app_id,
+        "Recording started"
+    );
+    Ok(SignalPayload::RecordingStarted {
+        id: recorder.id.clone(),
+        by: session.peer_id.clone(),
+    })
+}
+
+/// Only the host starts a recording, and one at a time
+fn check_recording_start(room: &RoomState, peer_id: &str) -> Result<(), ApiError> {
+    if room.host.as_deref() != Some(peer_id) {
+        return Err(ApiError::with_message(ErrorCode::NotPermitted, "Only the host can start a recording"));
+    }
+    if room.recording.is_some() {
+        return Err(ApiError::with_message(ErrorCode::InvalidMessage, "A recording is already running"));
+    }
+    Ok(())
+}
+
+/// Record messages that change room state (chat history, peer state, key
+/// epochs, stage roles, the floor) before they are relayed, filling in server-assigned
+/// fields, and keep fan-out offers to the planned links. Other payloads pass
+/// through untouched.
+fn apply_to_room(state: &AppState, session: &PeerSession, payload: &mut SignalPayload) -> Result<(), ApiError> {
+    if !matches!(
+        payload,
+        SignalPayload::Chat { .. }
+            | SignalPayload::ChatEdit { .. }
+            | SignalPayload::ChatDelete { .. }
+            | SignalPayload::Mute { .. }
+            | SignalPayload::PeerUpdate { .. }
+            | SignalPayload::KeyPackage { .. }
+            | SignalPayload::Rekey
+            | SignalPayload::RecordingStop
+            | SignalPayload::RecordingConsent { .. }
+            | SignalPayload::SpeakRequest { .. }
+            | SignalPayload::SetRole { .. }
+            | SignalPayload::Offer { .. }
+            | SignalPayload::RaiseHand
+            | SignalPayload::LowerHand { .. }
+            | SignalPayload::GrantFloor { .. }
+            | SignalPayload::SingleSpeaker { .. }
+    ) {
+        return Ok(());
+    }
+
+    let mut room_ref = state
+        .rooms
+        .get_mut(&session.key)
+        .ok_or(ErrorCode::RoomNotFound)?;
+    let room = &mut *room_ref;
+
+    match payload {
+        SignalPayload::Chat { id, text, ts } => {
+            let message = room.chat.post(&session.peer_id, text)?;
+            *id = message.id;
+            *text = message.text;
+            *ts = message.ts;
+        }
+        SignalPayload::ChatEdit { id, text } => {
+            *text = room.chat.edit(id, &session.peer_id, text)?;
+        }
+        SignalPayload::ChatDelete { id } => {
+            let is_host = room.host.as_deref() == Some(session.peer_id.as_str());
+            room.chat.delete(id, &session.peer_id, is_host)?;
+        }
+        SignalPayload::Mute { muted } => {
+            if !*muted && !room.floor.may_unmute(&session.peer_id) {
+                return Err(floor_held());
+            }
+            if let Some(peer) = room.peers.get_mut(&session.peer_id) {
+                peer.muted = *muted;
+            }
+        }
+        SignalPayload::PeerUpdate { peer_id, changes } => {
+            if changes.is_empty() {
+                return Err(ApiError::with_message(ErrorCode::InvalidMessage, "Peer update has no changes"));
+            }
+            if changes.muted == Some(false) && !room.floor.may_unmute(&session.peer_id) {
+                return Err(floor_held());
+            }
+            let peer = room
+                .peers
+                .get_mut(&session.peer_id)
+                .ok_or(ErrorCode::InvalidMessage)?;
+            *changes = peer.apply(std::mem::take(changes))?;
+            *peer_id = session.peer_id.clone();
+        }
+        SignalPayload::KeyPackage { epoch, ciphertext, .. } => {
+            keys::check_package(room.key_epoch, *epoch, ciphertext)?;
+        }
+        SignalPayload::Rekey => {
+            if room.host.as_deref() != Some(session.peer_id.as_str()) {
+                return Err(ApiError::with_message(ErrorCode::NotPermitted, "Only the host can rekey the room"));
+            }
+            room.key_epoch += 1;
+            *payload = SignalPayload::KeyEpoch {
+                epoch: room.key_epoch,
+                distributor: room.host.clone(),
+            };
+        }
+        SignalPayload::RecordingStop => {
+            if room.host.as_deref() != Some(session.peer_id.as_str()) {
+                return Err(ApiError::with_message(ErrorCode::NotPermitted, "Only the host can stop a recording"));
+            }
+            let recorder = room
+                .recording
+                .take()
+                .ok_or_else(|| ApiError::with_message(ErrorCode::InvalidMessage, "No recording is running"))?;
+            if let Some(forwarder) = &room.sfu {
+                forwarder.set_recorder(None);
+            }
+            for peer in room.peers.values_mut() {
+                peer.recording_consent = false;
+            }
+            *payload = SignalPayload::RecordingStopped { id: recorder.id.clone() };
+            tokio::spawn(recorder.finish());
+            if let Some(retired) = unpin_from_sfu(state, &session.key, room) {
+                tokio::spawn(async move { retired.close().await });
+            }
+        }
+        SignalPayload::RecordingConsent { peer_id, consent } => {
+            let recorder = room
+                .recording
+                .as_ref()
+                .ok_or_else(|| ApiError::with_message(ErrorCode::InvalidMessage, "No recording is running"))?;
+            let peer = room
+                .peers
+                .get_mut(&session.peer_id)
+                .ok_or(ErrorCode::InvalidMessage)?;
+            peer.recording_consent = *consent;
+            recorder.set_consent(&session.peer_id, *consent);
+            *peer_id = session.peer_id.clone();
+        }
+        SignalPayload::SpeakRequest { peer_id } => {
+            let peer = room
+                .peers
+                .get_mut(&session.peer_id)
+                .ok_or(ErrorCode::InvalidMessage)?;
+            if peer.stage_role != Some(StageRole::Listener) {
+                return Err(ApiError::with_message(
+                    ErrorCode::InvalidMessage,
+                    "Only stage listeners can ask to speak",
+                ));
+            }
+            peer.speak_requested = true;
+            *peer_id = session.peer_id.clone();
+        }
+        SignalPayload::SetRole { peer_id, stage_role } => {
+            let stage = room
+                .stage
+                .ok_or_else(|| ApiError::with_message(ErrorCode::InvalidMessage, "This room has no stage"))?;
+            stage.check_move(&room.peers, room.host.as_deref(), &session.peer_id, peer_id, *stage_role)?;
+            if let Some(peer) = room.peers.get_mut(peer_id.as_str()) {
+                peer.stage_role = Some(*stage_role);
+                peer.speak_requested = false;
+            }
+            sync_speaking(room);
+            info!(
+                event = "stage_role_changed",
+                peer_id = peer_id.as_str(),
+                role = ?stage_role,
+                by = session.peer_id,
+                room_id = session.key.room_id,
+                "Stage role changed"
+            );
+        }
+        SignalPayload::RaiseHand => {
+            if !room.floor.raise(&session.peer_id) {
+                return Err(ApiError::with_message(ErrorCode::InvalidMessage, "Your hand is already raised"));
+            }
+            *payload = SignalPayload::Floor(room.floor.clone());
+        }
+        SignalPayload::LowerHand { peer_id } => {
+            let target = peer_id.clone().unwrap_or_else(|| session.peer_id.clone());
+            if target != session.peer_id && room.host.as_deref() != Some(session.peer_id.as_str()) {
+                return Err(ApiError::with_message(
+                    ErrorCode::NotPermitted,
+                    "Only the host can lower someone else's hand",
+                ));
+            }
+            if !room.floor.lower(&target) {
+                return Err(ApiError::with_message(ErrorCode::InvalidMessage, "That hand isn't raised"));
+            }
+            enforce_floor(room);
+            *payload = SignalPayload::Floor(room.floor.clone());
+        }
+        SignalPayload::GrantFloor { peer_id } => {
+            if room.host.as_deref() != Some(session.peer_id.as_str()) {
+                return Err(ApiError::with_message(ErrorCode::NotPermitted, "Only the host can grant the floor"));
+            }
+            if let Some(peer_id) = peer_id
+                && !room.peers.contains_key(peer_id)
+            {
+                return Err(ApiError::with_message(ErrorCode::InvalidMessage, "No such peer in this room"));
+            }
+            room.floor.grant(peer_id.as_deref());
+            enforce_floor(room);
+            info!(
+                event = "floor_granted",
+                peer_id = ?peer_id,
+                room_id = session.key.room_id,
+                app_id = session.key.app_id,
+                "Floor granted"
+            );
+            *payload = SignalPayload::Floor(room.floor.clone());
+        }
+        SignalPayload::SingleSpeaker { enabled } => {
+            if room.host.as_deref() != Some(session.peer_id.as_str()) {
+                return Err(ApiError::with_message(
+                    ErrorCode::NotPermitted,
+                    "Only the host can change single-speaker mode",
+                ));
+            }
+            room.floor.single_speaker = *enabled;
+            enforce_floor(room);
+            *payload = SignalPayload::Floor(room.floor.clone());
+        }
+        SignalPayload::Offer { to, .. } => {
+            let role = |id: &str| room.peers.get(id).and_then(|p| p.stage_role);
+            if room.topology == Topology::Fanout
+                && room.peers.contains_key(to.as_str())
+                && !stage::linked(role(&session.peer_id), role(to))
+            {
+                return Err(ApiError::with_message(
+                    ErrorCode::InvalidMessage,
+                    "Listeners don't connect to each other",
+                ));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn floor_held() -> ApiError {
+    ApiError::with_message(ErrorCode::NotPermitted, "Only the floor holder can unmute")
+}
+
+/// In single-speaker mode, mute everyone but the floor holder and tell the
+/// room who was muted
+fn enforce_floor(room: &mut RoomState) {
+    if room.floor.single_speaker {
+        let holder = room.floor.holder.as_deref();
+        for (peer_id, peer) in room.peers.iter_mut() {
+            if peer.muted || holder == Some(peer_id.as_str()) {
+                continue;
+            }
+            peer.muted = true;
+            let mute_msg = SignalMessage {
+                from: "server".to_string(),
+                payload: SignalPayload::ForceMute {
+                    peer_id: peer_id.clone(),
+                },
+            };
+            let _ = room.tx.send(Frame::new(mute_msg));
+        }
+    }
+    sync_speaking(room);
+}
+
+/// Have the forwarder drop audio from every peer that may not speak
+fn sync_speaking(room: &RoomState) {
+    if let Some(forwarder) = &room.sfu {
+        for peer_id in room.peers.keys() {
+            forwarder.set_speaking(peer_id, room.may_speak(peer_id));
+        }
+    }
+}
+
+/// Move an adaptive room onto the SFU once it reaches `sfu_upgrade_at` peers
+/// and back to mesh at `sfu_downgrade_at`, announcing the change to the room.
+/// Returns the forwarder that was started or retired.
+fn adapt_topology(state: &AppState, key: &RoomKey, room: &mut RoomState) -> Option<Arc<SfuRoom>> {
+    let sfu = state.sfu.as_ref()?;
+    let peers = room.audio_peers();
+    if !room.adaptive || room.sfu_holds > 0 || state.sfu_upgrade_at == 0 || peers == 0 {
+        return None;
+    }
+
+    match room.topology {
+        Topology::Mesh if peers >= state.sfu_upgrade_at => set_topology(key, room, sfu, Topology::Sfu),
+        Topology::Sfu if peers <= state.sfu_downgrade_at => set_topology(key, room, sfu, Topology::Mesh),
+        _ => None,
+    }
+}
+
+/// Start or retire a room's forwarder and tell the room. Returns the
+/// forwarder that was started or retired.
+fn set_topology(key: &RoomKey, room: &mut RoomState, sfu: &Arc<Sfu>, topology: Topology) -> Option<Arc<SfuRoom>> {
+    room.topology = topology;
+    let changed = match topology {
+        Topology::Sfu => {
+            room.sfu = Some(sfu.new_room(room.tx.clone()));
+            sync_speaking(room);
+            room.sfu.clone()
+        }
+        Topology::Mesh | Topology::Fanout => room.sfu.take(),
+    };
+
+    let change_msg = SignalMessage {
+        from: "server".to_string(),
+        payload: SignalPayload::TopologyChange {
+            topology: room.topology,
+            peers: room.join_order(),
+        },
+    };
+    let _ = room.tx.send(Frame::new(change_msg));
+
+    info!(
+        event = "topology_changed",
+        room_id = key.room_id,
+        app_id = key.app_id,
+        topology = ?room.topology,
+        peers = room.peers.len(),
+        "Room topology changed"
+    );
+    changed
+}
+
+/// Hand Offer/Answer/Ice messages in SFU rooms to the forwarder. Returns
+/// `None` for anything that should be relayed to peers as usual.
+async fn route_to_sfu(
+    state: &AppState,
+    session: &PeerSession,
+    payload: &SignalPayload,
+) -> Option<Result<(), ApiError>> {
+    let to = match payload {
+        SignalPayload::Offer { to, .. } | SignalPayload::Answer { to, .. } | SignalPayload::Ice { to, .. } => to,
+        _ => return None,
+    };
+
+    let sfu = state.rooms.get(&session.key).and_then(|r| r.sfu.clone());
+    match sfu {
+        Some(sfu) if to == SFU_PEER_ID => Some(sfu.handle_signal(&session.peer_id, payload).await),
+        // Peers in an SFU room never connect to each other directly
+        Some(_) => Some(Err(ApiError::with_message(
+            ErrorCode::InvalidMessage,
+            "This room uses the SFU; negotiate with the sfu peer",
+        ))),
+        None => None,
+    }
+}
+
+/// Hold a room on the forwarder until the hold is released, moving an
+/// adaptive mesh room onto it first. Streams and recordings only work
+/// through the SFU.
+fn pin_to_sfu(state: &AppState, key: &RoomKey, room: &mut RoomState, feature: &str) -> Result<Arc<SfuRoom>, ApiError> {
+    let sfu = state.sfu.as_ref().ok_or(ErrorCode::SfuUnavailable)?;
+    let forwarder = match &room.sfu {
+        Some(forwarder) => forwarder.clone(),
+        None if room.adaptive => {
+            let forwarder = set_topology(key, room, sfu, Topology::Sfu).ok_or(ErrorCode::SfuUnavailable)?;
+            connect_to_sfu(&forwarder, room.join_order());
+            forwarder
+        }
+        None => {
+            return Err(ApiError::with_message(
+                ErrorCode::InvalidTopology,
+                format!("{} needs a room that can use the SFU", feature),
+            ));
+        }
+    };
+    room.sfu_holds += 1;
+    Ok(forwarder)
+}
+
+/// Release a hold taken by `pin_to_sfu`, letting an adaptive room fit its
+/// size again. Returns the forwarder it retired by going back to mesh.
+fn unpin_from_sfu(state: &AppState, key: &RoomKey, room: &mut RoomState) -> Option<Arc<SfuRoom>> {
+    room.sfu_holds = room.sfu_holds.saturating_sub(1);
+    adapt_topology(state, key, room)
+}
+
+/// Release a WHEP viewer's hold on its room
+async fn end_egress(state: &AppState, key: &RoomKey) {
+    let retired = state
+        .rooms
+        .get_mut(key)
+        .and_then(|mut room_ref| unpin_from_sfu(state, key, &mut room_ref));
+    if let Some(sfu) = retired {
+        sfu.close().await;
+    }
+}
+
+/// Give each peer its connection to the forwarder
+fn connect_to_sfu(sfu: &Arc<SfuRoom>, peers: Vec<String>) {
+    for peer in peers {
+        let sfu = sfu.clone();
+        tokio::spawn(async move {
+            if let Err(e) = sfu.add_peer(&peer).await {
+                warn!(event = "sfu_peer_failed", peer_id = peer, error = %e, "Failed to connect peer to SFU");
+            }
+        });
+    }
+}
+
+// ----------------------
+// WHIP / WHEP
+// ----------------------
+
+/// Authenticate a WHIP, WHEP or bot request and resolve its room key.
+/// Streaming clients such as OBS send the API key as a bearer token.
+fn stream_key(
+    state: &AppState,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    room: &str,
+    route: &'static str,
+) -> Result<RoomKey, ApiError> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string);
+    if !validate_api_key(&state.api_key, Some(headers), bearer.as_ref()) {
+        return Err(ErrorCode::InvalidApiKey.into());
+    }
+
+    let app_id = get_app_id(params);
+    if !check_origin(state, headers, &app_id, route) {
+        return Err(ErrorCode::OriginNotAllowed.into());
+    }
+    validate_room_id(room)?;
+
+    Ok(RoomKey {
+        app_id,
+        room_id: room.to_string(),
+    })
+}
+
+/// Checks shared by WHIP and WHEP before a session touches the room
+fn check_stream(state: &AppState, offer: &str) -> Result<(), ApiError> {
+    if state.draining.load(std::sync::atomic::Ordering::Relaxed) {
+        return Err(ErrorCode::ServerDraining.into());
+    }
+    sfu::check_offer(offer)
+}
+
+/// `201 Created` with an SDP answer and the session's resource URL,
+/// relative to the request's so it holds behind a path prefix
+fn sdp_created(key: &RoomKey, kind: &str, id: &str, answer: String) -> Response {
+    let mut location = format!("{}/{}", kind, id);
+    if key.app_id != "public" {
+        location.push('?');
+        location.push_str(&form_urlencoded::Serializer::new(String::new()).append_pair("appId", &key.app_id).finish());
+    }
+    (
+        StatusCode::CREATED,
+        [(header::CONTENT_TYPE, "application/sdp".to_string()), (header::LOCATION, location)],
+        answer,
+    )
+        .into_response()
+}
+
+/// WHIP ingest: stream an external source, such as a DJ set, into a room.
+/// The source joins as a listen-only peer named by the `name` parameter.
+pub async fn whip_publish(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(room): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    offer: String,
+) -> Response {
+    if !check_ban(&state, &addr, "whip") {
+        return ErrorCode::Banned.into_response();
+    }
+    let key = match stream_key(&state, &headers, &params, &room, "whip") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+    if let Err(error) = check_stream(&state, &offer) {
+        return error.into_response();
+    }
+
+    let peer_id = generate_peer_id();
+    let mut peer_state = PeerState::new(now_millis());
+    peer_state.ingest = true;
+    if let Some(name) = params.get("name")
+        && let Err(error) = peer_state.apply(PeerUpdate {
+            name: Some(name.clone()),
+            ..PeerUpdate::default()
+        })
+    {
+        return error.into_response();
+    }
+
+    // Registered like any join, so capacity applies and the room sees it arrive
+    let sfu = {
+        let Some(mut room_ref) = state.rooms.get_mut(&key) else {
+            return ErrorCode::RoomNotFound.into_response();
+        };
+        if !room_ref.schedule.is_open(now_millis()) {
+            return ErrorCode::RoomNotOpen.into_response();
+        }
+        // On a stage a source takes a speaker's slot
+        match room_ref.stage {
+            Some(stage) if !stage.has_slot(&room_ref.peers, StageRole::Speaker) => {
+                return ErrorCode::StageRoleFull.into_response();
+            }
+            Some(_) => peer_state.stage_role = Some(StageRole::Speaker),
+            // Clients already queueing keep their place
+            None if room_ref.peers.len() >= room_ref.max_peers || !room_ref.waitlist.is_empty() => {
+                state.webhooks.emit(WebhookEvent::peer(WebhookEventType::RoomFull, &key, &peer_id));
+                return ErrorCode::RoomFull.into_response();
+            }
+            None => {}
+        }
+        // Only a source the room admits moves it onto the SFU; it's let go
+        // when the source leaves
+        let sfu = match pin_to_sfu(&state, &key, &mut room_ref, "Streaming") {
+            Ok(sfu) => sfu,
+            Err(error) => return error.into_response(),
+        };
+        room_ref.schedule.begin_session(now_millis());
+        room_ref.peers.insert(peer_id.clone(), peer_state.clone());
+        let join_msg = SignalMessage {
+            from: peer_id.clone(),
+            payload: SignalPayload::Join {
+                peer_id: peer_id.clone(),
+                state: Some(peer_state),
+                role: None,
+            },
+        };
+        let _ = room_ref.tx.send(Frame::new(join_msg));
+        sfu
+    };
+    state.webhooks.emit(WebhookEvent::peer(WebhookEventType::PeerJoined, &key, &peer_id));
+
+    let (answer, ended) = match sfu.ingest(&peer_id, offer).await {
+        Ok(session) => session,
+        Err(error) => {
+            end_ingest(&state, &key, &peer_id).await;
+            return error.into_response();
+        }
+    };
+
+    // A source that drops without a DELETE still leaves the room
+    let (watch_state, watch_key, watch_peer) = (state.clone(), key.clone(), peer_id.clone());
+    tokio::spawn(async move {
+        if ended.await.is_ok() {
+            end_ingest(&watch_state, &watch_key, &watch_peer).await;
+        }
+    });
+
+    info!(
+        event = "whip_started",
+        peer_id = peer_id,
+        room_id = key.room_id,
+        app_id = key.app_id,
+        "WHIP source joined room"
+    );
+    sdp_created(&key, "whip", &peer_id, answer)
+}
+
+/// End a WHIP session
+pub async fn whip_stop(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((room, peer_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let key = match stream_key(&state, &headers, &params, &room, "whip") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+    if end_ingest(&state, &key, &peer_id).await {
+        StatusCode::OK.into_response()
+    } else {
+        ErrorCode::StreamNotFound.into_response()
+    }
+}
+
+/// Take a WHIP source out of its room, whether it was deleted or dropped.
+/// Returns false if there was no such source.
+async fn end_ingest(state: &AppState, key: &RoomKey, peer_id: &str) -> bool {
+    let source = state
+        .rooms
+        .get(key)
+        .is_some_and(|r| r.peers.get(peer_id).is_some_and(|p| p.ingest));
+    if !source {
+        return false;
+    }
+
+    leave_room(state, key, peer_id).await;
+    info!(
+        event = "whip_ended",
+        peer_id = peer_id,
+        room_id = key.room_id,
+        app_id = key.app_id,
+        "WHIP source left room"
+    );
+    true
+}
+
+/// WHEP egress: receive a room's audio, e.g. in OBS or a streaming ingest.
+/// Each audio section in the offer carries one participant's stream.
+pub async fn whep_play(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(room): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    offer: String,
+) -> Response {
+    if !check_ban(&state, &addr, "whep") {
+        return ErrorCode::Banned.into_response();
+    }
+    let key = match stream_key(&state, &headers, &params, &room, "whep") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+    if let Err(error) = check_stream(&state, &offer) {
+        return error.into_response();
+    }
+    let sfu = {
+        let Some(mut room_ref) = state.rooms.get_mut(&key) else {
+            return ErrorCode::RoomNotFound.into_response();
+        };
+        if !room_ref.schedule.is_open(now_millis()) {
+            return ErrorCode::RoomNotOpen.into_response();
+        }
+        match pin_to_sfu(&state, &key, &mut room_ref, "Streaming") {
+            Ok(sfu) => sfu,
+            Err(error) => return error.into_response(),
+        }
+    };
+
+    let session_id = generate_peer_id();
+    let (answer, ended) = match sfu.egress(&session_id, offer).await {
+        Ok(session) => session,
+        Err(error) => {
+            end_egress(&state, &key).await;
+            return error.into_response();
+        }
+    };
+
+    // Whichever of a DELETE and a dropped connection closes the session
+    // releases its hold
+    let (watch_state, watch_key, watch_sfu, watch_session) =
+        (state.clone(), key.clone(), sfu.clone(), session_id.clone());
+    tokio::spawn(async move {
+        if ended.await.is_ok() && watch_sfu.close_session(&watch_session).await {
+            end_egress(&watch_state, &watch_key).await;
+        }
+    });
+
+    info!(
+        event = "whep_started",
+        session = session_id,
+        room_id = key.room_id,
+        app_id = key.app_id,
+        "WHEP viewer connected"
+    );
+    sdp_created(&key, "whep", &session_id, answer)
+}
+
+/// End a WHEP session
+pub async fn whep_stop(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((room, session_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let key = match stream_key(&state, &headers, &params, &room, "whep") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+    let sfu = state.rooms.get(&key).and_then(|r| r.sfu.clone());
+    match sfu {
+        Some(sfu) if sfu.close_session(&session_id).await => {
+            end_egress(&state, &key).await;
+            StatusCode::OK.into_response()
+        }
+        _ => ErrorCode::StreamNotFound.into_response(),
+    }
+}
+
+// ----------------------
+// Bots
+// ----------------------
+
+/// A bot addressed by the request path, if it is in that room
+fn find_bot(state: &AppState, key: &RoomKey, peer_id: &str) -> Result<Arc<BotSession>, ApiError> {
+    state
+        .bots
+        .get(peer_id)
+        .map(|bot| bot.value().clone())
+        .filter(|bot| bot.key == *key)
+        .ok_or_else(|| ErrorCode::BotNotFound.into())
+}
+
+fn events_busy() -> ApiError {
+    ApiError::with_message(ErrorCode::NotPermitted, "Another request is already reading this bot's events")
+}
+
+/// Join a room as a bot peer. The body sets its name, avatar and metadata.
+/// Bots take a slot like any peer except in stage rooms, where they have no
+/// role, and never become host.
+pub async fn bot_join(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(room): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(profile): Json<PeerUpdate>,
+) -> Response {
+    if !check_ban(&state, &addr, "bot") {
+        return ErrorCode::Banned.into_response();
+    }
+    if state.draining.load(std::sync::atomic::Ordering::Relaxed) {
+        return ErrorCode::ServerDraining.into_response();
+    }
+    let key = match stream_key(&state, &headers, &params, &room, "bot") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+
+    let peer_id = generate_peer_id();
+    let mut peer_state = PeerState::new(now_millis());
+    peer_state.bot = true;
+    if let Err(error) = peer_state.apply(profile) {
+        return error.into_response();
+    }
+
+    let (events, existing, host, chat, closes_at) = {
+        let Some(mut room_ref) = state.rooms.get_mut(&key) else {
+            return ErrorCode::RoomNotFound.into_response();
+        };
+        if !room_ref.schedule.is_open(now_millis()) {
+            return ErrorCode::RoomNotOpen.into_response();
+        }
+        let full = match room_ref.stage {
+            Some(_) => room_ref.peers.values().filter(|p| p.bot).count() >= MAX_STAGE_BOTS,
+            // Clients already queueing keep their place
+            None => room_ref.peers.len() >= room_ref.max_peers || !room_ref.waitlist.is_empty(),
+        };
+        if full {
+            state.webhooks.emit(WebhookEvent::peer(WebhookEventType::RoomFull, &key, &peer_id));
+            return ErrorCode::RoomFull.into_response();
+        }
+
+        let existing: BTreeMap<String, PeerState> = room_ref
+            .peers
+            .iter()
+            .map(|(id, state)| (id.clone(), state.clone()))
+            .collect();
+        let events = room_ref.tx.subscribe();
+        room_ref.schedule.begin_session(now_millis());
+        room_ref.peers.insert(peer_id.clone(), peer_state.clone());
+        if existing.is_empty() {
+            persist_room(&state, &key, &room_ref);
+        }
+        let join_msg = SignalMessage {
+            from: peer_id.clone(),
+            payload: SignalPayload::Join {
+                peer_id: peer_id.clone(),
+                state: Some(peer_state),
+                role: None,
+            },
+        };
+        let _ = room_ref.tx.send(Frame::new(join_msg));
+        (
+            events,
+            existing,
+            room_ref.host.clone(),
+            room_ref.chat.snapshot(),
+            room_ref.schedule.closes_at(state.schedule.max_duration(&key.app_id)),
+        )
+    };
+    state
+        .bots
+        .insert(peer_id.clone(), Arc::new(BotSession::new(key.clone(), peer_id.clone(), events)));
+    state.connections_today.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    state.webhooks.emit(WebhookEvent::peer(WebhookEventType::PeerJoined, &key, &peer_id));
+
+    info!(
+        event = "bot_joined",
+        peer_id = peer_id,
+        room_id = key.room_id,
+        app_id = key.app_id,
+        "Bot joined room"
+    );
+    let response = BotJoinResponse {
+        peer_id,
+        peers: existing,
+        host,
+        chat,
+        closes_at,
+    };
+    (StatusCode::CREATED, Json(response)).into_response()
+}
+
+/// Take a bot out of its room
+pub async fn bot_leave(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((room, peer_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let key = match stream_key(&state, &headers, &params, &room, "bot") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+    if let Err(error) = find_bot(&state, &key, &peer_id) {
+        return error.into_response();
+    }
+
+    if let Some((_, bot)) = state.bots.remove(&peer_id) {
+        bot.leave();
+    }
+    leave_room(&state, &key, &peer_id).await;
+    info!(event = "bot_left", peer_id = peer_id, room_id = key.room_id, app_id = key.app_id, "Bot left room");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Send a message into the room as the bot. The body is a protocol message
+/// as a WebSocket client would send it: `chat`, `chat_edit`, `chat_delete`,
+/// `app`, `peer_update` or `announcement`. Returns the message as relayed,
+/// with server-assigned fields such as a chat message's id filled in.
+pub async fn bot_send(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((room, peer_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(mut payload): Json<SignalPayload>,
+) -> Response {
+    let key = match stream_key(&state, &headers, &params, &room, "bot") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+    let bot = match find_bot(&state, &key, &peer_id) {
+        Ok(bot) => bot,
+        Err(error) => return error.into_response(),
+    };
+    bot.touch();
+
+    match &mut payload {
+        SignalPayload::Announcement { text } => {
+            *text = text.trim().to_string();
+            if text.is_empty() || text.chars().count() > MAX_CHAT_LENGTH {
+                let message = format!("Announcement must be 1-{} characters", MAX_CHAT_LENGTH);
+                return ApiError::with_message(ErrorCode::InvalidMessage, message).into_response();
+            }
+        }
+        SignalPayload::App { kind, data, .. } => {
+            if let Err(error) = state.app_messages.check(&key.app_id, kind, data) {
+                return error.into_response();
+            }
+        }
+        SignalPayload::Chat { .. }
+        | SignalPayload::ChatEdit { .. }
+        | SignalPayload::ChatDelete { .. }
+        | SignalPayload::PeerUpdate { .. } => {}
+        _ => {
+            return ApiError::with_message(
+                ErrorCode::InvalidMessage,
+                "Bots can send chat, app, peer_update and announcement messages",
+            )
+            .into_response();
+        }
+    }
+
+    let session = PeerSession {
+        key: key.clone(),
+        peer_id: peer_id.clone(),
+        encoding: Encoding::Json,
+    };
+    if let Err(error) = apply_to_room(&state, &session, &mut payload) {
+        return error.into_response();
+    }
+    let signal = SignalMessage { from: peer_id, payload };
+    if let Some(room_ref) = state.rooms.get(&key) {
+        let _ = room_ref.tx.send(Frame::new(signal.clone()));
+    }
+    Json(signal).into_response()
+}
+
+/// Read the room's traffic as the bot. Clients asking for
+/// `text/event-stream` get every event as it happens; anyone else
+/// long-polls, getting whatever is waiting or the first events within
+/// `?wait=` seconds (default 25, at most 30).
+pub async fn bot_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((room, peer_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let key = match stream_key(&state, &headers, &params, &room, "bot") {
+        Ok(k) => k,
+        Err(error) => return error.into_response(),
+    };
+    let bot = match find_bot(&state, &key, &peer_id) {
+        Ok(bot) => bot,
+        Err(error) => return error.into_response(),
+    };
+    let Some(mut events) = bot.reader() else {
+        return events_busy().into_response();
+    };
+    bot.touch();
+
+    let streaming = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+    if !streaming {
+        let wait = params
+            .get("wait")
+            .and_then(|s| s.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_POLL_WAIT)
+            .min(MAX_POLL_WAIT);
+        let read = bot.poll(&mut events, wait).await;
+        return Json(BotEventsResponse {
+            events: read.events,
+            missed: read.missed,
+        })
+        .into_response();
+    }
+
+    // Each event is a protocol message; falling behind sends a `missed`
+    // event with the number dropped
+    let guard = bot.stream();
+    let stream = futures::stream::unfold((bot, events, guard), |(bot, mut events, guard)| async move {
+        let event = match bot.next(&mut events).await? {
+            Ok(msg) => Event::default().json_data(&msg).ok()?,
+            Err(missed) => Event::default().event("missed").data(missed.to_string()),
+        };
+        Some((Ok::<_, std::convert::Infallible>(event), (bot, events, guard)))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
@@ -0,0 +1,26 @@
+//! Voca's signaling server as a library: the room state and HTTP handlers
+//! behind the `signaling` binary. [`server`] builds the whole server as a
+//! `Router` to serve or mount in another axum app. The wire types live in
+//! the `voca-protocol` crate, shared with the Rust client, and are
+//! re-exported here under their old module paths.
+
+pub mod bots;
+pub mod codec;
+pub mod codegen;
+pub mod config;
+pub mod handlers;
+pub mod origin;
+pub mod recording;
+pub mod relay;
+pub mod schedule;
+pub mod secret;
+pub mod server;
+pub mod sfu;
+pub mod sqlite;
+pub mod state;
+pub mod storage;
+pub mod types;
+pub mod waitlist;
+pub mod webhooks;
+
+pub use voca_protocol::{chat, error, floor, keys, peer, protocol, stage};
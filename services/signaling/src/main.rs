@@ -1,25 +1,39 @@
-mod handlers;
-mod state;
-mod types;
-
-use axum::{
-    routing::{get, post},
-    Router,
-};
-use dashmap::DashMap;
-use std::{net::SocketAddr, sync::Arc};
-use tower_governor::{
-    governor::GovernorConfigBuilder, key_extractor::PeerIpKeyExtractor, GovernorLayer,
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
-use tower_http::cors::CorsLayer;
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::handlers::{admin_logs, admin_metrics, admin_rooms, check_room, create_room, ws_handler};
-use crate::state::{AppState, MAX_GLOBAL_ROOMS, MAX_PEERS_PER_ROOM};
+use signaling::server::{ServerBuilder, ServerConfig};
+use signaling::webhooks::WebhookConfig;
 
 #[tokio::main]
 async fn main() {
+    // Offline subcommands that print or generate artifacts and exit
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        // `signaling error-codes` prints the error catalogue for the SDKs
+        Some("error-codes") => {
+            println!("{}", serde_json::to_string_pretty(&signaling::error::catalogue()).unwrap());
+            return;
+        }
+        // `signaling codegen <dir>` writes JSON Schema, TypeScript and OpenAPI files
+        Some("codegen") => {
+            let dir = args.get(2).map(String::as_str).unwrap_or("generated");
+            if let Err(e) = signaling::codegen::write_all(std::path::Path::new(dir)) {
+                eprintln!("codegen failed: {}", e);
+                std::process::exit(1);
+            }
+            println!("Wrote protocol.schema.json, protocol.ts and openapi.json to {}", dir);
+            return;
+        }
+        _ => {}
+    }
+
     // Logging Setup
     let log_dir = std::env::var("VOCA_LOG_DIR").ok();
     let format = std::env::var("RUST_LOG_FORMAT").unwrap_or_else(|_| "text".to_string());
@@ -50,96 +64,28 @@ async fn main() {
         }
     }
 
-    let admin_token = std::env::var("VOCA_ADMIN_TOKEN").unwrap_or_else(|_| "changeme".to_string());
-    let api_key = std::env::var("VOCA_API_KEY").ok();
-    
-    // Read configurable limits from environment
-    let max_peers_per_room = std::env::var("VOCA_MAX_PEERS_PER_ROOM")
+    // How long readiness reports draining before the listener shuts down
+    let drain_seconds = std::env::var("VOCA_DRAIN_SECONDS")
         .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(MAX_PEERS_PER_ROOM);
-    
-    let max_global_rooms = std::env::var("VOCA_MAX_GLOBAL_ROOMS")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(MAX_GLOBAL_ROOMS);
-
-    info!(
-        event = "startup",
-        max_peers_per_room = max_peers_per_room,
-        max_global_rooms = max_global_rooms,
-        "Starting signaling server"
-    );
-
-    let state = AppState {
-        rooms: Arc::new(DashMap::new()),
-        rooms_created_today: Arc::new(std::sync::atomic::AtomicU64::new(0)),
-        connections_today: Arc::new(std::sync::atomic::AtomicU64::new(0)),
-        start_time: std::time::Instant::now(),
-        admin_token,
-        api_key,
-        log_dir,
-        max_peers_per_room,
-        max_global_rooms,
-    };
-
-    // Rate limiting: 5 room creations per minute per IP
-    let governor_conf = Arc::new(
-        GovernorConfigBuilder::default()
-            .per_second(12) // Refill rate: 1 token per 12 seconds = 5 per minute
-            .burst_size(5)
-            .key_extractor(PeerIpKeyExtractor)
-            .finish()
-            .unwrap(),
-    );
-    let governor_limiter = governor_conf.limiter().clone();
-    let rate_limit_layer = GovernorLayer::new(governor_conf.clone());
-
-    // Spawn background task to clean up rate limiter
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
-            governor_limiter.retain_recent();
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(10);
+
+    // Every webhook is signed, so an endpoint without a secret is a mistake
+    let webhooks = match WebhookConfig::from_env() {
+        Ok(webhooks) => webhooks,
+        Err(e) => {
+            tracing::error!(event = "webhook_config_invalid", error = %e, "Invalid webhook configuration");
+            std::process::exit(1);
         }
-    });
-
-    // Spawn background task to clean up empty rooms
-    let rooms = state.rooms.clone();
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
-            
-            let mut rooms_to_remove = Vec::new();
-            
-            // Identify empty rooms older than 5 minutes
-            for entry in rooms.iter() {
-                if entry.value().peers.is_empty() && entry.value().created_at.elapsed() > std::time::Duration::from_secs(300) {
-                    rooms_to_remove.push(entry.key().clone());
-                }
-            }
-            
-            // Remove them
-            for key in rooms_to_remove {
-                info!(event = "room_cleanup", room_id = %key.room_id, app_id = %key.app_id, "Removing empty stale room");
-                rooms.remove(&key);
-            }
+    };
+    let builder = ServerBuilder::new(ServerConfig::from_env()).webhooks(webhooks);
+    let server = match builder.build() {
+        Ok(server) => server,
+        Err(e) => {
+            tracing::error!(event = "storage_open_failed", error = %e, "Failed to open database");
+            std::process::exit(1);
         }
-    });
-
-    // Routes with rate limiting on room creation
-    let room_routes = Router::new()
-        .route("/api/room", post(create_room))
-        .layer(rate_limit_layer);
-
-    let app = Router::new()
-        .merge(room_routes)
-        .route("/api/room/{room}", get(check_room))
-        .route("/api/admin/rooms", get(admin_rooms))
-        .route("/api/admin/metrics", get(admin_metrics))
-        .route("/api/admin/logs", get(admin_logs))
-        .route("/ws/{room}", get(ws_handler))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+    };
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
     info!(event = "listening", address = %addr, "Signaling server listening");
@@ -147,8 +93,41 @@ async fn main() {
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     axum::serve(
         listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
+        server.router.clone().into_make_service_with_connect_info::<SocketAddr>(),
     )
+    .with_graceful_shutdown(shutdown_signal(server.state.draining.clone(), drain_seconds))
     .await
     .unwrap();
+
+    server.shutdown();
+}
+
+/// Wait for SIGINT/SIGTERM, then flip the drain flag so `/readyz` fails
+/// and give load balancers time to notice before the listener closes.
+async fn shutdown_signal(draining: Arc<AtomicBool>, drain_seconds: u64) {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    draining.store(true, Ordering::Relaxed);
+    info!(event = "draining", drain_seconds = drain_seconds, "Shutdown requested, draining");
+    tokio::time::sleep(std::time::Duration::from_secs(drain_seconds)).await;
 }
@@ -0,0 +1,193 @@
+use axum::{
+    extract::Query,
+    http::{request::Parts, HeaderMap, HeaderValue},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::config::parse_app_map;
+
+/// Allowed browser origins, configured globally and per app_id.
+///
+/// An app with its own list is checked against that list only; other apps
+/// fall back to the global list. Browsers on the server's own origin are
+/// always accepted; with nothing configured, nothing else is. A list
+/// containing `*` accepts every origin.
+#[derive(Debug, Default)]
+pub struct OriginPolicy {
+    global: Option<HashSet<String>>,
+    per_app: HashMap<String, HashSet<String>>,
+}
+
+impl OriginPolicy {
+    /// Build the policy from the environment:
+    /// - `VOCA_ALLOWED_ORIGINS`: comma-separated origins for every app
+    /// - `VOCA_APP_ALLOWED_ORIGINS`: `app=origin,origin;other=origin`
+    pub fn from_env() -> Self {
+        let global = std::env::var("VOCA_ALLOWED_ORIGINS")
+            .ok()
+            .map(|s| parse_origin_list(&s))
+            .filter(|set| !set.is_empty());
+
+        let per_app = parse_app_map("VOCA_APP_ALLOWED_ORIGINS")
+            .into_iter()
+            .map(|(app, origins)| (app, parse_origin_list(&origins)))
+            .filter(|(_, origins)| !origins.is_empty())
+            .collect();
+
+        Self { global, per_app }
+    }
+
+    /// The same origins for every app; `*` accepts any
+    pub fn allow(origins: &[&str]) -> Self {
+        Self {
+            global: Some(origins.iter().map(|o| normalize_origin(o)).filter(|o| !o.is_empty()).collect()),
+            per_app: HashMap::new(),
+        }
+    }
+
+    /// True when no allowlist is configured at all, so only same-origin
+    /// browsers get in
+    pub fn is_unconfigured(&self) -> bool {
+        self.global.is_none() && self.per_app.is_empty()
+    }
+
+    /// True when the global list is `*`
+    pub fn accepts_any(&self) -> bool {
+        self.global.as_ref().is_some_and(|g| g.contains("*"))
+    }
+
+    /// Check an `Origin` header value for the given app, where `host` is the
+    /// request's `Host` header. Requests without an `Origin` (non-browser
+    /// clients) and same-origin requests are always allowed.
+    pub fn is_allowed(&self, app_id: &str, origin: Option<&str>, host: Option<&str>) -> bool {
+        let origin = match origin {
+            Some(o) => normalize_origin(o),
+            None => return true,
+        };
+        if is_same_origin(&origin, host) {
+            return true;
+        }
+
+        match self.per_app.get(app_id).or(self.global.as_ref()) {
+            Some(allowed) => allowed.contains("*") || allowed.contains(&origin),
+            None => false,
+        }
+    }
+
+    pub fn origin_count(&self) -> usize {
+        self.global.as_ref().map(|g| g.len()).unwrap_or(0)
+            + self.per_app.values().map(|a| a.len()).sum::<usize>()
+    }
+}
+
+/// Extract the `Origin` header as a string, if present and valid UTF-8
+pub fn request_origin(headers: &HeaderMap) -> Option<&str> {
+    headers.get("origin").and_then(|v| v.to_str().ok())
+}
+
+/// Extract the `Host` header, if present and valid UTF-8
+pub fn request_host(headers: &HeaderMap) -> Option<&str> {
+    headers.get("host").and_then(|v| v.to_str().ok())
+}
+
+/// CORS layer for the REST endpoints backed by the origin policy
+pub fn cors_layer(policy: Arc<OriginPolicy>) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(
+            move |origin: &HeaderValue, parts: &Parts| {
+                let app_id = Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+                    .ok()
+                    .and_then(|Query(params)| params.get("appId").cloned())
+                    .unwrap_or_else(|| "public".to_string());
+                policy.is_allowed(&app_id, origin.to_str().ok(), request_host(&parts.headers))
+            },
+        ))
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any)
+}
+
+fn parse_origin_list(s: &str) -> HashSet<String> {
+    s.split(',')
+        .map(normalize_origin)
+        .filter(|o| !o.is_empty())
+        .collect()
+}
+
+fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+/// Whether a normalized origin names the host the request was sent to
+fn is_same_origin(origin: &str, host: Option<&str>) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+    let authority = origin.split_once("://").map_or(origin, |(_, rest)| rest);
+    authority.eq_ignore_ascii_case(host.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(global: Option<&[&str]>, per_app: &[(&str, &[&str])]) -> OriginPolicy {
+        OriginPolicy {
+            global: global.map(|origins| origins.iter().map(|o| normalize_origin(o)).collect()),
+            per_app: per_app
+                .iter()
+                .map(|(app, origins)| (app.to_string(), origins.iter().map(|o| normalize_origin(o)).collect()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn unconfigured_accepts_only_same_origin() {
+        let policy = OriginPolicy::default();
+        assert!(policy.is_allowed("public", None, Some("voca.example")));
+        assert!(policy.is_allowed("public", Some("https://voca.example"), Some("voca.example")));
+        assert!(policy.is_allowed("public", Some("https://VOCA.example/"), Some("voca.example")));
+        assert!(!policy.is_allowed("public", Some("https://evil.example"), Some("voca.example")));
+        assert!(!policy.is_allowed("public", Some("https://voca.example"), None));
+    }
+
+    #[test]
+    fn allowlist_matches_normalized_origins() {
+        let policy = OriginPolicy::allow(&["https://App.example/", " http://localhost:5173 "]);
+        assert!(policy.is_allowed("public", Some("https://app.example"), None));
+        assert!(policy.is_allowed("other", Some("http://localhost:5173"), None));
+        assert!(!policy.is_allowed("public", Some("https://app.example.evil"), None));
+        assert!(!policy.is_allowed("public", Some("http://app.example"), None));
+        assert!(!policy.accepts_any());
+    }
+
+    #[test]
+    fn wildcard_accepts_any_origin() {
+        let policy = OriginPolicy::allow(&["*"]);
+        assert!(policy.accepts_any());
+        assert!(policy.is_allowed("public", Some("https://anywhere.example"), None));
+    }
+
+    #[test]
+    fn per_app_lists_replace_the_global_one() {
+        let policy = policy(Some(&["https://main.example"]), &[("acme", &["https://acme.example"])]);
+        assert!(policy.is_allowed("acme", Some("https://acme.example"), None));
+        assert!(!policy.is_allowed("acme", Some("https://main.example"), None));
+        assert!(policy.is_allowed("public", Some("https://main.example"), None));
+        assert!(!policy.is_allowed("public", Some("https://acme.example"), None));
+        assert_eq!(policy.origin_count(), 2);
+    }
+
+    #[test]
+    fn per_app_lists_without_a_global_one() {
+        let policy = policy(None, &[("acme", &["https://acme.example"])]);
+        assert!(!policy.is_unconfigured());
+        assert!(policy.is_allowed("acme", Some("https://acme.example"), None));
+        assert!(!policy.is_allowed("public", Some("https://acme.example"), Some("voca.example")));
+        assert!(policy.is_allowed("public", Some("https://voca.example"), Some("voca.example")));
+    }
+}
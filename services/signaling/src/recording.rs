@@ -0,0 +1,314 @@
+//! Server-side recording for rooms on the SFU.
+//!
+//! Only the host can start a recording, and every peer is told with
+//! `RecordingStarted`. Nobody is captured until they answer with a positive
+//! `RecordingConsent`; each stretch of consented audio becomes its own Ogg
+//! Opus file, and withdrawing consent closes it. When the recording stops a
+//! `manifest.json` lists every file with its start offset, and the files are
+//! mixed into `mixed.ogg` with ffmpeg when it is available.
+
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tracing::{info, warn};
+use webrtc::media::io::{ogg_writer::OggWriter, Writer};
+use webrtc::rtp::packet::Packet;
+
+use crate::state::{now_millis, RoomKey};
+
+const OPUS_SAMPLE_RATE: u32 = 48000;
+const OPUS_CHANNELS: u8 = 2;
+/// First granule written to each file; the Ogg writer treats 1 as unset
+const FIRST_TIMESTAMP: u32 = 960;
+
+/// Where recordings go, read from the environment:
+/// - `VOCA_RECORDING_DIR`: base directory; recording is disabled when unset
+/// - `VOCA_FFMPEG`: ffmpeg binary used for the mixed file (default: `ffmpeg`)
+pub struct RecordingConfig {
+    dir: PathBuf,
+    ffmpeg: String,
+}
+
+impl RecordingConfig {
+    pub fn from_env() -> Option<Self> {
+        let dir = std::env::var("VOCA_RECORDING_DIR").ok().filter(|d| !d.is_empty())?;
+        Some(Self {
+            dir: PathBuf::from(dir),
+            ffmpeg: std::env::var("VOCA_FFMPEG").unwrap_or_else(|_| "ffmpeg".to_string()),
+        })
+    }
+
+    /// Record under `dir`, mixing with the `ffmpeg` on the `PATH`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ffmpeg: "ffmpeg".to_string(),
+        }
+    }
+}
+
+/// One recording of one room, fed with every forwarded packet
+pub struct Recorder {
+    pub id: String,
+    dir: PathBuf,
+    ffmpeg: String,
+    started: Instant,
+    started_at: u64,
+    state: Mutex<RecorderState>,
+}
+
+#[derive(Default)]
+struct RecorderState {
+    consented: HashSet<String>,
+    /// Peers whose next file is being created
+    opening: HashSet<String>,
+    open: HashMap<String, Segment>,
+    finished: Vec<SegmentInfo>,
+}
+
+/// A file holding one consented stretch of a peer's audio
+struct Segment {
+    writer: OggWriter<BufWriter<File>>,
+    info: SegmentInfo,
+    first_timestamp: u32,
+    last_timestamp: u32,
+}
+
+#[derive(Clone, Serialize)]
+struct SegmentInfo {
+    peer_id: String,
+    file: String,
+    /// Milliseconds after the recording started
+    offset_ms: u64,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    id: &'a str,
+    started_at: u64,
+    stopped_at: u64,
+    tracks: &'a [SegmentInfo],
+}
+
+impl Recorder {
+    /// Create the recording's directory, `<dir>/<app>/<room>/<id>`
+    pub async fn start(config: &RecordingConfig, key: &RoomKey) -> std::io::Result<Arc<Self>> {
+        let id = nanoid::nanoid!(12);
+        let dir = config
+            .dir
+            .join(safe_component(&key.app_id))
+            .join(safe_component(&key.room_id))
+            .join(&id);
+        tokio::fs::create_dir_all(&dir).await?;
+
+        Ok(Arc::new(Self {
+            id,
+            dir,
+            ffmpeg: config.ffmpeg.clone(),
+            started: Instant::now(),
+            started_at: now_millis(),
+            state: Mutex::new(RecorderState::default()),
+        }))
+    }
+
+    /// Remove the directory of a recording that never started
+    pub async fn discard(&self) {
+        let _ = tokio::fs::remove_dir(&self.dir).await;
+    }
+
+    /// Record whether a peer agreed to be captured. Withdrawing consent
+    /// closes the peer's current file straight away.
+    pub fn set_consent(&self, peer_id: &str, consent: bool) {
+        let mut state = self.lock();
+        if consent {
+            state.consented.insert(peer_id.to_string());
+        } else {
+            state.consented.remove(peer_id);
+            state.close(peer_id);
+        }
+    }
+
+    /// Write one of a peer's packets if they have consented. A peer's file
+    /// is created on a blocking thread when their first packet arrives, and
+    /// their packets are dropped until it is open.
+    pub fn write(self: &Arc<Self>, peer_id: &str, packet: &Packet) {
+        let mut state = self.lock();
+        if !state.consented.contains(peer_id) {
+            return;
+        }
+        if let Some(segment) = state.open.get_mut(peer_id) {
+            segment.write(packet);
+            return;
+        }
+        if !state.opening.insert(peer_id.to_string()) {
+            return;
+        }
+
+        let index = state.finished.iter().filter(|s| s.peer_id == peer_id).count();
+        let info = SegmentInfo {
+            peer_id: peer_id.to_string(),
+            file: format!("{}-{}.ogg", peer_id, index),
+            offset_ms: self.started.elapsed().as_millis() as u64,
+        };
+        let first_timestamp = packet.header.timestamp;
+        drop(state);
+
+        let recorder = self.clone();
+        tokio::task::spawn_blocking(move || recorder.open(info, first_timestamp));
+    }
+
+    /// Create a peer's next file, unless they withdrew consent meanwhile
+    fn open(&self, info: SegmentInfo, first_timestamp: u32) {
+        let path = self.dir.join(&info.file);
+        let writer = File::create(&path)
+            .map_err(webrtc::media::Error::from)
+            .and_then(|f| OggWriter::new(BufWriter::new(f), OPUS_SAMPLE_RATE, OPUS_CHANNELS));
+
+        let mut state = self.lock();
+        state.opening.remove(&info.peer_id);
+        match writer {
+            Ok(writer) if state.consented.contains(&info.peer_id) => {
+                let segment = Segment {
+                    writer,
+                    info: info.clone(),
+                    first_timestamp,
+                    last_timestamp: FIRST_TIMESTAMP.wrapping_sub(1),
+                };
+                state.open.insert(info.peer_id, segment);
+            }
+            Ok(mut writer) => {
+                drop(state);
+                let _ = writer.close();
+                let _ = std::fs::remove_file(&path);
+            }
+            Err(e) => {
+                warn!(event = "recording_file_failed", recording = self.id, peer_id = info.peer_id, error = %e, "Failed to open recording file");
+                // Don't retry on every packet
+                state.consented.remove(&info.peer_id);
+            }
+        }
+    }
+
+    /// Stop capturing a peer that left the room
+    pub fn forget(&self, peer_id: &str) {
+        self.set_consent(peer_id, false);
+    }
+
+    /// Close every file, write the manifest and produce the mixed file
+    pub async fn finish(self: Arc<Self>) {
+        let tracks = {
+            let mut state = self.lock();
+            state.consented.clear();
+            let peers: Vec<String> = state.open.keys().cloned().collect();
+            for peer_id in peers {
+                state.close(&peer_id);
+            }
+            std::mem::take(&mut state.finished)
+        };
+
+        let manifest = Manifest {
+            id: &self.id,
+            started_at: self.started_at,
+            stopped_at: now_millis(),
+            tracks: &tracks,
+        };
+        if let Err(e) = serde_json::to_vec_pretty(&manifest)
+            .map_err(std::io::Error::other)
+            .and_then(|json| std::fs::write(self.dir.join("manifest.json"), json))
+        {
+            warn!(event = "recording_manifest_failed", recording = self.id, error = %e, "Failed to write recording manifest");
+        }
+
+        if !tracks.is_empty() {
+            self.mix(&tracks).await;
+        }
+        info!(
+            event = "recording_finished",
+            recording = self.id,
+            tracks = tracks.len(),
+            dir = %self.dir.display(),
+            "Recording finished"
+        );
+    }
+
+    /// Mix every file into `mixed.ogg`, each delayed to where it started
+    async fn mix(&self, tracks: &[SegmentInfo]) {
+        let mut command = tokio::process::Command::new(&self.ffmpeg);
+        command.current_dir(&self.dir).args(["-y", "-loglevel", "error"]);
+        let mut filter = String::new();
+        for (i, track) in tracks.iter().enumerate() {
+            command.arg("-i").arg(&track.file);
+            filter.push_str(&format!("[{}]adelay={}:all=1[a{}];", i, track.offset_ms, i));
+        }
+        for i in 0..tracks.len() {
+            filter.push_str(&format!("[a{}]", i));
+        }
+        filter.push_str(&format!("amix=inputs={}:normalize=0[out]", tracks.len()));
+        command.args(["-filter_complex", &filter, "-map", "[out]", "-c:a", "libopus", "mixed.ogg"]);
+
+        match command.status().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                warn!(event = "recording_mix_failed", recording = self.id, status = %status, "ffmpeg failed to mix recording")
+            }
+            Err(e) => warn!(
+                event = "recording_mix_failed",
+                recording = self.id,
+                error = %e,
+                "ffmpeg unavailable, keeping per-track files only"
+            ),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RecorderState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl RecorderState {
+    fn close(&mut self, peer_id: &str) {
+        if let Some(mut segment) = self.open.remove(peer_id) {
+            let _ = segment.writer.close();
+            self.finished.push(segment.info);
+        }
+    }
+}
+
+impl Segment {
+    /// Rebase timestamps onto the file's start and drop late packets, which
+    /// the Ogg writer can't go back for
+    fn write(&mut self, packet: &Packet) {
+        let timestamp = packet
+            .header
+            .timestamp
+            .wrapping_sub(self.first_timestamp)
+            .wrapping_add(FIRST_TIMESTAMP);
+        if (timestamp.wrapping_sub(self.last_timestamp) as i32) <= 0 {
+            return;
+        }
+        self.last_timestamp = timestamp;
+
+        let mut packet = packet.clone();
+        packet.header.timestamp = timestamp;
+        let _ = self.writer.write_rtp(&packet);
+    }
+}
+
+/// Keep app and room ids from escaping the recording directory
+fn safe_component(s: &str) -> String {
+    let cleaned: String = s
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if cleaned.is_empty() || Path::new(&cleaned) == Path::new("..") {
+        "_".to_string()
+    } else {
+        cleaned
+    }
+}
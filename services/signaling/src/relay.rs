@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::parse_app_map;
+use crate::error::{ApiError, ErrorCode};
+
+/// Default cap on the serialized size of an `app` message's `data`
+pub const DEFAULT_MAX_APP_PAYLOAD_BYTES: usize = 16 * 1024;
+const MAX_KIND_LEN: usize = 64;
+
+/// Limits for opaque application messages relayed between peers.
+///
+/// Apps listed in `VOCA_APP_MESSAGE_KINDS` may only send the kinds named
+/// there; apps that aren't listed may send any well-formed kind.
+#[derive(Debug)]
+pub struct AppMessagePolicy {
+    pub max_payload_bytes: usize,
+    kinds: HashMap<String, HashSet<String>>,
+}
+
+/// The default size limit and any kind for every app
+impl Default for AppMessagePolicy {
+    fn default() -> Self {
+        Self {
+            max_payload_bytes: DEFAULT_MAX_APP_PAYLOAD_BYTES,
+            kinds: HashMap::new(),
+        }
+    }
+}
+
+impl AppMessagePolicy {
+    /// Build the policy from the environment:
+    /// - `VOCA_MAX_APP_PAYLOAD_BYTES`: size limit for `data`
+    /// - `VOCA_APP_MESSAGE_KINDS`: `app=reaction,raise-hand;other=*`
+    pub fn from_env() -> Self {
+        let max_payload_bytes = std::env::var("VOCA_MAX_APP_PAYLOAD_BYTES")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_APP_PAYLOAD_BYTES);
+
+        let kinds = parse_app_map("VOCA_APP_MESSAGE_KINDS")
+            .into_iter()
+            .map(|(app, kinds)| {
+                let kinds = kinds
+                    .split(',')
+                    .map(|k| k.trim().to_string())
+                    .filter(|k| !k.is_empty())
+                    .collect();
+                (app, kinds)
+            })
+            .collect();
+
+        Self {
+            max_payload_bytes,
+            kinds,
+        }
+    }
+
+    /// Validate an `app` message before it is relayed
+    pub fn check(&self, app_id: &str, kind: &str, data: &serde_json::Value) -> Result<(), ApiError> {
+        if kind.is_empty()
+            || kind.len() > MAX_KIND_LEN
+            || !kind
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ':'))
+        {
+            return Err(ApiError::with_message(
+                ErrorCode::InvalidMessage,
+                "App message kind must be 1-64 characters of letters, numbers, '.', '_', '-' or ':'",
+            ));
+        }
+
+        if let Some(allowed) = self.kinds.get(app_id)
+            && !allowed.contains("*")
+            && !allowed.contains(kind)
+        {
+            return Err(ApiError::with_message(
+                ErrorCode::MessageKindNotAllowed,
+                format!("Message kind '{}' is not allowed for this app", kind),
+            ));
+        }
+
+        let size = serde_json::to_vec(data).map(|v| v.len()).unwrap_or(usize::MAX);
+        if size > self.max_payload_bytes {
+            return Err(ApiError::with_message(
+                ErrorCode::PayloadTooLarge,
+                format!("App message data is {} bytes, limit is {}", size, self.max_payload_bytes),
+            ));
+        }
+
+        Ok(())
+    }
+}
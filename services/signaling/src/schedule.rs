@@ -0,0 +1,161 @@
+//! Room opening hours.
+//!
+//! A room may be created ahead of time with `starts_at`, turning joins away
+//! with `room_not_open` until then, and with `ends_at`, after which it
+//! closes. Operators can also cap how long a session in any room of an app
+//! lasts, counted from the first join into the empty room. The room sweep
+//! announces the closing time to the room as it approaches and closes the
+//! room when it arrives; a reserved room that only ran out of session time
+//! stays for the next one.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::parse_app_map;
+
+/// Seconds before closing at which the room is warned, by default
+pub const CLOSE_WARNINGS: &[u64] = &[300, 60];
+
+/// Operator limits on how long rooms stay open
+pub struct SchedulePolicy {
+    /// Longest a session may last, in seconds
+    max_duration: Option<u64>,
+    /// Per-app overrides of `max_duration`
+    per_app: HashMap<String, u64>,
+    /// Seconds before closing at which to warn, longest first
+    warnings: Vec<u64>,
+}
+
+/// No duration limits and the default warnings
+impl Default for SchedulePolicy {
+    fn default() -> Self {
+        Self {
+            max_duration: None,
+            per_app: HashMap::new(),
+            warnings: CLOSE_WARNINGS.to_vec(),
+        }
+    }
+}
+
+impl SchedulePolicy {
+    /// Reads `VOCA_MAX_ROOM_DURATION_SECONDS`, `VOCA_APP_MAX_ROOM_DURATION`
+    /// (`app=seconds;app2=seconds`) and `VOCA_CLOSE_WARNINGS_SECONDS`
+    /// (`600,60`). A duration of 0 means no limit.
+    pub fn from_env() -> Self {
+        let max_duration = std::env::var("VOCA_MAX_ROOM_DURATION_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .filter(|&s| s > 0);
+
+        let per_app = parse_app_map("VOCA_APP_MAX_ROOM_DURATION")
+            .into_iter()
+            .filter_map(|(app, secs)| Some((app, secs.parse::<u64>().ok()?)))
+            .collect();
+
+        let mut warnings: Vec<u64> = std::env::var("VOCA_CLOSE_WARNINGS_SECONDS")
+            .ok()
+            .map(|s| s.split(',').filter_map(|w| w.trim().parse().ok()).filter(|&w| w > 0).collect())
+            .unwrap_or_else(|| CLOSE_WARNINGS.to_vec());
+        warnings.sort_unstable_by(|a, b| b.cmp(a));
+        warnings.dedup();
+
+        Self { max_duration, per_app, warnings }
+    }
+
+    /// Cap every app's sessions at `seconds`
+    pub fn with_max_duration(mut self, seconds: u64) -> Self {
+        self.max_duration = (seconds > 0).then_some(seconds);
+        self
+    }
+
+    /// Longest a session in a room of this app may last, in milliseconds
+    pub fn max_duration(&self, app_id: &str) -> Option<u64> {
+        let secs = self.per_app.get(app_id).copied().or(self.max_duration)?;
+        (secs > 0).then_some(secs * 1000)
+    }
+}
+
+/// When a room opens and closes, in Unix milliseconds
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Joins before this are refused; creation time for unscheduled rooms
+    pub opens_at: u64,
+    /// Requested end, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<u64>,
+    /// When the first peer joined the empty room, if anyone is in it
+    #[serde(skip)]
+    session_started: Option<u64>,
+    /// Most urgent warning already sent, in seconds before closing
+    #[serde(skip)]
+    warned: Option<u64>,
+}
+
+/// What the sweep should do with a room right now
+pub enum ScheduleAction {
+    /// Announce that the room closes at `closes_at`
+    Warn { closes_at: u64, seconds_left: u64 },
+    Close,
+}
+
+impl Schedule {
+    pub fn new(opens_at: u64, ends_at: Option<u64>) -> Self {
+        Self {
+            opens_at,
+            ends_at,
+            session_started: None,
+            warned: None,
+        }
+    }
+
+    /// Start timing a session, unless one is running
+    pub fn begin_session(&mut self, now: u64) {
+        if self.session_started.is_none() {
+            self.session_started = Some(now);
+            self.warned = None;
+        }
+    }
+
+    /// The room emptied; the next join starts a new session
+    pub fn end_session(&mut self) {
+        self.session_started = None;
+        self.warned = None;
+    }
+
+    /// Whether the room is past its requested end, rather than only out of
+    /// session time
+    pub fn has_ended(&self, now: u64) -> bool {
+        self.ends_at.is_some_and(|end| now >= end)
+    }
+
+    pub fn is_open(&self, now: u64) -> bool {
+        now >= self.opens_at
+    }
+
+    /// When the room closes: its requested end or the end of the current
+    /// session under the app's duration limit, whichever comes first
+    pub fn closes_at(&self, max_duration: Option<u64>) -> Option<u64> {
+        let limit = max_duration.zip(self.session_started).map(|(d, start)| start.saturating_add(d));
+        match (self.ends_at, limit) {
+            (Some(end), Some(limit)) => Some(end.min(limit)),
+            (end, limit) => end.or(limit),
+        }
+    }
+
+    /// Close the room once its time is up, or warn when it crosses a
+    /// warning threshold it hasn't been warned about yet
+    pub fn due(&mut self, now: u64, closes_at: Option<u64>, policy: &SchedulePolicy) -> Option<ScheduleAction> {
+        let closes_at = closes_at?;
+        if now >= closes_at {
+            return Some(ScheduleAction::Close);
+        }
+        let seconds_left = (closes_at - now).div_ceil(1000);
+        // The most urgent threshold already crossed
+        let crossed = policy.warnings.iter().copied().filter(|&w| w >= seconds_left).min()?;
+        if self.warned.is_some_and(|w| w <= crossed) {
+            return None;
+        }
+        self.warned = Some(crossed);
+        Some(ScheduleAction::Warn { closes_at, seconds_left })
+    }
+}
@@ -0,0 +1,50 @@
+//! Room secrets the server keeps.
+//!
+//! Room passwords are stored as salted HMAC-SHA256 digests, `salt$hex`, so
+//! neither memory nor the database holds them in the clear. Host keys and
+//! tokens are compared in constant time.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+/// Salted digest of a room password, to keep instead of the password
+pub fn hash_password(password: &str) -> String {
+    let salt = nanoid::nanoid!(16);
+    let digest = keyed(&salt).chain_update(password.as_bytes()).finalize().into_bytes();
+    format!("{}${}", salt, hex::encode(digest))
+}
+
+/// Whether `password` is the one `hash` was made from, compared in constant time
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    let Some((salt, digest)) = hash.split_once('$') else {
+        return false;
+    };
+    let Ok(digest) = hex::decode(digest) else {
+        return false;
+    };
+    keyed(salt).chain_update(password.as_bytes()).verify_slice(&digest).is_ok()
+}
+
+/// Whether `provided` is the `expected` secret, compared in constant time.
+/// No secret set matches nothing.
+pub fn secret_matches(provided: &str, expected: Option<&str>) -> bool {
+    expected.is_some_and(|expected| provided.as_bytes().ct_eq(expected.as_bytes()).into())
+}
+
+fn keyed(salt: &str) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC accepts keys of any length")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_match_only_themselves() {
+        assert!(secret_matches("host-key", Some("host-key")));
+        assert!(!secret_matches("host-kez", Some("host-key")));
+        assert!(!secret_matches("host", Some("host-key")));
+        assert!(!secret_matches("", None));
+    }
+}
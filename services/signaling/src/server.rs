@@ -0,0 +1,502 @@
+//! The signaling server as a router to embed.
+//!
+//! [`ServerBuilder`] turns a [`ServerConfig`] into the `Router` the
+//! `signaling` binary serves, with the background tasks it relies on
+//! already running. Hosts can mount that router in their own axum app,
+//! register routes that share its [`AppState`], and pass those routes their
+//! own values as extensions:
+//!
+//! ```no_run
+//! use axum::{extract::State, routing::get, Router};
+//! use signaling::server::{ServerBuilder, ServerConfig};
+//! use signaling::state::AppState;
+//! use std::net::SocketAddr;
+//!
+//! #[derive(Clone)]
+//! struct Greeting(&'static str);
+//!
+//! async fn hello(State(state): State<AppState>) -> String {
+//!     let greeting = state.extensions.get::<Greeting>().unwrap();
+//!     format!("{} ({} rooms)", greeting.0, state.rooms.len())
+//! }
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let server = ServerBuilder::new(ServerConfig::default())
+//!     .extension(Greeting("hello"))
+//!     .routes(Router::new().route("/hello", get(hello)))
+//!     .build()?;
+//! let app = Router::new().nest("/voca", server.router.clone());
+//!
+//! // Handlers read the peer address, so serve with connect info
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:3001").await?;
+//! axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+//! server.shutdown();
+//! # Ok(())
+//! # }
+//! ```
+
+use axum::{
+    http::Extensions,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, MethodRouter},
+    Router,
+};
+use dashmap::DashMap;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tower_governor::{
+    governor::GovernorConfigBuilder, key_extractor::PeerIpKeyExtractor, GovernorError, GovernorLayer,
+};
+use tracing::info;
+
+use crate::error::{ApiError, ErrorCode};
+use crate::handlers::{
+    admin_apps, admin_ban, admin_bans, admin_clear_dead_letters, admin_logs, admin_metrics, admin_retry_webhooks,
+    admin_rooms, admin_unban, admin_usage, admin_webhooks, bot_events, bot_join, bot_leave, bot_send, check_room,
+    create_room, delete_room, healthz, readyz, sweep_rooms, whep_play, whep_stop, whip_publish, whip_stop, ws_handler,
+};
+use crate::origin::{cors_layer, OriginPolicy};
+use crate::recording::RecordingConfig;
+use crate::relay::AppMessagePolicy;
+use crate::schedule::SchedulePolicy;
+use crate::sfu::Sfu;
+use crate::sqlite::SqliteStorage;
+use crate::state::{
+    now_millis, AppState, RoomKey, RoomState, MAX_GLOBAL_ROOMS, MAX_PEERS_PER_ROOM, MAX_SFU_PEERS_PER_ROOM,
+    RESERVED_ROOM_TTL, SWEEP_INTERVAL,
+};
+use crate::storage::{logged, utc_day, MemoryStorage, RoomWrites, Storage, StorageResult};
+use crate::waitlist::{MAX_WAITLIST, WAITLIST_TIMEOUT};
+use crate::webhooks::{WebhookConfig, Webhooks};
+
+/// Everything the server can be configured with. [`ServerConfig::from_env`]
+/// reads what the binary reads, except for webhooks, which go to
+/// [`ServerBuilder::webhooks`]. The default is an in-memory server with no
+/// SFU, recording or admin API that accepts same-origin browsers only.
+pub struct ServerConfig {
+    /// Bearer token for the admin API; `None` disables it
+    pub admin_token: Option<String>,
+    pub api_key: Option<String>,
+    pub max_peers_per_room: usize,
+    pub max_sfu_peers_per_room: usize,
+    /// Peer count at which adaptive rooms switch to the SFU; 0 disables
+    pub sfu_upgrade_at: usize,
+    /// Peer count at which upgraded rooms return to mesh
+    pub sfu_downgrade_at: usize,
+    pub max_global_rooms: usize,
+    /// Clients that may wait for a slot in one full room; 0 disables waiting
+    pub max_waitlist: usize,
+    pub waitlist_timeout: Duration,
+    /// Reserved rooms nobody has joined for this long are removed
+    pub reserved_room_ttl: Duration,
+    /// How often rooms are checked for closing times, warnings and expiry
+    pub sweep_interval: Duration,
+    pub origins: OriginPolicy,
+    pub app_messages: AppMessagePolicy,
+    pub schedule: SchedulePolicy,
+    /// WebRTC stack for SFU rooms; `None` disables the SFU
+    pub sfu: Option<Arc<Sfu>>,
+    /// Where recordings are written; `None` disables recording
+    pub recording: Option<RecordingConfig>,
+    /// Where the admin API reads logs from
+    pub log_dir: Option<String>,
+    /// SQLite database for state that outlives the process; in memory when unset
+    pub database_path: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    /// Read the configuration from `VOCA_*` environment variables, falling
+    /// back to the defaults for anything unset
+    pub fn from_env() -> Self {
+        let mut config = Self {
+            admin_token: std::env::var("VOCA_ADMIN_TOKEN").ok().filter(|token| !token.is_empty()),
+            api_key: std::env::var("VOCA_API_KEY").ok(),
+            ..Self::default()
+        };
+        if config.admin_token.is_none() {
+            info!(event = "admin_disabled", "VOCA_ADMIN_TOKEN is unset, admin API disabled");
+        }
+
+        config.max_peers_per_room = env_parse("VOCA_MAX_PEERS_PER_ROOM").unwrap_or(MAX_PEERS_PER_ROOM);
+        config.max_sfu_peers_per_room = env_parse("VOCA_MAX_SFU_PEERS_PER_ROOM").unwrap_or(MAX_SFU_PEERS_PER_ROOM);
+
+        // Rooms created without a topology outgrow the mesh one peer past its limit,
+        // and go back a little below it so a peer hovering at the edge doesn't
+        // make the room flap
+        config.sfu_upgrade_at =
+            env_parse("VOCA_SFU_UPGRADE_AT").unwrap_or(config.max_peers_per_room.min(MAX_PEERS_PER_ROOM) + 1);
+        config.sfu_downgrade_at = env_parse("VOCA_SFU_DOWNGRADE_AT")
+            .unwrap_or(config.sfu_upgrade_at.saturating_sub(2))
+            .min(config.sfu_upgrade_at.saturating_sub(1));
+
+        config.max_global_rooms = env_parse("VOCA_MAX_GLOBAL_ROOMS").unwrap_or(MAX_GLOBAL_ROOMS);
+
+        // Clients connecting with `?wait=true` queue for a slot in full rooms
+        config.max_waitlist = env_parse("VOCA_MAX_WAITLIST").unwrap_or(MAX_WAITLIST);
+        config.waitlist_timeout = env_parse("VOCA_WAITLIST_TIMEOUT_SECONDS")
+            .map(Duration::from_secs)
+            .unwrap_or(WAITLIST_TIMEOUT);
+
+        // Reserved rooms outlive their peers but not months of disuse
+        config.reserved_room_ttl = env_parse("VOCA_RESERVED_ROOM_TTL_SECONDS")
+            .map(Duration::from_secs)
+            .unwrap_or(RESERVED_ROOM_TTL);
+        // Closing warnings are only as punctual as the sweep
+        config.sweep_interval = env_parse("VOCA_SWEEP_INTERVAL_SECONDS")
+            .filter(|&s| s > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(SWEEP_INTERVAL);
+
+        config.origins = OriginPolicy::from_env();
+        if config.origins.is_unconfigured() {
+            info!(event = "origins_same_only", "No origin allowlist configured, accepting same-origin browsers only");
+        } else if config.origins.accepts_any() {
+            tracing::warn!(event = "origins_open", "VOCA_ALLOWED_ORIGINS is *, accepting any origin");
+        }
+        config.app_messages = AppMessagePolicy::from_env();
+        config.schedule = SchedulePolicy::from_env();
+
+        // Server-side forwarding for rooms created with `topology=sfu`
+        if env_parse("VOCA_SFU_ENABLED").unwrap_or(true) {
+            match Sfu::from_env() {
+                Ok(sfu) => config.sfu = Some(Arc::new(sfu)),
+                Err(e) => {
+                    tracing::error!(event = "sfu_init_failed", error = %e, "SFU disabled: failed to initialise WebRTC");
+                }
+            }
+        }
+
+        // Host-started recordings of SFU rooms; disabled without a directory
+        config.recording = RecordingConfig::from_env();
+        config.log_dir = std::env::var("VOCA_LOG_DIR").ok();
+        // Reserved rooms, apps, bans and usage survive restarts only with a database
+        config.database_path = std::env::var("VOCA_DATABASE_PATH").ok().map(PathBuf::from);
+        config
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let sfu_upgrade_at = MAX_PEERS_PER_ROOM + 1;
+        Self {
+            admin_token: None,
+            api_key: None,
+            max_peers_per_room: MAX_PEERS_PER_ROOM,
+            max_sfu_peers_per_room: MAX_SFU_PEERS_PER_ROOM,
+            sfu_upgrade_at,
+            sfu_downgrade_at: sfu_upgrade_at - 2,
+            max_global_rooms: MAX_GLOBAL_ROOMS,
+            max_waitlist: MAX_WAITLIST,
+            waitlist_timeout: WAITLIST_TIMEOUT,
+            reserved_room_ttl: RESERVED_ROOM_TTL,
+            sweep_interval: SWEEP_INTERVAL,
+            origins: OriginPolicy::default(),
+            app_messages: AppMessagePolicy::default(),
+            schedule: SchedulePolicy::default(),
+            sfu: None,
+            recording: None,
+            log_dir: None,
+            database_path: None,
+        }
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|s| s.parse().ok())
+}
+
+/// Builds the router and starts the background tasks
+pub struct ServerBuilder {
+    config: ServerConfig,
+    storage: Option<Arc<dyn Storage>>,
+    webhooks: WebhookConfig,
+    extensions: Extensions,
+    routes: Router<AppState>,
+}
+
+impl ServerBuilder {
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            storage: None,
+            webhooks: WebhookConfig::default(),
+            extensions: Extensions::new(),
+            routes: Router::new(),
+        }
+    }
+
+    /// Keep state that outlives the process here instead of in the
+    /// configured database
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Send room and peer lifecycle events to these endpoints, e.g.
+    /// [`WebhookConfig::from_env`]. Without them events are dropped.
+    pub fn webhooks(mut self, webhooks: WebhookConfig) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// A value for the host's routes, found in `AppState::extensions` by its
+    /// type. Adding a second value of the same type replaces the first.
+    pub fn extension<T: Clone + Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.extensions.insert(value);
+        self
+    }
+
+    /// Routes of the host's own, served alongside the signaling routes with
+    /// the same state and CORS policy
+    pub fn routes(mut self, routes: Router<AppState>) -> Self {
+        self.routes = self.routes.merge(routes);
+        self
+    }
+
+    /// Open storage, restore what it holds and start the background tasks.
+    /// Must be called within a Tokio runtime.
+    pub fn build(self) -> StorageResult<Server> {
+        let ServerBuilder {
+            config,
+            storage,
+            webhooks,
+            extensions,
+            routes,
+        } = self;
+
+        let database = match (&storage, &config.database_path) {
+            (Some(_), _) => "custom".to_string(),
+            (None, Some(path)) => path.display().to_string(),
+            (None, None) => "memory".to_string(),
+        };
+        let storage: Arc<dyn Storage> = match (storage, &config.database_path) {
+            (Some(storage), _) => storage,
+            (None, Some(path)) => Arc::new(SqliteStorage::open(path)?),
+            (None, None) => Arc::new(MemoryStorage::default()),
+        };
+
+        let mut tasks = Vec::new();
+        // Lifecycle events are delivered in the background
+        let (webhooks, webhook_queue) = Webhooks::new(webhooks);
+        let webhooks = Arc::new(webhooks);
+        tasks.push(tokio::spawn(webhooks.clone().run(webhook_queue)));
+
+        info!(
+            event = "startup",
+            max_peers_per_room = config.max_peers_per_room,
+            max_global_rooms = config.max_global_rooms,
+            sfu_enabled = config.sfu.is_some(),
+            sfu_upgrade_at = config.sfu_upgrade_at,
+            recording_enabled = config.recording.is_some(),
+            database = %database,
+            webhook_endpoints = webhooks.endpoint_count(),
+            allowed_origins = config.origins.origin_count(),
+            "Starting signaling server"
+        );
+
+        let origins = Arc::new(config.origins);
+        let room_writes = Arc::new(RoomWrites::new(storage.clone()));
+        tasks.push(tokio::spawn(room_writes.clone().run()));
+        let state = AppState {
+            rooms: Arc::new(DashMap::new()),
+            rooms_created_today: Arc::new(AtomicU64::new(0)),
+            connections_today: Arc::new(AtomicU64::new(0)),
+            origin_rejections: Arc::new(AtomicU64::new(0)),
+            start_time: Instant::now(),
+            draining: Arc::new(AtomicBool::new(false)),
+            admin_token: config.admin_token,
+            api_key: config.api_key,
+            origins: origins.clone(),
+            app_messages: Arc::new(config.app_messages),
+            sfu: config.sfu,
+            recording: config.recording.map(Arc::new),
+            log_dir: config.log_dir,
+            max_peers_per_room: config.max_peers_per_room,
+            max_sfu_peers_per_room: config.max_sfu_peers_per_room,
+            sfu_upgrade_at: config.sfu_upgrade_at,
+            sfu_downgrade_at: config.sfu_downgrade_at,
+            max_global_rooms: config.max_global_rooms,
+            max_waitlist: config.max_waitlist,
+            waitlist_timeout: config.waitlist_timeout,
+            schedule: Arc::new(config.schedule),
+            storage: storage.clone(),
+            room_writes,
+            bans: Arc::new(DashMap::new()),
+            webhooks,
+            bots: Arc::new(DashMap::new()),
+            extensions: Arc::new(extensions),
+        };
+
+        // Pick up where the last run left off
+        for ban in logged("bans", storage.bans()).unwrap_or_default() {
+            state.bans.insert(ban.ip.clone(), ban);
+        }
+        let restored = logged("rooms", storage.rooms()).unwrap_or_default();
+        let restored_count = restored.len();
+        for record in restored {
+            let key = RoomKey {
+                app_id: record.app_id.clone(),
+                room_id: record.room_id.clone(),
+            };
+            state.rooms.insert(key, RoomState::restore(&record, state.sfu.as_ref()));
+        }
+        if let Some(usage) = logged("usage", storage.usage_on(&utc_day(now_millis()))) {
+            state.seed_usage(usage);
+        }
+        info!(
+            event = "storage_loaded",
+            reserved_rooms = restored_count,
+            bans = state.bans.len(),
+            "Loaded stored state"
+        );
+
+        // Usage counters are per UTC day: save today's regularly, and start from
+        // zero at midnight after saving the finished day
+        let usage_state = state.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut day = utc_day(now_millis());
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                let today = utc_day(now_millis());
+                if today == day {
+                    logged("set_usage", usage_state.storage.set_usage(&day, usage_state.usage()));
+                } else {
+                    logged("set_usage", usage_state.storage.set_usage(&day, usage_state.take_usage()));
+                    day = today;
+                }
+            }
+        }));
+
+        // Rate limiting: 5 room creations per minute per IP
+        let governor_conf = Arc::new(
+            GovernorConfigBuilder::default()
+                .per_second(12) // Refill rate: 1 token per 12 seconds = 5 per minute
+                .burst_size(5)
+                .key_extractor(PeerIpKeyExtractor)
+                .finish()
+                .unwrap(),
+        );
+        let governor_limiter = governor_conf.limiter().clone();
+        let rate_limit_layer = GovernorLayer::new(governor_conf.clone()).error_handler(rate_limit_error);
+
+        // Spawn background task to clean up rate limiter
+        tasks.push(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                governor_limiter.retain_recent();
+            }
+        }));
+
+        // Spawn background task to close rooms at their end time, warning them
+        // beforehand, and to clean up empty rooms
+        let sweep_state = state.clone();
+        let reserved_room_ttl = config.reserved_room_ttl;
+        let sweep_interval = config.sweep_interval;
+        tasks.push(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(sweep_interval).await;
+                sweep_rooms(&sweep_state, reserved_room_ttl).await;
+            }
+        }));
+
+        let mut router = Router::new();
+        let mut rate_limit = Some(rate_limit_layer);
+        for (path, route) in signaling_routes() {
+            // Rate limiting on room creation
+            let route = match rate_limit.take_if(|_| path == "/api/room") {
+                Some(layer) => route.layer(layer),
+                None => route,
+            };
+            router = router.route(path, route);
+        }
+        let router = router
+            .merge(routes)
+            .layer(cors_layer(origins))
+            .with_state(state.clone());
+
+        Ok(Server { router, state, tasks })
+    }
+}
+
+/// Every signaling route. The OpenAPI document in `codegen` describes the
+/// same paths; a test keeps the two in step.
+fn signaling_routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    vec![
+        ("/api/room", post(create_room)),
+        ("/healthz", get(healthz)),
+        ("/readyz", get(readyz)),
+        ("/api/room/{room}", get(check_room).delete(delete_room)),
+        ("/api/admin/rooms", get(admin_rooms)),
+        ("/api/admin/metrics", get(admin_metrics)),
+        ("/api/admin/logs", get(admin_logs)),
+        ("/api/admin/bans", get(admin_bans).post(admin_ban)),
+        ("/api/admin/bans/{ip}", delete(admin_unban)),
+        ("/api/admin/apps", get(admin_apps)),
+        ("/api/admin/usage", get(admin_usage)),
+        ("/api/admin/webhooks", get(admin_webhooks)),
+        ("/api/admin/webhooks/retry", post(admin_retry_webhooks)),
+        ("/api/admin/webhooks/dead-letters", delete(admin_clear_dead_letters)),
+        ("/api/room/{room}/bots", post(bot_join)),
+        ("/api/room/{room}/bots/{id}", delete(bot_leave)),
+        ("/api/room/{room}/bots/{id}/messages", post(bot_send)),
+        ("/api/room/{room}/bots/{id}/events", get(bot_events)),
+        ("/api/room/{room}/whip", post(whip_publish)),
+        ("/api/room/{room}/whip/{id}", delete(whip_stop)),
+        ("/api/room/{room}/whep", post(whep_play)),
+        ("/api/room/{room}/whep/{id}", delete(whep_stop)),
+        ("/ws/{room}", get(ws_handler)),
+    ]
+}
+
+/// Paths the server routes, not counting the host's own
+pub fn route_paths() -> Vec<&'static str> {
+    signaling_routes().into_iter().map(|(path, _)| path).collect()
+}
+
+/// Answer rate-limited requests with the usual error envelope
+fn rate_limit_error(error: GovernorError) -> Response {
+    match error {
+        GovernorError::TooManyRequests { wait_time, headers } => {
+            let message = format!("Too many requests, try again in {}s", wait_time);
+            let mut response = ApiError::with_message(ErrorCode::RateLimited, message).into_response();
+            response.headers_mut().extend(headers.unwrap_or_default());
+            response
+        }
+        GovernorError::UnableToExtractKey => ErrorCode::Unknown.into_response(),
+        GovernorError::Other { code, msg, headers } => {
+            let mut response = (code, msg.unwrap_or_default()).into_response();
+            response.headers_mut().extend(headers.unwrap_or_default());
+            response
+        }
+    }
+}
+
+/// A built server, ready to serve
+pub struct Server {
+    /// Every route, with its state applied. Serve it with
+    /// `into_make_service_with_connect_info::<SocketAddr>()`.
+    pub router: Router,
+    pub state: AppState,
+    /// Webhook delivery, room and usage saving, rate limiter cleanup and the room sweep
+    pub tasks: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Stop the background tasks, then save queued room writes and today's
+    /// usage. Call once the router has stopped serving.
+    pub fn shutdown(self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        self.state.room_writes.flush();
+        // Don't lose the minute of usage since the last save
+        logged("set_usage", self.state.storage.set_usage(&utc_day(now_millis()), self.state.usage()));
+    }
+}
@@ -0,0 +1,662 @@
+//! Selective forwarding unit for audio rooms larger than the mesh limit.
+//!
+//! In an SFU room each peer holds a single peer connection to the server
+//! instead of one per peer. Its Opus track is received here and forwarded
+//! unchanged to every other peer, using the sender's peer_id as the stream id
+//! so clients can tell the streams apart. The server always makes the offer,
+//! sent through the room's broadcast channel addressed to the peer; clients
+//! answer and trickle ICE candidates addressed to [`SFU_PEER_ID`].
+//!
+//! HTTP streaming sessions negotiate once with the client making the offer:
+//! a WHIP source becomes one more stream in the room, and a WHEP viewer gets
+//! one outgoing track per audio section it offers, each filled with one of
+//! the room's streams.
+//!
+//! Stage listeners still send their track, but it is silenced: nobody is
+//! subscribed to it and its packets are dropped until they are promoted.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock, Weak},
+};
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tracing::{info, warn};
+use webrtc::{
+    api::{
+        interceptor_registry::register_default_interceptors,
+        media_engine::{MediaEngine, MIME_TYPE_OPUS},
+        setting_engine::SettingEngine,
+        APIBuilder, API,
+    },
+    ice::udp_network::{EphemeralUDP, UDPNetwork},
+    ice_transport::{ice_candidate::RTCIceCandidateInit, ice_candidate_type::RTCIceCandidateType},
+    interceptor::registry::Registry,
+    peer_connection::{
+        configuration::RTCConfiguration, peer_connection_state::RTCPeerConnectionState,
+        sdp::session_description::RTCSessionDescription, RTCPeerConnection,
+    },
+    rtp_transceiver::{
+        rtp_codec::{RTCRtpCodecCapability, RTPCodecType},
+        rtp_sender::RTCRtpSender,
+        rtp_transceiver_direction::RTCRtpTransceiverDirection, RTCRtpTransceiverInit,
+    },
+    track::{
+        track_local::{track_local_static_rtp::TrackLocalStaticRTP, TrackLocal, TrackLocalWriter},
+        track_remote::TrackRemote,
+    },
+};
+
+use crate::codec::Frame;
+use crate::error::{ApiError, ErrorCode};
+use crate::recording::Recorder;
+use crate::types::{SignalMessage, SignalPayload};
+
+pub use crate::protocol::SFU_PEER_ID;
+
+/// WebRTC stack shared by every SFU room
+pub struct Sfu {
+    api: API,
+}
+
+impl Sfu {
+    /// Build the WebRTC stack from the environment:
+    /// - `VOCA_SFU_PUBLIC_IP`: address advertised in ICE candidates when behind NAT
+    /// - `VOCA_SFU_UDP_PORTS`: `min-max` UDP port range used for media
+    pub fn from_env() -> Result<Self, webrtc::Error> {
+        let mut media = MediaEngine::default();
+        media.register_default_codecs()?;
+        let registry = register_default_interceptors(Registry::new(), &mut media)?;
+
+        let mut settings = SettingEngine::default();
+        if let Ok(ip) = std::env::var("VOCA_SFU_PUBLIC_IP") {
+            settings.set_nat_1to1_ips(vec![ip], RTCIceCandidateType::Host);
+        }
+        if let Some((min, max)) = std::env::var("VOCA_SFU_UDP_PORTS")
+            .ok()
+            .and_then(|s| parse_port_range(&s))
+        {
+            settings.set_udp_network(UDPNetwork::Ephemeral(EphemeralUDP::new(min, max)?));
+        }
+
+        let api = APIBuilder::new()
+            .with_media_engine(media)
+            .with_interceptor_registry(registry)
+            .with_setting_engine(settings)
+            .build();
+        Ok(Self { api })
+    }
+
+    /// Forwarder for one room; offers go out on the room's channel `tx`
+    pub fn new_room(self: &Arc<Self>, tx: broadcast::Sender<Arc<Frame>>) -> Arc<SfuRoom> {
+        Arc::new(SfuRoom {
+            sfu: self.clone(),
+            tx,
+            links: Mutex::new(HashMap::new()),
+            sources: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            taps: Arc::new(RwLock::new(Vec::new())),
+            recorder: Arc::new(RwLock::new(None)),
+            silenced: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+}
+
+/// Forwarding state for one room: a server-side connection per peer and a
+/// local track per peer that is sending audio.
+pub struct SfuRoom {
+    sfu: Arc<Sfu>,
+    tx: broadcast::Sender<Arc<Frame>>,
+    links: Mutex<HashMap<String, Arc<PeerLink>>>,
+    sources: Mutex<HashMap<String, Arc<TrackLocalStaticRTP>>>,
+    /// WHIP and WHEP connections, by peer or session id
+    sessions: Mutex<HashMap<String, Arc<RTCPeerConnection>>>,
+    /// Outgoing WHEP tracks, read on every forwarded packet
+    taps: Arc<RwLock<Vec<Arc<Tap>>>>,
+    /// Active recording, which sees every forwarded packet
+    recorder: Arc<RwLock<Option<Arc<Recorder>>>>,
+    /// Peers whose audio isn't forwarded, i.e. stage listeners
+    silenced: Arc<RwLock<HashSet<String>>>,
+}
+
+impl SfuRoom {
+    /// Connect a peer to the forwarder and send it the first offer, which
+    /// already carries every stream in the room.
+    pub async fn add_peer(self: &Arc<Self>, peer_id: &str) -> Result<(), webrtc::Error> {
+        let pc = Arc::new(self.sfu.api.new_peer_connection(RTCConfiguration::default()).await?);
+
+        // One upstream audio track from the peer
+        pc.add_transceiver_from_kind(
+            RTPCodecType::Audio,
+            &[RTCRtpTransceiverInit {
+                direction: RTCRtpTransceiverDirection::Recvonly,
+                send_encodings: Vec::new(),
+            }],
+        )
+        .await?;
+
+        let room: Weak<SfuRoom> = Arc::downgrade(self);
+        let source_id = peer_id.to_string();
+        pc.on_track(Box::new(move |track, _receiver| {
+            let room = room.clone();
+            let source_id = source_id.clone();
+            Box::pin(async move {
+                if let (Some(track), Some(room)) = (track, room.upgrade()) {
+                    room.add_source(source_id, track).await;
+                }
+            })
+        }));
+
+        let link = Arc::new(PeerLink {
+            peer_id: peer_id.to_string(),
+            pc,
+            signal: self.tx.clone(),
+            senders: Mutex::new(HashMap::new()),
+            negotiation: Mutex::new(NegotiationState::default()),
+            pending_candidates: Mutex::new(Vec::new()),
+        });
+
+        // Hold the source list while registering so no stream is missed
+        {
+            let sources = self.sources.lock().await;
+            for (source_id, track) in sources.iter().filter(|(id, _)| !self.is_silenced(id)) {
+                link.subscribe(source_id, track).await?;
+            }
+            self.links.lock().await.insert(peer_id.to_string(), link.clone());
+        }
+
+        info!(event = "sfu_peer_added", peer_id = peer_id, "Peer connected to SFU");
+        link.negotiate().await;
+        Ok(())
+    }
+
+    /// Disconnect a peer and stop forwarding its stream to everyone else
+    pub async fn remove_peer(&self, peer_id: &str) {
+        let link = self.links.lock().await.remove(peer_id);
+        if let Some(link) = link {
+            let _ = link.pc.close().await;
+        }
+        let session = self.sessions.lock().await.remove(peer_id);
+        if let Some(pc) = session {
+            let _ = pc.close().await;
+        }
+        self.silenced.write().unwrap_or_else(|e| e.into_inner()).remove(peer_id);
+
+        let mut sources = self.sources.lock().await;
+        if sources.remove(peer_id).is_none() {
+            return;
+        }
+        self.assign_taps(&sources);
+        drop(sources);
+
+        let links: Vec<Arc<PeerLink>> = self.links.lock().await.values().cloned().collect();
+        for link in links {
+            link.unsubscribe(peer_id).await;
+        }
+    }
+
+    /// Start or stop forwarding a peer's audio, as stage roles change. Peers
+    /// already receiving the stream are renegotiated in the background.
+    pub fn set_speaking(self: &Arc<Self>, peer_id: &str, speaking: bool) {
+        {
+            let mut silenced = self.silenced.write().unwrap_or_else(|e| e.into_inner());
+            let changed = if speaking {
+                silenced.remove(peer_id)
+            } else {
+                silenced.insert(peer_id.to_string())
+            };
+            if !changed {
+                return;
+            }
+        }
+        tokio::spawn(self.clone().refresh_source(peer_id.to_string()));
+    }
+
+    /// Bring every subscription to a source in line with whether it is
+    /// silenced. Safe to run in any order, since it only looks at the
+    /// current state.
+    async fn refresh_source(self: Arc<Self>, source_id: String) {
+        let sources = self.sources.lock().await;
+        self.assign_taps(&sources);
+        let Some(track) = sources.get(&source_id) else {
+            return;
+        };
+
+        let speaking = !self.is_silenced(&source_id);
+        let links: Vec<Arc<PeerLink>> = self
+            .links
+            .lock()
+            .await
+            .values()
+            .filter(|l| l.peer_id != source_id)
+            .cloned()
+            .collect();
+        for link in links {
+            let subscribed = link.senders.lock().await.contains_key(&source_id);
+            if speaking && !subscribed {
+                match link.subscribe(&source_id, track).await {
+                    Ok(()) => {
+                        tokio::spawn(link.clone().negotiate());
+                    }
+                    Err(e) => warn!(
+                        event = "sfu_subscribe_failed",
+                        peer_id = link.peer_id,
+                        source = source_id,
+                        error = %e,
+                        "Failed to forward stream"
+                    ),
+                }
+            } else if !speaking && subscribed {
+                link.unsubscribe(&source_id).await;
+            }
+        }
+    }
+
+    fn is_silenced(&self, peer_id: &str) -> bool {
+        self.silenced.read().unwrap_or_else(|e| e.into_inner()).contains(peer_id)
+    }
+
+    /// Disconnect every peer, e.g. when the room moves back to mesh
+    pub async fn close(&self) {
+        let links: Vec<Arc<PeerLink>> = self.links.lock().await.drain().map(|(_, link)| link).collect();
+        let sessions: Vec<Arc<RTCPeerConnection>> =
+            self.sessions.lock().await.drain().map(|(_, pc)| pc).collect();
+        self.sources.lock().await.clear();
+        self.taps.write().unwrap_or_else(|e| e.into_inner()).clear();
+        if let Some(recorder) = self.set_recorder(None) {
+            tokio::spawn(recorder.finish());
+        }
+        for pc in links.iter().map(|l| &l.pc).chain(sessions.iter()) {
+            let _ = pc.close().await;
+        }
+    }
+
+    /// Start or stop feeding a recording, returning the one it replaced
+    pub fn set_recorder(&self, recorder: Option<Arc<Recorder>>) -> Option<Arc<Recorder>> {
+        std::mem::replace(&mut *self.recorder.write().unwrap_or_else(|e| e.into_inner()), recorder)
+    }
+
+    /// Accept a WHIP offer from a source that streams into the room as
+    /// `peer_id`. It receives nothing back. Returns the SDP answer and a
+    /// receiver that fires when the connection fails or closes.
+    pub async fn ingest(
+        self: &Arc<Self>,
+        peer_id: &str,
+        offer: String,
+    ) -> Result<(String, oneshot::Receiver<()>), ApiError> {
+        let pc = Arc::new(self.sfu.api.new_peer_connection(RTCConfiguration::default()).await.map_err(invalid)?);
+
+        let room: Weak<SfuRoom> = Arc::downgrade(self);
+        let source_id = peer_id.to_string();
+        pc.on_track(Box::new(move |track, _receiver| {
+            let room = room.clone();
+            let source_id = source_id.clone();
+            Box::pin(async move {
+                if let (Some(track), Some(room)) = (track, room.upgrade()) {
+                    room.add_source(source_id, track).await;
+                }
+            })
+        }));
+
+        self.open_session(peer_id, pc, offer).await
+    }
+
+    /// Accept a WHEP offer from a viewer. Each audio section in the offer
+    /// carries one of the room's streams. Returns the SDP answer and a
+    /// receiver that fires when the connection fails or closes.
+    pub async fn egress(
+        self: &Arc<Self>,
+        session_id: &str,
+        offer: String,
+    ) -> Result<(String, oneshot::Receiver<()>), ApiError> {
+        let slots = offer.lines().filter(|l| l.starts_with("m=audio")).count();
+        if slots == 0 {
+            return Err(ApiError::with_message(ErrorCode::InvalidMessage, "Offer has no audio section"));
+        }
+
+        let pc = Arc::new(self.sfu.api.new_peer_connection(RTCConfiguration::default()).await.map_err(invalid)?);
+        let mut taps = Vec::with_capacity(slots);
+        for slot in 0..slots {
+            let track = Arc::new(TrackLocalStaticRTP::new(
+                RTCRtpCodecCapability {
+                    mime_type: MIME_TYPE_OPUS.to_string(),
+                    clock_rate: 48000,
+                    channels: 2,
+                    ..Default::default()
+                },
+                format!("audio{}", slot),
+                "room".to_string(),
+            ));
+            let transceiver = pc
+                .add_transceiver_from_track(
+                    track.clone(),
+                    &[RTCRtpTransceiverInit {
+                        direction: RTCRtpTransceiverDirection::Sendonly,
+                        send_encodings: Vec::new(),
+                    }],
+                )
+                .await
+                .map_err(invalid)?;
+            if let Some(sender) = transceiver.sender().await {
+                drain_rtcp(sender);
+            }
+            taps.push(Arc::new(Tap {
+                session: session_id.to_string(),
+                track,
+                source: RwLock::new(None),
+            }));
+        }
+
+        let answer = self.open_session(session_id, pc, offer).await?;
+        self.taps.write().unwrap_or_else(|e| e.into_inner()).extend(taps);
+        self.assign_taps(&*self.sources.lock().await);
+        Ok(answer)
+    }
+
+    /// End a WHEP session. Returns false if there was no such session.
+    pub async fn close_session(&self, session_id: &str) -> bool {
+        self.taps
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|t| t.session != session_id);
+        let session = self.sessions.lock().await.remove(session_id);
+        match session {
+            Some(pc) => {
+                let _ = pc.close().await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Answer a client's offer without trickle ICE and keep the connection
+    async fn open_session(
+        &self,
+        id: &str,
+        pc: Arc<RTCPeerConnection>,
+        offer: String,
+    ) -> Result<(String, oneshot::Receiver<()>), ApiError> {
+        let (ended_tx, ended_rx) = oneshot::channel();
+        let ended_tx = std::sync::Mutex::new(Some(ended_tx));
+        pc.on_peer_connection_state_change(Box::new(move |state| {
+            if matches!(state, RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed)
+                && let Some(tx) = ended_tx.lock().unwrap_or_else(|e| e.into_inner()).take()
+            {
+                let _ = tx.send(());
+            }
+            Box::pin(async {})
+        }));
+
+        let result = async {
+            pc.set_remote_description(RTCSessionDescription::offer(offer)?).await?;
+            let answer = pc.create_answer(None).await?;
+            let mut gathered = pc.gathering_complete_promise().await;
+            pc.set_local_description(answer).await?;
+            let _ = gathered.recv().await;
+            Ok::<_, webrtc::Error>(pc.local_description().await.map(|d| d.sdp).unwrap_or_default())
+        }
+        .await;
+
+        match result {
+            Ok(answer) => {
+                self.sessions.lock().await.insert(id.to_string(), pc);
+                info!(event = "sfu_session_opened", session = id, "HTTP media session opened");
+                Ok((answer, ended_rx))
+            }
+            Err(e) => {
+                let _ = pc.close().await;
+                Err(invalid(e))
+            }
+        }
+    }
+
+    /// Point every empty WHEP slot at a stream its session isn't already
+    /// showing. Slots keep their stream until it leaves.
+    fn assign_taps(&self, sources: &HashMap<String, Arc<TrackLocalStaticRTP>>) {
+        let taps = self.taps.read().unwrap_or_else(|e| e.into_inner());
+        let mut shown: HashMap<&str, HashSet<String>> = HashMap::new();
+        for tap in taps.iter() {
+            let mut source = tap.source.write().unwrap_or_else(|e| e.into_inner());
+            if source.as_ref().is_some_and(|s| !sources.contains_key(s) || self.is_silenced(s)) {
+                *source = None;
+            }
+            if let Some(s) = source.as_ref() {
+                shown.entry(&tap.session).or_default().insert(s.clone());
+            }
+        }
+
+        let mut ids: Vec<&String> = sources.keys().filter(|id| !self.is_silenced(id)).collect();
+        ids.sort();
+        for tap in taps.iter() {
+            let mut source = tap.source.write().unwrap_or_else(|e| e.into_inner());
+            if source.is_some() {
+                continue;
+            }
+            let shown = shown.entry(&tap.session).or_default();
+            if let Some(id) = ids.iter().find(|id| !shown.contains(id.as_str())) {
+                shown.insert(id.to_string());
+                *source = Some(id.to_string());
+            }
+        }
+    }
+
+    /// Handle an Answer or Ice message a peer addressed to the forwarder
+    pub async fn handle_signal(&self, peer_id: &str, payload: &SignalPayload) -> Result<(), ApiError> {
+        let link = self
+            .links
+            .lock()
+            .await
+            .get(peer_id)
+            .cloned()
+            .ok_or_else(|| ApiError::with_message(ErrorCode::InvalidMessage, "Not connected to the SFU"))?;
+
+        match payload {
+            SignalPayload::Answer { sdp, .. } => link.handle_answer(sdp.clone()).await,
+            SignalPayload::Ice { candidate, .. } => {
+                let candidate: RTCIceCandidateInit = serde_json::from_str(candidate)
+                    .map_err(|e| ApiError::with_message(ErrorCode::InvalidMessage, e.to_string()))?;
+                link.add_candidate(candidate).await
+            }
+            _ => Err(ApiError::with_message(
+                ErrorCode::InvalidMessage,
+                "The SFU makes all offers; reply with an answer",
+            )),
+        }
+    }
+
+    /// Start forwarding a peer's upstream track to every other peer
+    async fn add_source(&self, source_id: String, remote: Arc<TrackRemote>) {
+        let codec = remote.codec().await.capability;
+        let local = Arc::new(TrackLocalStaticRTP::new(codec, "audio".to_string(), source_id.clone()));
+
+        {
+            let mut sources = self.sources.lock().await;
+            sources.insert(source_id.clone(), local.clone());
+            self.assign_taps(&sources);
+            let links = self.links.lock().await;
+            let silenced = self.is_silenced(&source_id);
+            for link in links.values().filter(|l| l.peer_id != source_id && !silenced) {
+                match link.subscribe(&source_id, &local).await {
+                    Ok(()) => {
+                        tokio::spawn(link.clone().negotiate());
+                    }
+                    Err(e) => warn!(
+                        event = "sfu_subscribe_failed",
+                        peer_id = link.peer_id,
+                        source = source_id,
+                        error = %e,
+                        "Failed to forward stream"
+                    ),
+                }
+            }
+        }
+
+        info!(event = "sfu_track", peer_id = source_id, "Forwarding audio track");
+
+        // Packets are forwarded unchanged; a read error means the track ended
+        let taps = self.taps.clone();
+        let recorder = self.recorder.clone();
+        let silenced = self.silenced.clone();
+        tokio::spawn(async move {
+            while let Ok((packet, _)) = remote.read_rtp().await {
+                if silenced.read().unwrap_or_else(|e| e.into_inner()).contains(&source_id) {
+                    continue;
+                }
+                let _ = local.write_rtp(&packet).await;
+
+                let recording = recorder.read().unwrap_or_else(|e| e.into_inner()).clone();
+                if let Some(recording) = recording {
+                    recording.write(&source_id, &packet);
+                }
+
+                let viewers: Vec<Arc<TrackLocalStaticRTP>> = taps
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .iter()
+                    .filter(|t| t.source.read().unwrap_or_else(|e| e.into_inner()).as_deref() == Some(&source_id))
+                    .map(|t| t.track.clone())
+                    .collect();
+                for track in viewers {
+                    let _ = track.write_rtp(&packet).await;
+                }
+            }
+        });
+    }
+}
+
+/// One outgoing track of a WHEP session
+struct Tap {
+    session: String,
+    track: Arc<TrackLocalStaticRTP>,
+    /// Stream currently carried, by source peer_id
+    source: RwLock<Option<String>>,
+}
+
+#[derive(Default)]
+struct NegotiationState {
+    awaiting_answer: bool,
+    /// Tracks changed while an offer was outstanding
+    pending: bool,
+}
+
+/// The server side of one peer's connection
+struct PeerLink {
+    peer_id: String,
+    pc: Arc<RTCPeerConnection>,
+    signal: broadcast::Sender<Arc<Frame>>,
+    /// Senders for forwarded streams, by source peer_id
+    senders: Mutex<HashMap<String, Arc<RTCRtpSender>>>,
+    negotiation: Mutex<NegotiationState>,
+    /// Candidates that arrived before the answer
+    pending_candidates: Mutex<Vec<RTCIceCandidateInit>>,
+}
+
+impl PeerLink {
+    async fn subscribe(&self, source_id: &str, track: &Arc<TrackLocalStaticRTP>) -> Result<(), webrtc::Error> {
+        let track: Arc<dyn TrackLocal + Send + Sync> = track.clone();
+        let sender = self.pc.add_track(track).await?;
+        drain_rtcp(sender.clone());
+        self.senders.lock().await.insert(source_id.to_string(), sender);
+        Ok(())
+    }
+
+    /// Stop forwarding one source to this peer
+    async fn unsubscribe(self: &Arc<Self>, source_id: &str) {
+        let sender = self.senders.lock().await.remove(source_id);
+        if let Some(sender) = sender {
+            let _ = self.pc.remove_track(&sender).await;
+            tokio::spawn(self.clone().negotiate());
+        }
+    }
+
+    /// Send a fresh offer, or queue one if the previous offer is unanswered
+    async fn negotiate(self: Arc<Self>) {
+        {
+            let mut state = self.negotiation.lock().await;
+            if state.awaiting_answer {
+                state.pending = true;
+                return;
+            }
+            state.awaiting_answer = true;
+        }
+
+        if let Err(e) = self.send_offer().await {
+            warn!(event = "sfu_offer_failed", peer_id = self.peer_id, error = %e, "Failed to create SFU offer");
+            self.negotiation.lock().await.awaiting_answer = false;
+        }
+    }
+
+    async fn send_offer(&self) -> Result<(), webrtc::Error> {
+        let offer = self.pc.create_offer(None).await?;
+        // Offers carry all candidates so clients don't need server-side trickle
+        let mut gathered = self.pc.gathering_complete_promise().await;
+        self.pc.set_local_description(offer).await?;
+        let _ = gathered.recv().await;
+
+        let sdp = self.pc.local_description().await.map(|d| d.sdp).unwrap_or_default();
+        let offer = SignalMessage {
+            from: SFU_PEER_ID.to_string(),
+            payload: SignalPayload::Offer {
+                to: self.peer_id.clone(),
+                sdp,
+            },
+        };
+        let _ = self.signal.send(Frame::new(offer));
+        Ok(())
+    }
+
+    async fn handle_answer(self: Arc<Self>, sdp: String) -> Result<(), ApiError> {
+        let answer = RTCSessionDescription::answer(sdp).map_err(invalid)?;
+        self.pc.set_remote_description(answer).await.map_err(invalid)?;
+
+        let candidates = std::mem::take(&mut *self.pending_candidates.lock().await);
+        for candidate in candidates {
+            let _ = self.pc.add_ice_candidate(candidate).await;
+        }
+
+        let again = {
+            let mut state = self.negotiation.lock().await;
+            state.awaiting_answer = false;
+            std::mem::take(&mut state.pending)
+        };
+        if again {
+            tokio::spawn(self.negotiate());
+        }
+        Ok(())
+    }
+
+    async fn add_candidate(&self, candidate: RTCIceCandidateInit) -> Result<(), ApiError> {
+        if self.pc.remote_description().await.is_none() {
+            self.pending_candidates.lock().await.push(candidate);
+            return Ok(());
+        }
+        self.pc.add_ice_candidate(candidate).await.map_err(invalid)
+    }
+}
+
+/// Turn down a WHIP or WHEP offer that couldn't be answered, before
+/// anything is set up for it
+pub fn check_offer(offer: &str) -> Result<(), ApiError> {
+    RTCSessionDescription::offer(offer.to_string()).map_err(invalid)?;
+    if !offer.lines().any(|l| l.starts_with("m=audio")) {
+        return Err(ApiError::with_message(ErrorCode::InvalidMessage, "Offer has no audio section"));
+    }
+    Ok(())
+}
+
+/// Read incoming RTCP so the interceptors keep running
+fn drain_rtcp(sender: Arc<RTCRtpSender>) {
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 1500];
+        while sender.read(&mut buf).await.is_ok() {}
+    });
+}
+
+fn invalid(e: webrtc::Error) -> ApiError {
+    ApiError::with_message(ErrorCode::InvalidMessage, e.to_string())
+}
+
+fn parse_port_range(s: &str) -> Option<(u16, u16)> {
+    let (min, max) = s.split_once('-')?;
+    let (min, max) = (min.trim().parse().ok()?, max.trim().parse().ok()?);
+    (min <= max).then_some((min, max))
+}
@@ -0,0 +1,339 @@
+//! SQLite implementation of `Storage`.
+//!
+//! The schema is versioned with `PRAGMA user_version`: opening a database
+//! applies every migration it hasn't seen yet, in order, so upgrading the
+//! binary upgrades the database. Migrations are append-only; never edit one
+//! that has shipped.
+
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::secret::hash_password;
+use crate::storage::{AppRecord, Ban, DailyUsage, RoomRecord, Storage, StorageResult, Usage};
+
+/// Schema changes, applied in order; the database's `user_version` counts
+/// how many have run
+const MIGRATIONS: &[&str] = &[
+    // 1: reserved rooms, apps, bans and daily usage
+    "CREATE TABLE rooms (
+        app_id TEXT NOT NULL,
+        room_id TEXT NOT NULL,
+        settings TEXT NOT NULL,
+        idle_since INTEGER NOT NULL,
+        PRIMARY KEY (app_id, room_id)
+    );
+    CREATE TABLE apps (
+        app_id TEXT PRIMARY KEY,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL,
+        rooms_created INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE bans (
+        ip TEXT PRIMARY KEY,
+        reason TEXT,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE usage_daily (
+        day TEXT PRIMARY KEY,
+        rooms_created INTEGER NOT NULL DEFAULT 0,
+        connections INTEGER NOT NULL DEFAULT 0,
+        origin_rejections INTEGER NOT NULL DEFAULT 0
+    );",
+];
+
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Open or create the database and bring its schema up to date
+    pub fn open(path: &Path) -> StorageResult<Self> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        migrate(&mut conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Apply every migration newer than the database, each in its own
+/// transaction. Returns how many ran.
+fn migrate(conn: &mut Connection) -> StorageResult<usize> {
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if applied > MIGRATIONS.len() {
+        return Err(format!(
+            "database schema version {} is newer than this build supports ({})",
+            applied,
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(MIGRATIONS.len() - applied)
+}
+
+impl Storage for SqliteStorage {
+    fn ping(&self) -> StorageResult<()> {
+        self.lock().query_row("SELECT 1", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    fn rooms(&self) -> StorageResult<Vec<RoomRecord>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare("SELECT settings, idle_since FROM rooms")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+        let mut rooms = Vec::new();
+        for row in rows {
+            let (settings, idle_since) = row?;
+            let mut settings: serde_json::Value = serde_json::from_str(&settings)?;
+            // Rooms saved before passwords were hashed hold them in the clear
+            if let Some(password) = settings.as_object_mut().and_then(|s| s.remove("password"))
+                && let Some(password) = password.as_str()
+            {
+                settings["password_hash"] = hash_password(password).into();
+            }
+            let mut room: RoomRecord = serde_json::from_value(settings)?;
+            room.idle_since = idle_since as u64;
+            rooms.push(room);
+        }
+        Ok(rooms)
+    }
+
+    fn save_room(&self, room: &RoomRecord) -> StorageResult<()> {
+        let settings = serde_json::to_string(room)?;
+        self.lock().execute(
+            "INSERT INTO rooms (app_id, room_id, settings, idle_since) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (app_id, room_id) DO UPDATE SET settings = ?3, idle_since = ?4",
+            params![room.app_id, room.room_id, settings, room.idle_since as i64],
+        )?;
+        Ok(())
+    }
+
+    fn delete_room(&self, app_id: &str, room_id: &str) -> StorageResult<()> {
+        self.lock()
+            .execute("DELETE FROM rooms WHERE app_id = ?1 AND room_id = ?2", params![app_id, room_id])?;
+        Ok(())
+    }
+
+    fn apps(&self) -> StorageResult<Vec<AppRecord>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare("SELECT app_id, first_seen, last_seen, rooms_created FROM apps ORDER BY app_id")?;
+        let apps = stmt
+            .query_map([], |row| {
+                Ok(AppRecord {
+                    app_id: row.get(0)?,
+                    first_seen: row.get::<_, i64>(1)? as u64,
+                    last_seen: row.get::<_, i64>(2)? as u64,
+                    rooms_created: row.get::<_, i64>(3)? as u64,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(apps)
+    }
+
+    fn record_room_created(&self, app_id: &str, at: u64) -> StorageResult<()> {
+        self.lock().execute(
+            "INSERT INTO apps (app_id, first_seen, last_seen, rooms_created) VALUES (?1, ?2, ?2, 1)
+             ON CONFLICT (app_id) DO UPDATE SET last_seen = ?2, rooms_created = rooms_created + 1",
+            params![app_id, at as i64],
+        )?;
+        Ok(())
+    }
+
+    fn bans(&self) -> StorageResult<Vec<Ban>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare("SELECT ip, reason, created_at FROM bans ORDER BY ip")?;
+        let bans = stmt
+            .query_map([], |row| {
+                Ok(Ban {
+                    ip: row.get(0)?,
+                    reason: row.get(1)?,
+                    created_at: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(bans)
+    }
+
+    fn save_ban(&self, ban: &Ban) -> StorageResult<()> {
+        self.lock().execute(
+            "INSERT OR REPLACE INTO bans (ip, reason, created_at) VALUES (?1, ?2, ?3)",
+            params![ban.ip, ban.reason, ban.created_at as i64],
+        )?;
+        Ok(())
+    }
+
+    fn delete_ban(&self, ip: &str) -> StorageResult<bool> {
+        let removed = self.lock().execute("DELETE FROM bans WHERE ip = ?1", params![ip])?;
+        Ok(removed > 0)
+    }
+
+    fn usage(&self, since: &str) -> StorageResult<Vec<DailyUsage>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT day, rooms_created, connections, origin_rejections FROM usage_daily
+             WHERE day >= ?1 ORDER BY day",
+        )?;
+        let days = stmt
+            .query_map(params![since], |row| {
+                Ok(DailyUsage {
+                    day: row.get(0)?,
+                    usage: Usage {
+                        rooms_created: row.get::<_, i64>(1)? as u64,
+                        connections: row.get::<_, i64>(2)? as u64,
+                        origin_rejections: row.get::<_, i64>(3)? as u64,
+                    },
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(days)
+    }
+
+    fn set_usage(&self, day: &str, usage: Usage) -> StorageResult<()> {
+        self.lock().execute(
+            "INSERT OR REPLACE INTO usage_daily (day, rooms_created, connections, origin_rejections)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                day,
+                usage.rooms_created as i64,
+                usage.connections as i64,
+                usage.origin_rejections as i64
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Topology;
+    use crate::schedule::Schedule;
+    use crate::secret::verify_password;
+
+    fn memory() -> SqliteStorage {
+        SqliteStorage::open(Path::new(":memory:")).unwrap()
+    }
+
+    fn room(room_id: &str) -> RoomRecord {
+        RoomRecord {
+            app_id: "app".to_string(),
+            room_id: room_id.to_string(),
+            password_hash: Some(hash_password("hunter2")),
+            max_peers: 6,
+            topology: Topology::Mesh,
+            adaptive: true,
+            stage: None,
+            host_key: "host-key".to_string(),
+            schedule: Some(Schedule::new(1_000, Some(2_000))),
+            idle_since: 42,
+        }
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let storage = memory();
+        let mut conn = storage.lock();
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        assert_eq!(migrate(&mut conn).unwrap(), 0);
+
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn rooms_round_trip() {
+        let storage = memory();
+        storage.save_room(&room("a")).unwrap();
+        let mut updated = room("a");
+        updated.idle_since = 43;
+        storage.save_room(&updated).unwrap();
+        storage.save_room(&room("b")).unwrap();
+
+        let mut rooms = storage.rooms().unwrap();
+        rooms.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+        assert_eq!(rooms.len(), 2);
+        assert_eq!((rooms[0].idle_since, rooms[0].adaptive), (43, true));
+        assert_eq!(rooms[0].host_key, "host-key");
+        assert_eq!(rooms[0].schedule.map(|s| (s.opens_at, s.ends_at)), Some((1_000, Some(2_000))));
+        assert!(verify_password("hunter2", rooms[0].password_hash.as_deref().unwrap()));
+
+        storage.delete_room("app", "a").unwrap();
+        assert_eq!(storage.rooms().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn plaintext_passwords_are_hashed_on_load() {
+        let storage = memory();
+        let mut settings = serde_json::to_value(room("old")).unwrap();
+        let settings = settings.as_object_mut().unwrap();
+        settings.remove("password_hash");
+        settings.insert("password".to_string(), "hunter2".into());
+        storage
+            .lock()
+            .execute(
+                "INSERT INTO rooms (app_id, room_id, settings, idle_since) VALUES ('app', 'old', ?1, 0)",
+                params![serde_json::Value::Object(settings.clone()).to_string()],
+            )
+            .unwrap();
+
+        let hash = storage.rooms().unwrap()[0].password_hash.clone().unwrap();
+        assert!(!hash.contains("hunter2"));
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+    }
+
+    #[test]
+    fn bans_and_apps_round_trip() {
+        let storage = memory();
+        let ban = Ban {
+            ip: "10.0.0.1".to_string(),
+            reason: Some("spam".to_string()),
+            created_at: 7,
+        };
+        storage.save_ban(&ban).unwrap();
+        let bans = storage.bans().unwrap();
+        assert_eq!((bans[0].ip.as_str(), bans[0].reason.as_deref()), ("10.0.0.1", Some("spam")));
+        assert!(storage.delete_ban("10.0.0.1").unwrap());
+        assert!(!storage.delete_ban("10.0.0.1").unwrap());
+
+        storage.record_room_created("app", 10).unwrap();
+        storage.record_room_created("app", 20).unwrap();
+        let apps = storage.apps().unwrap();
+        assert_eq!(
+            (apps[0].first_seen, apps[0].last_seen, apps[0].rooms_created),
+            (10, 20, 2)
+        );
+    }
+
+    #[test]
+    fn usage_round_trips_by_day() {
+        let storage = memory();
+        let usage = Usage {
+            rooms_created: 1,
+            connections: 2,
+            origin_rejections: 3,
+        };
+        storage.set_usage("2024-02-28", usage).unwrap();
+        storage.set_usage("2024-02-29", Usage::default()).unwrap();
+        storage.set_usage("2024-02-29", usage).unwrap();
+
+        let days = storage.usage("2024-02-29").unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].usage.connections, 2);
+        assert_eq!(storage.usage_on("2024-02-28").unwrap().origin_rejections, 3);
+        assert_eq!(storage.usage_on("2024-03-01").unwrap().rooms_created, 0);
+    }
+}
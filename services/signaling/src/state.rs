@@ -1,12 +1,41 @@
-use std::{collections::HashSet, sync::{Arc, atomic::AtomicU64}};
+use std::{collections::HashMap, sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}}, time::{Duration, Instant}};
+use axum::http::Extensions;
 use tokio::sync::broadcast;
 use dashmap::DashMap;
 use nanoid::nanoid;
-use crate::types::SignalMessage;
+use crate::bots::BotSession;
+use crate::chat::ChatHistory;
+use crate::peer::PeerState;
+use crate::codec::Frame;
+use crate::floor::Floor;
+use crate::origin::OriginPolicy;
+use crate::protocol::Topology;
+use crate::recording::{Recorder, RecordingConfig};
+use crate::relay::AppMessagePolicy;
+use crate::schedule::{Schedule, SchedulePolicy};
+use crate::secret::secret_matches;
+use crate::sfu::{Sfu, SfuRoom};
+use crate::stage::{Stage, StageRole};
+use crate::storage::{Ban, RoomRecord, RoomWrites, Storage, Usage};
+use crate::waitlist::Waitlist;
+use crate::webhooks::Webhooks;
+
+/// Server build version, reported by health checks and the protocol handshake
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 // === LIMITS ===
 pub const MAX_PEERS_PER_ROOM: usize = 6;
+/// SFU rooms aren't limited by mesh fan-out, only by server bandwidth
+pub const MAX_SFU_PEERS_PER_ROOM: usize = 50;
 pub const MAX_GLOBAL_ROOMS: usize = 10000;
+/// Empty rooms nobody reserved are swept after this long
+pub const EMPTY_ROOM_TTL: Duration = Duration::from_secs(300);
+/// Reserved rooms are swept once nobody has joined for this long
+pub const RESERVED_ROOM_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// How often the room sweep runs. Rooms close at their `ends_at` or session
+/// limit and are warned at set seconds before it, so the sweep has to run
+/// about once a second for those to land on time; it only walks the room map.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
 
 const ROOM_SLUG_ALPHABET: [char; 36] = [
     'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
@@ -15,24 +44,227 @@ const ROOM_SLUG_ALPHABET: [char; 36] = [
 
 /// Tracks room state including peers and broadcast channel
 pub struct RoomState {
-    pub tx: broadcast::Sender<SignalMessage>,
-    pub peers: HashSet<String>,
-    pub created_at: std::time::Instant,
+    pub tx: broadcast::Sender<Arc<Frame>>,
+    pub peers: HashMap<String, PeerState>,
+    pub created_at: Instant,
+    /// When the last peer left, or creation if nobody has joined yet
+    pub idle_since: Instant,
     pub max_peers: usize,
-    pub password: Option<String>,
+    /// Salted digest of the room password; see `secret`
+    pub password_hash: Option<String>,
+    /// First peer to join; passed on to the longest-connected peer when they leave
+    pub host: Option<String>,
+    pub chat: ChatHistory,
+    /// End-to-end encryption key epoch, bumped whenever the room key must change
+    pub key_epoch: u64,
+    pub topology: Topology,
+    /// Forwarder for SFU rooms
+    pub sfu: Option<Arc<SfuRoom>>,
+    /// Created without a topology, so it moves between mesh and SFU as it
+    /// grows and shrinks
+    pub adaptive: bool,
+    /// Streams, WHEP viewers and recordings holding the room on the SFU; an
+    /// adaptive room stays there until they have all ended
+    pub sfu_holds: usize,
+    /// Recording started by the host, if one is running
+    pub recording: Option<Arc<Recorder>>,
+    /// Per-role capacity of a stage room; `None` for everyone-speaks rooms
+    pub stage: Option<Stage>,
+    pub floor: Floor,
+    /// Clients waiting for a slot while the room is full
+    pub waitlist: Waitlist,
+    /// Reserved through the API: kept while empty, with its settings, until
+    /// deleted or idle for the reserved-room TTL
+    pub reserved: bool,
+    /// Whoever reserved the room takes the host role by connecting with this
+    pub host_key: Option<String>,
+    /// When joins are first allowed and when the room ends
+    pub schedule: Schedule,
 }
 
 impl RoomState {
-    pub fn with_capacity(max_peers: usize, password: Option<String>) -> Self {
-        let (tx, _) = broadcast::channel::<SignalMessage>(32);
+    pub fn with_capacity(max_peers: usize, password_hash: Option<String>) -> Self {
+        let (tx, _) = broadcast::channel::<Arc<Frame>>(32);
         Self {
             tx,
-            peers: HashSet::new(),
-            created_at: std::time::Instant::now(),
+            peers: HashMap::new(),
+            created_at: Instant::now(),
+            idle_since: Instant::now(),
             max_peers: max_peers.min(MAX_PEERS_PER_ROOM), // Cap at global max
-            password,
+            password_hash,
+            host: None,
+            chat: ChatHistory::default(),
+            key_epoch: 0,
+            topology: Topology::Mesh,
+            sfu: None,
+            adaptive: false,
+            sfu_holds: 0,
+            recording: None,
+            stage: None,
+            floor: Floor::default(),
+            waitlist: Waitlist::default(),
+            reserved: false,
+            host_key: None,
+            schedule: Schedule::new(now_millis(), None),
+        }
+    }
+
+    /// A room whose audio goes through the server-side forwarder
+    pub fn with_sfu(max_peers: usize, password_hash: Option<String>, sfu: &Arc<Sfu>) -> Self {
+        let room = Self::with_capacity(0, password_hash);
+        Self {
+            max_peers: max_peers.min(MAX_SFU_PEERS_PER_ROOM),
+            topology: Topology::Sfu,
+            sfu: Some(sfu.new_room(room.tx.clone())),
+            ..room
+        }
+    }
+
+    /// A room that starts as a mesh and switches to the forwarder when it
+    /// outgrows it, so it takes the SFU capacity
+    pub fn adaptive(max_peers: usize, password_hash: Option<String>) -> Self {
+        Self {
+            max_peers: max_peers.min(MAX_SFU_PEERS_PER_ROOM),
+            adaptive: true,
+            ..Self::with_capacity(0, password_hash)
+        }
+    }
+
+    /// A stage room, forwarded by the SFU when there is one and fanned out
+    /// from the speakers otherwise
+    pub fn stage(stage: Stage, password_hash: Option<String>, sfu: Option<&Arc<Sfu>>) -> Self {
+        let room = match sfu {
+            Some(sfu) => Self::with_sfu(0, password_hash, sfu),
+            None => Self {
+                topology: Topology::Fanout,
+                ..Self::with_capacity(0, password_hash)
+            },
+        };
+        Self {
+            max_peers: stage.capacity(),
+            stage: Some(stage),
+            ..room
+        }
+    }
+
+    /// Whether a new peer would be turned away. Stage rooms only admit
+    /// listeners once someone is speaking.
+    pub fn is_full(&self) -> bool {
+        match &self.stage {
+            Some(stage) => !stage.has_slot(&self.peers, stage.joining_role(&self.peers)),
+            None => self.peers.len() >= self.max_peers,
         }
     }
+
+    /// Whether a peer may take a slot now. While anyone is waiting, only the
+    /// first in line may.
+    pub fn can_admit(&self, peer_id: &str) -> bool {
+        !self.is_full() && (self.waitlist.is_empty() || self.waitlist.is_next(peer_id))
+    }
+
+    /// Role a new peer gets in a stage room
+    pub fn joining_role(&self) -> Option<StageRole> {
+        self.stage.map(|s| s.joining_role(&self.peers))
+    }
+
+    /// Whether a peer's audio should reach the room. Stage listeners, and
+    /// everyone but the floor holder in single-speaker mode, stay silent.
+    pub fn may_speak(&self, peer_id: &str) -> bool {
+        let listening = self
+            .peers
+            .get(peer_id)
+            .is_some_and(|p| p.stage_role == Some(StageRole::Listener));
+        !listening && self.floor.may_unmute(peer_id)
+    }
+
+    /// Keep the room while it's empty, handing back the key that makes its
+    /// holder the host
+    pub fn reserve(&mut self) -> String {
+        let host_key = nanoid!(24);
+        self.reserved = true;
+        self.host_key = Some(host_key.clone());
+        host_key
+    }
+
+    /// Recreate a reserved room from storage. Rooms that needed the SFU fall
+    /// back to what this server can run.
+    pub fn restore(record: &RoomRecord, sfu: Option<&Arc<Sfu>>) -> Self {
+        let password_hash = record.password_hash.clone();
+        let mut room = match (record.stage, sfu) {
+            (Some(stage), sfu) => Self::stage(stage, password_hash, sfu.filter(|_| record.topology == Topology::Sfu)),
+            (None, Some(sfu)) if record.topology == Topology::Sfu && !record.adaptive => {
+                Self::with_sfu(record.max_peers, password_hash, sfu)
+            }
+            (None, Some(_)) if record.adaptive => Self::adaptive(record.max_peers, password_hash),
+            (None, _) => Self::with_capacity(record.max_peers, password_hash),
+        };
+        let idle_for = Duration::from_millis(now_millis().saturating_sub(record.idle_since));
+        room.idle_since = Instant::now().checked_sub(idle_for).unwrap_or_else(Instant::now);
+        room.reserved = true;
+        room.host_key = Some(record.host_key.clone());
+        if let Some(schedule) = record.schedule {
+            room.schedule = schedule;
+            // Restored rooms are empty, whatever the record was taken from
+            room.schedule.end_session();
+        }
+        room
+    }
+
+    /// What storage keeps of a reserved room; `None` for everything else
+    pub fn record(&self, key: &RoomKey) -> Option<RoomRecord> {
+        if !self.reserved {
+            return None;
+        }
+        // An occupied room isn't idle
+        let idle_since = if self.peers.is_empty() {
+            now_millis().saturating_sub(self.idle_since.elapsed().as_millis() as u64)
+        } else {
+            now_millis()
+        };
+        Some(RoomRecord {
+            app_id: key.app_id.clone(),
+            room_id: key.room_id.clone(),
+            password_hash: self.password_hash.clone(),
+            max_peers: self.max_peers,
+            topology: self.topology,
+            adaptive: self.adaptive,
+            stage: self.stage,
+            host_key: self.host_key.clone()?,
+            schedule: Some(self.schedule),
+            idle_since,
+        })
+    }
+
+    /// Whether an empty room should be swept. Scheduled rooms get the
+    /// grace period from their start, not their creation.
+    pub fn is_stale(&self, reserved_ttl: Duration, now: u64) -> bool {
+        self.peers.is_empty()
+            && self.waitlist.is_empty()
+            && if self.reserved {
+                self.idle_since.elapsed() > reserved_ttl
+            } else {
+                self.created_at.elapsed() > EMPTY_ROOM_TTL
+                    && now.saturating_sub(self.schedule.opens_at) > EMPTY_ROOM_TTL.as_millis() as u64
+            }
+    }
+
+    /// Peers that exchange audio; bots only signal
+    pub fn audio_peers(&self) -> usize {
+        self.peers.values().filter(|p| !p.bot).count()
+    }
+
+    /// Ids of the peers that exchange audio, ordered by join time, earliest
+    /// first
+    pub fn join_order(&self) -> Vec<String> {
+        let mut peers: Vec<(&String, u64)> = self
+            .peers
+            .iter()
+            .filter(|(_, p)| !p.bot)
+            .map(|(id, p)| (id, p.joined_at))
+            .collect();
+        peers.sort_by(|(a_id, a), (b_id, b)| a.cmp(b).then(a_id.cmp(b_id)));
+        peers.into_iter().map(|(id, _)| id.clone()).collect()
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -44,17 +276,81 @@ pub struct RoomKey {
 #[derive(Clone)]
 pub struct AppState {
     pub rooms: Arc<DashMap<RoomKey, RoomState>>,
-    pub admin_token: String,
+    /// `None` disables the admin API
+    pub admin_token: Option<String>,
     pub api_key: Option<String>,
+    pub origins: Arc<OriginPolicy>,
+    pub app_messages: Arc<AppMessagePolicy>,
+    /// WebRTC stack for SFU rooms; `None` when the SFU is disabled
+    pub sfu: Option<Arc<Sfu>>,
+    /// Where recordings are written; `None` when recording is disabled
+    pub recording: Option<Arc<RecordingConfig>>,
     // Metrics
     pub rooms_created_today: Arc<AtomicU64>,
     pub connections_today: Arc<AtomicU64>,
+    pub origin_rejections: Arc<AtomicU64>,
     pub start_time: std::time::Instant,
+    // Set once shutdown begins; new rooms are refused while draining
+    pub draining: Arc<AtomicBool>,
     // Logging
     pub log_dir: Option<String>,
     // Configurable limits
     pub max_peers_per_room: usize,
+    pub max_sfu_peers_per_room: usize,
+    /// Peer count at which adaptive rooms switch to the SFU; 0 disables
+    pub sfu_upgrade_at: usize,
+    /// Peer count at which upgraded rooms return to mesh
+    pub sfu_downgrade_at: usize,
     pub max_global_rooms: usize,
+    /// Clients that may wait for a slot in one full room; 0 disables waiting
+    pub max_waitlist: usize,
+    pub waitlist_timeout: Duration,
+    /// Per-app room duration limits and closing warnings
+    pub schedule: Arc<SchedulePolicy>,
+    /// Reserved rooms, apps, bans and usage; in memory unless a database is set
+    pub storage: Arc<dyn Storage>,
+    /// Saves and deletes of reserved rooms, applied in order off the runtime
+    pub room_writes: Arc<RoomWrites>,
+    /// Banned addresses, loaded from storage and checked on every join
+    pub bans: Arc<DashMap<String, Ban>>,
+    /// Lifecycle events for apps with a webhook URL
+    pub webhooks: Arc<Webhooks>,
+    /// Bot peers by peer id
+    pub bots: Arc<DashMap<String, Arc<BotSession>>>,
+    /// Values a host embedding the server hands to its own routes, by type
+    pub extensions: Arc<Extensions>,
+}
+
+impl AppState {
+    /// Whether `token` opens the admin API
+    pub fn is_admin(&self, token: &str) -> bool {
+        secret_matches(token, self.admin_token.as_deref())
+    }
+
+    /// Today's usage counters
+    pub fn usage(&self) -> Usage {
+        Usage {
+            rooms_created: self.rooms_created_today.load(Ordering::Relaxed),
+            connections: self.connections_today.load(Ordering::Relaxed),
+            origin_rejections: self.origin_rejections.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Pick up counting where a previous run left off
+    pub fn seed_usage(&self, usage: Usage) {
+        self.rooms_created_today.store(usage.rooms_created, Ordering::Relaxed);
+        self.connections_today.store(usage.connections, Ordering::Relaxed);
+        self.origin_rejections.store(usage.origin_rejections, Ordering::Relaxed);
+    }
+
+    /// Start a new day, handing back the finished day's counters
+    pub fn take_usage(&self) -> Usage {
+        Usage {
+            rooms_created: self.rooms_created_today.swap(0, Ordering::Relaxed),
+            connections: self.connections_today.swap(0, Ordering::Relaxed),
+            origin_rejections: self.origin_rejections.swap(0, Ordering::Relaxed),
+        }
+    }
 }
 
 /// Generate a unique 6-character room slug with collision checking
@@ -77,3 +373,5 @@ pub fn generate_unique_slug(rooms: &DashMap<RoomKey, RoomState>, app_id: &str) -
 pub fn generate_peer_id() -> String {
     nanoid!(8, &ROOM_SLUG_ALPHABET)
 }
+
+pub use voca_protocol::now_millis;
@@ -0,0 +1,343 @@
+//! State that should survive a restart.
+//!
+//! Live peers, chat and everything else about a call stay in memory. What
+//! outlives the process goes through `Storage`: the settings of reserved
+//! rooms, the apps that have used the server, admin bans and daily usage
+//! counters. `MemoryStorage` keeps them for the life of the process; set
+//! `VOCA_DATABASE_PATH` to keep them in SQLite instead.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::protocol::Topology;
+use crate::schedule::Schedule;
+use crate::stage::Stage;
+
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// Settings of a reserved room, enough to recreate it after a restart
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomRecord {
+    pub app_id: String,
+    pub room_id: String,
+    /// Salted digest from `secret::hash_password`, never the password
+    pub password_hash: Option<String>,
+    pub max_peers: usize,
+    pub topology: Topology,
+    /// Moves between mesh and SFU as it grows and shrinks
+    pub adaptive: bool,
+    pub stage: Option<Stage>,
+    pub host_key: String,
+    /// Missing for rooms saved before scheduling existed
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    /// Unix milliseconds when the room was last left empty or first joined
+    pub idle_since: u64,
+}
+
+/// An application namespace that has created rooms
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct AppRecord {
+    pub app_id: String,
+    /// Unix milliseconds of its first room
+    pub first_seen: u64,
+    /// Unix milliseconds of its latest room
+    pub last_seen: u64,
+    pub rooms_created: u64,
+}
+
+/// An address the admin has shut out
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Ban {
+    pub ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Unix milliseconds
+    pub created_at: u64,
+}
+
+/// Usage counters for one period
+#[derive(Clone, Copy, Debug, Default, Serialize, JsonSchema)]
+pub struct Usage {
+    pub rooms_created: u64,
+    pub connections: u64,
+    pub origin_rejections: u64,
+}
+
+/// Usage for one UTC day
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct DailyUsage {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+/// Where durable state is kept. Calls are synchronous and may wait on disk,
+/// so nothing calls them while holding a room's lock: room writes go through
+/// [`RoomWrites`]. Failures are logged by the caller and never end a call in
+/// progress.
+pub trait Storage: Send + Sync {
+    fn rooms(&self) -> StorageResult<Vec<RoomRecord>>;
+    /// Insert or replace a reserved room
+    fn save_room(&self, room: &RoomRecord) -> StorageResult<()>;
+    fn delete_room(&self, app_id: &str, room_id: &str) -> StorageResult<()>;
+
+    fn apps(&self) -> StorageResult<Vec<AppRecord>>;
+    /// Register the app if it's new and count a room created at `at`
+    fn record_room_created(&self, app_id: &str, at: u64) -> StorageResult<()>;
+
+    fn bans(&self) -> StorageResult<Vec<Ban>>;
+    fn save_ban(&self, ban: &Ban) -> StorageResult<()>;
+    /// Returns false if the address wasn't banned
+    fn delete_ban(&self, ip: &str) -> StorageResult<bool>;
+
+    /// Daily usage from `since` (a `YYYY-MM-DD` day) onwards, oldest first
+    fn usage(&self, since: &str) -> StorageResult<Vec<DailyUsage>>;
+    /// Replace a day's counters
+    fn set_usage(&self, day: &str, usage: Usage) -> StorageResult<()>;
+
+    /// Check the backend can be reached, for the readiness probe
+    fn ping(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// One day's counters, zero if nothing was recorded
+    fn usage_on(&self, day: &str) -> StorageResult<Usage> {
+        Ok(self
+            .usage(day)?
+            .into_iter()
+            .find(|d| d.day == day)
+            .map(|d| d.usage)
+            .unwrap_or_default())
+    }
+}
+
+/// Keeps everything in memory; the default when no database is configured
+#[derive(Default)]
+pub struct MemoryStorage {
+    data: Mutex<MemoryData>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    rooms: HashMap<(String, String), RoomRecord>,
+    apps: BTreeMap<String, AppRecord>,
+    bans: BTreeMap<String, Ban>,
+    usage: BTreeMap<String, Usage>,
+}
+
+impl MemoryStorage {
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn rooms(&self) -> StorageResult<Vec<RoomRecord>> {
+        Ok(self.lock().rooms.values().cloned().collect())
+    }
+
+    fn save_room(&self, room: &RoomRecord) -> StorageResult<()> {
+        let key = (room.app_id.clone(), room.room_id.clone());
+        self.lock().rooms.insert(key, room.clone());
+        Ok(())
+    }
+
+    fn delete_room(&self, app_id: &str, room_id: &str) -> StorageResult<()> {
+        self.lock().rooms.remove(&(app_id.to_string(), room_id.to_string()));
+        Ok(())
+    }
+
+    fn apps(&self) -> StorageResult<Vec<AppRecord>> {
+        Ok(self.lock().apps.values().cloned().collect())
+    }
+
+    fn record_room_created(&self, app_id: &str, at: u64) -> StorageResult<()> {
+        let mut data = self.lock();
+        let app = data.apps.entry(app_id.to_string()).or_insert_with(|| AppRecord {
+            app_id: app_id.to_string(),
+            first_seen: at,
+            last_seen: at,
+            rooms_created: 0,
+        });
+        app.last_seen = at;
+        app.rooms_created += 1;
+        Ok(())
+    }
+
+    fn bans(&self) -> StorageResult<Vec<Ban>> {
+        Ok(self.lock().bans.values().cloned().collect())
+    }
+
+    fn save_ban(&self, ban: &Ban) -> StorageResult<()> {
+        self.lock().bans.insert(ban.ip.clone(), ban.clone());
+        Ok(())
+    }
+
+    fn delete_ban(&self, ip: &str) -> StorageResult<bool> {
+        Ok(self.lock().bans.remove(ip).is_some())
+    }
+
+    fn usage(&self, since: &str) -> StorageResult<Vec<DailyUsage>> {
+        Ok(self
+            .lock()
+            .usage
+            .range(since.to_string()..)
+            .map(|(day, usage)| DailyUsage {
+                day: day.clone(),
+                usage: *usage,
+            })
+            .collect())
+    }
+
+    fn set_usage(&self, day: &str, usage: Usage) -> StorageResult<()> {
+        self.lock().usage.insert(day.to_string(), usage);
+        Ok(())
+    }
+}
+
+/// A change to a stored reserved room
+pub enum RoomWrite {
+    Save(RoomRecord),
+    Delete { app_id: String, room_id: String },
+}
+
+/// Reserved-room writes waiting for storage. Handlers queue them while they
+/// hold a room's lock, and [`RoomWrites::run`] applies them in order on the
+/// blocking pool.
+pub struct RoomWrites {
+    storage: Arc<dyn Storage>,
+    queue: Mutex<VecDeque<RoomWrite>>,
+    /// Held while writes are applied, so a flush never overtakes the writer
+    applying: Mutex<()>,
+    queued: Notify,
+}
+
+impl RoomWrites {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            queue: Mutex::default(),
+            applying: Mutex::default(),
+            queued: Notify::new(),
+        }
+    }
+
+    pub fn save(&self, room: RoomRecord) {
+        self.push(RoomWrite::Save(room));
+    }
+
+    pub fn delete(&self, app_id: &str, room_id: &str) {
+        self.push(RoomWrite::Delete {
+            app_id: app_id.to_string(),
+            room_id: room_id.to_string(),
+        });
+    }
+
+    fn push(&self, write: RoomWrite) {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner()).push_back(write);
+        self.queued.notify_one();
+    }
+
+    /// Apply every write queued so far. Blocks on storage.
+    pub fn flush(&self) {
+        let _applying = self.applying.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let next = self.queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+            match next {
+                Some(RoomWrite::Save(room)) => logged("save_room", self.storage.save_room(&room)),
+                Some(RoomWrite::Delete { app_id, room_id }) => {
+                    logged("delete_room", self.storage.delete_room(&app_id, &room_id))
+                }
+                None => return,
+            };
+        }
+    }
+
+    /// Apply writes as they are queued, off the async workers
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            self.queued.notified().await;
+            let writes = self.clone();
+            let _ = tokio::task::spawn_blocking(move || writes.flush()).await;
+        }
+    }
+}
+
+/// Log a failed storage call and carry on without its result
+pub fn logged<T>(op: &'static str, result: StorageResult<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!(event = "storage_failed", op = op, error = %e, "Storage call failed");
+            None
+        }
+    }
+}
+
+/// UTC calendar day of a Unix millisecond timestamp, as `YYYY-MM-DD`
+pub fn utc_day(millis: u64) -> String {
+    // Civil-from-days, counting from 0000-03-01 so leap days fall last
+    let days = (millis / 86_400_000) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86_400_000;
+
+    #[test]
+    fn utc_days() {
+        assert_eq!(utc_day(0), "1970-01-01");
+        assert_eq!(utc_day(DAY - 1), "1970-01-01");
+        assert_eq!(utc_day(DAY), "1970-01-02");
+        // Leap days, including the century rule
+        assert_eq!(utc_day(951_782_400_000), "2000-02-29");
+        assert_eq!(utc_day(951_782_400_000 + DAY), "2000-03-01");
+        assert_eq!(utc_day(1_709_164_800_000), "2024-02-29");
+        assert_eq!(utc_day(4_107_542_400_000), "2100-03-01");
+        assert_eq!(utc_day(4_107_542_400_000 - DAY), "2100-02-28");
+        assert_eq!(utc_day(1_735_689_599_999), "2024-12-31");
+        assert_eq!(utc_day(1_735_689_600_000), "2025-01-01");
+    }
+
+    #[test]
+    fn room_writes_apply_in_order() {
+        let storage = Arc::new(MemoryStorage::default());
+        let writes = RoomWrites::new(storage.clone());
+        let room = RoomRecord {
+            app_id: "app".to_string(),
+            room_id: "room".to_string(),
+            password_hash: None,
+            max_peers: 6,
+            topology: Topology::Mesh,
+            adaptive: false,
+            stage: None,
+            host_key: "key".to_string(),
+            schedule: None,
+            idle_since: 0,
+        };
+        writes.save(room.clone());
+        writes.delete("app", "room");
+        writes.save(room);
+        assert!(storage.rooms().unwrap().is_empty());
+        writes.flush();
+        assert_eq!(storage.rooms().unwrap().len(), 1);
+    }
+}
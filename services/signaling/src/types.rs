@@ -1,57 +1,171 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct SignalMessage {
-    pub from: String,
-    #[serde(flatten)]
-    pub payload: SignalPayload,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "lowercase")]
-pub enum SignalPayload {
-    /// Client sends hello on connect with version info
-    Hello { version: String, client: String },
-    /// Server responds with welcome and assigned peer_id
-    Welcome { version: String, peer_id: String },
-    Join { peer_id: String },
-    Leave { peer_id: String },
-    Offer { to: String, sdp: String },
-    Answer { to: String, sdp: String },
-    Ice { to: String, candidate: String },
-    Mute { muted: bool },
-    Ping,
-    Pong,
-    Error { code: String, message: String },
-}
-
-#[derive(Serialize)]
+use crate::chat::ChatMessage;
+use crate::peer::PeerState;
+use crate::protocol::Topology;
+use crate::storage::{AppRecord, Ban, DailyUsage};
+use crate::webhooks::DeadLetter;
+
+pub use voca_protocol::message::{SignalMessage, SignalPayload};
+
+#[derive(Serialize, JsonSchema)]
 pub struct CreateRoomResponse {
     pub room: String,
+    pub topology: Topology,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    /// Reserved rooms only: connect with it to take the host role, or pass it
+    /// to delete the room
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_key: Option<String>,
+    /// Unix milliseconds before which joins are refused, for scheduled rooms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opens_at: Option<u64>,
+    /// Unix milliseconds when the room closes, if it has an end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closes_at: Option<u64>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
+pub struct CheckRoomResponse {
+    pub exists: bool,
+    pub peers: usize,
+    pub capacity: usize,
+    pub full: bool,
+    pub password_required: bool,
+    pub topology: Topology,
+    /// Kept while empty until deleted or idle for the reserved-room TTL
+    pub reserved: bool,
+    /// False until a scheduled room's start time
+    pub open: bool,
+    /// Unix milliseconds when a room that isn't open yet opens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opens_at: Option<u64>,
+    /// Unix milliseconds when the room closes, if it has an end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closes_at: Option<u64>,
+    /// Speakers and listeners allowed in a stage room
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_speakers: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_listeners: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct RoomInfo {
     pub id: String,
     pub app_id: String,
     pub peers: usize,
     pub capacity: usize,
+    pub topology: Topology,
+    pub reserved: bool,
+}
+
+/// Reply to `POST /api/room/{room}/bots`
+#[derive(Serialize, JsonSchema)]
+pub struct BotJoinResponse {
+    /// The bot's peer id, used in the rest of the bot API
+    pub peer_id: String,
+    /// Everyone already in the room, by peer id
+    pub peers: BTreeMap<String, PeerState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Recent chat history, oldest first
+    pub chat: Vec<ChatMessage>,
+    /// Unix milliseconds when the room closes, if it has an end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closes_at: Option<u64>,
+}
+
+/// Reply to a long-poll of `GET /api/room/{room}/bots/{id}/events`
+#[derive(Serialize, JsonSchema)]
+pub struct BotEventsResponse {
+    /// Oldest first; empty if nothing happened while waiting
+    pub events: Vec<SignalMessage>,
+    /// Events dropped because the bot fell behind
+    pub missed: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct AdminRoomsResponse {
     pub rooms: Vec<RoomInfo>,
     pub total_rooms: usize,
     pub max_rooms: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
+pub struct AdminLogsResponse {
+    pub logs: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AdminBansResponse {
+    pub bans: Vec<Ban>,
+}
+
+/// Body of `POST /api/admin/bans`
+#[derive(Deserialize, JsonSchema)]
+pub struct BanRequest {
+    pub ip: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AdminAppsResponse {
+    pub apps: Vec<AppRecord>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AdminUsageResponse {
+    /// Oldest first; days without activity are left out
+    pub days: Vec<DailyUsage>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AdminWebhooksResponse {
+    /// Events queued or waiting to be retried
+    pub pending: usize,
+    /// Events that ran out of attempts, oldest first
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+/// Reply to `POST /api/admin/webhooks/retry`
+#[derive(Serialize, JsonSchema)]
+pub struct WebhookRetryResponse {
+    /// Dead letters queued for delivery again
+    pub queued: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct MetricsResponse {
     pub active_rooms: usize,
     pub active_connections: usize,
     pub rooms_created_today: u64,
     pub connections_today: u64,
+    pub origin_rejections: u64,
+    pub uptime_seconds: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct HealthResponse {
+    pub status: &'static str,
+    pub version: &'static str,
     pub uptime_seconds: u64,
 }
+
+#[derive(Serialize, JsonSchema)]
+pub struct ReadinessResponse {
+    pub status: &'static str,
+    pub version: &'static str,
+    pub uptime_seconds: u64,
+    pub accepting_rooms: bool,
+    pub draining: bool,
+    /// Whether the storage backend (the SQLite database, or memory when none
+    /// is configured) answered a ping. This is the only dependency checked.
+    pub storage_ok: bool,
+    pub active_rooms: usize,
+    pub max_rooms: usize,
+}
@@ -0,0 +1,108 @@
+//! Waitlist for full rooms.
+//!
+//! A client connecting with `?wait=true` to a full room is queued instead of
+//! being turned away. It stays connected, hears its position whenever it
+//! changes, and is admitted automatically once a peer leaves. New arrivals
+//! never jump the queue: while anyone is waiting the room counts as full.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::watch;
+
+// === WAITLIST LIMITS ===
+/// Most clients queued for one room; 0 turns the waitlist off
+pub const MAX_WAITLIST: usize = 20;
+/// How long a client may wait before giving up
+pub const WAITLIST_TIMEOUT: Duration = Duration::from_secs(300);
+
+struct Waiter {
+    peer_id: String,
+    position: watch::Sender<usize>,
+}
+
+/// Clients waiting for a slot, earliest first
+#[derive(Default)]
+pub struct Waitlist {
+    waiters: VecDeque<Waiter>,
+}
+
+impl Waitlist {
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    /// Queue a client at the back. The receiver yields its 1-based position
+    /// and wakes again whenever the position changes or a slot frees up.
+    pub fn push(&mut self, peer_id: &str) -> watch::Receiver<usize> {
+        let (position, rx) = watch::channel(self.waiters.len() + 1);
+        self.waiters.push_back(Waiter {
+            peer_id: peer_id.to_string(),
+            position,
+        });
+        rx
+    }
+
+    /// Whether a client is first in line
+    pub fn is_next(&self, peer_id: &str) -> bool {
+        self.waiters.front().is_some_and(|w| w.peer_id == peer_id)
+    }
+
+    /// Take a client out of the queue, moving everyone behind it up
+    pub fn remove(&mut self, peer_id: &str) {
+        let Some(index) = self.waiters.iter().position(|w| w.peer_id == peer_id) else {
+            return;
+        };
+        self.waiters.remove(index);
+        for (offset, waiter) in self.waiters.iter().enumerate().skip(index) {
+            let _ = waiter.position.send(offset + 1);
+        }
+    }
+
+    /// Tell the first client a slot may have freed up
+    pub fn wake_next(&self) {
+        if let Some(waiter) = self.waiters.front() {
+            let _ = waiter.position.send(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queues_in_arrival_order() {
+        let mut waitlist = Waitlist::default();
+        let a = waitlist.push("a");
+        let b = waitlist.push("b");
+        let c = waitlist.push("c");
+        assert_eq!((*a.borrow(), *b.borrow(), *c.borrow()), (1, 2, 3));
+        assert!(waitlist.is_next("a") && !waitlist.is_next("b"));
+
+        // Everyone behind a leaver moves up; those ahead don't hear about it
+        waitlist.remove("b");
+        assert!(!a.has_changed().unwrap());
+        assert_eq!(*c.borrow(), 2);
+        waitlist.remove("a");
+        assert!(waitlist.is_next("c"));
+        assert_eq!((*c.borrow(), waitlist.len()), (1, 1));
+    }
+
+    #[test]
+    fn wakes_only_the_first() {
+        let mut waitlist = Waitlist::default();
+        let mut a = waitlist.push("a");
+        let mut b = waitlist.push("b");
+        a.mark_unchanged();
+        b.mark_unchanged();
+        waitlist.wake_next();
+        assert!(a.has_changed().unwrap());
+        assert!(!b.has_changed().unwrap());
+        waitlist.remove("unknown");
+        assert_eq!(waitlist.len(), 2);
+    }
+}
@@ -0,0 +1,461 @@
+//! Room and peer lifecycle webhooks.
+//!
+//! Apps with a webhook URL are sent a signed JSON event whenever one of their
+//! rooms is created or ends, a peer joins or leaves, or a peer is turned away
+//! from a full room. Events go onto a queue and are delivered in the
+//! background, so a slow or failing endpoint never holds up a call. Failed
+//! deliveries are retried with exponential backoff; those that run out of
+//! attempts land in a dead-letter list the admin API can inspect and replay.
+
+use hmac::{Hmac, Mac};
+use nanoid::nanoid;
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{info, warn};
+
+use crate::config::parse_app_map;
+use crate::state::{now_millis, RoomKey};
+
+/// Events waiting for their first attempt; more are dead-lettered straight away
+pub const QUEUE_CAPACITY: usize = 1024;
+/// Events being delivered or retried at once; the rest wait in the queue
+pub const MAX_CONCURRENT_DELIVERIES: usize = 32;
+/// Delivery attempts before an event is dead-lettered, by default
+pub const MAX_ATTEMPTS: u32 = 6;
+/// Undeliverable events kept for the admin API; the oldest are dropped
+pub const DEAD_LETTER_CAPACITY: usize = 500;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What happened
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub enum WebhookEventType {
+    #[serde(rename = "room.created")]
+    RoomCreated,
+    #[serde(rename = "room.ended")]
+    RoomEnded,
+    #[serde(rename = "peer.joined")]
+    PeerJoined,
+    #[serde(rename = "peer.left")]
+    PeerLeft,
+    /// A peer was turned away because the room (and its waitlist) was full
+    #[serde(rename = "room.full")]
+    RoomFull,
+}
+
+impl WebhookEventType {
+    /// Wire name, as sent in `type` and `x-voca-event`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::RoomCreated => "room.created",
+            Self::RoomEnded => "room.ended",
+            Self::PeerJoined => "peer.joined",
+            Self::PeerLeft => "peer.left",
+            Self::RoomFull => "room.full",
+        }
+    }
+}
+
+/// Why a room ended
+#[derive(Clone, Copy, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomEndReason {
+    /// Its last peer left
+    Empty,
+    /// Its scheduled end or duration limit arrived
+    Closed,
+    /// A reserved room was deleted through the API
+    Deleted,
+    /// It sat empty past its grace period
+    Idle,
+}
+
+/// Body of every webhook request
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct WebhookEvent {
+    /// Unique per event and kept across retries, for deduplication
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: WebhookEventType,
+    pub app_id: String,
+    pub room_id: String,
+    /// The peer that joined, left or was turned away
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    /// Set on `room.ended`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<RoomEndReason>,
+    /// Unix milliseconds when it happened
+    pub timestamp: u64,
+}
+
+impl WebhookEvent {
+    pub fn new(kind: WebhookEventType, key: &RoomKey) -> Self {
+        Self {
+            id: nanoid!(16),
+            kind,
+            app_id: key.app_id.clone(),
+            room_id: key.room_id.clone(),
+            peer_id: None,
+            reason: None,
+            timestamp: now_millis(),
+        }
+    }
+
+    pub fn peer(kind: WebhookEventType, key: &RoomKey, peer_id: &str) -> Self {
+        Self {
+            peer_id: Some(peer_id.to_string()),
+            ..Self::new(kind, key)
+        }
+    }
+
+    pub fn ended(key: &RoomKey, reason: RoomEndReason) -> Self {
+        Self {
+            reason: Some(reason),
+            ..Self::new(WebhookEventType::RoomEnded, key)
+        }
+    }
+}
+
+/// An event that couldn't be delivered
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct DeadLetter {
+    pub event: WebhookEvent,
+    pub url: String,
+    pub attempts: u32,
+    /// Why the last attempt failed
+    pub error: String,
+    /// Unix milliseconds
+    pub failed_at: u64,
+}
+
+/// Where an app's events go and the secret they're signed with. Every
+/// event is signed, so there is no endpoint without a secret.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    pub url: String,
+    pub secret: String,
+}
+
+impl Endpoint {
+    pub fn new(url: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            secret: secret.into(),
+        }
+    }
+}
+
+/// Which endpoints get events, configured globally and per app_id. The
+/// default has none, so every event is dropped.
+#[derive(Clone, Debug)]
+pub struct WebhookConfig {
+    /// For apps without an endpoint of their own
+    pub global: Option<Endpoint>,
+    pub per_app: HashMap<String, Endpoint>,
+    /// Delivery attempts before an event is dead-lettered
+    pub max_attempts: u32,
+}
+
+impl WebhookConfig {
+    /// Read the endpoints from the environment:
+    /// - `VOCA_WEBHOOK_URL` / `VOCA_WEBHOOK_SECRET`: for every app
+    /// - `VOCA_APP_WEBHOOK_URLS`: `app=url;other=url`
+    /// - `VOCA_APP_WEBHOOK_SECRETS`: `app=secret;other=secret`, falling back
+    ///   to the global secret
+    /// - `VOCA_WEBHOOK_MAX_ATTEMPTS`
+    ///
+    /// Fails if an endpoint has no secret to sign its events with.
+    pub fn from_env() -> Result<Self, String> {
+        let secret = std::env::var("VOCA_WEBHOOK_SECRET").ok().filter(|s| !s.is_empty());
+        let global = match std::env::var("VOCA_WEBHOOK_URL").ok().map(|url| url.trim().to_string()) {
+            Some(url) if !url.is_empty() => {
+                let secret = secret.clone().ok_or("VOCA_WEBHOOK_URL is set without VOCA_WEBHOOK_SECRET")?;
+                Some(Endpoint::new(url, secret))
+            }
+            _ => None,
+        };
+
+        let mut secrets = parse_app_map("VOCA_APP_WEBHOOK_SECRETS");
+        secrets.retain(|_, secret| !secret.is_empty());
+        let per_app = parse_app_map("VOCA_APP_WEBHOOK_URLS")
+            .into_iter()
+            .filter(|(_, url)| !url.is_empty())
+            .map(|(app, url)| match secrets.get(&app).or(secret.as_ref()) {
+                Some(secret) => Ok((app, Endpoint::new(url, secret))),
+                None => Err(format!(
+                    "webhook endpoint for app {} has no secret in VOCA_APP_WEBHOOK_SECRETS or VOCA_WEBHOOK_SECRET",
+                    app
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let max_attempts = std::env::var("VOCA_WEBHOOK_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(MAX_ATTEMPTS);
+
+        Ok(Self {
+            global,
+            per_app,
+            max_attempts,
+        })
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            global: None,
+            per_app: HashMap::new(),
+            max_attempts: MAX_ATTEMPTS,
+        }
+    }
+}
+
+/// One event on its way to one endpoint
+pub struct Delivery {
+    event: WebhookEvent,
+    endpoint: Endpoint,
+}
+
+/// The configured endpoints and the state of their deliveries
+pub struct Webhooks {
+    global: Option<Endpoint>,
+    per_app: HashMap<String, Endpoint>,
+    max_attempts: u32,
+    client: reqwest::Client,
+    queue: mpsc::Sender<Delivery>,
+    /// Events queued or between retries
+    pending: AtomicUsize,
+    dead_letters: Mutex<VecDeque<DeadLetter>>,
+}
+
+impl Webhooks {
+    /// Deliver to `config`'s endpoints, returning the queue for `run` to
+    /// deliver from
+    pub fn new(config: WebhookConfig) -> (Self, mpsc::Receiver<Delivery>) {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        let (queue, deliveries) = mpsc::channel(QUEUE_CAPACITY);
+        let webhooks = Self {
+            global: config.global,
+            per_app: config.per_app,
+            max_attempts: config.max_attempts.max(1),
+            client,
+            queue,
+            pending: AtomicUsize::new(0),
+            dead_letters: Mutex::new(VecDeque::new()),
+        };
+        (webhooks, deliveries)
+    }
+
+    /// Number of endpoints configured
+    pub fn endpoint_count(&self) -> usize {
+        self.global.iter().count() + self.per_app.len()
+    }
+
+    /// Queue an event for its app's endpoint, if it has one
+    pub fn emit(&self, event: WebhookEvent) {
+        let Some(endpoint) = self.per_app.get(&event.app_id).or(self.global.as_ref()) else {
+            return;
+        };
+        let delivery = Delivery {
+            event,
+            endpoint: endpoint.clone(),
+        };
+        self.enqueue(delivery);
+    }
+
+    fn enqueue(&self, delivery: Delivery) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self.queue.try_send(delivery) {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            let delivery = match e {
+                mpsc::error::TrySendError::Full(d) | mpsc::error::TrySendError::Closed(d) => d,
+            };
+            self.dead_letter(delivery, 0, "queue full".to_string());
+        }
+    }
+
+    /// Events queued or waiting to be retried
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Undeliverable events, oldest first
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.lock_dead_letters().iter().cloned().collect()
+    }
+
+    /// Empty the dead-letter list, returning how many were dropped
+    pub fn clear_dead_letters(&self) -> usize {
+        let mut dead = self.lock_dead_letters();
+        let count = dead.len();
+        dead.clear();
+        count
+    }
+
+    /// Queue every dead letter for another full round of attempts, returning
+    /// how many were queued. Letters for apps that no longer have an endpoint
+    /// are dropped.
+    pub fn retry_dead_letters(&self) -> usize {
+        let dead: Vec<DeadLetter> = self.lock_dead_letters().drain(..).collect();
+        let mut count = 0;
+        for letter in dead {
+            // Sent to the app's current endpoint in case the URL was fixed
+            let Some(endpoint) = self.per_app.get(&letter.event.app_id).or(self.global.as_ref()) else {
+                info!(
+                    event = "webhook_dead_letter_dropped",
+                    event_id = letter.event.id,
+                    app_id = letter.event.app_id,
+                    "Dead letter dropped, its app has no webhook endpoint"
+                );
+                continue;
+            };
+            self.enqueue(Delivery {
+                event: letter.event,
+                endpoint: endpoint.clone(),
+            });
+            count += 1;
+        }
+        count
+    }
+
+    /// Deliver queued events until the server shuts down. Up to
+    /// [`MAX_CONCURRENT_DELIVERIES`] events are delivered at once, each on its
+    /// own task, so one slow endpoint doesn't delay the rest and events for
+    /// the same room may arrive out of order; use `timestamp` to order them.
+    pub async fn run(self: Arc<Self>, mut deliveries: mpsc::Receiver<Delivery>) {
+        let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_DELIVERIES));
+        while let Some(delivery) = deliveries.recv().await {
+            // Waiting here leaves events in the queue, which dead-letters
+            // new ones once full
+            let Ok(permit) = permits.clone().acquire_owned().await else {
+                return;
+            };
+            let webhooks = self.clone();
+            tokio::spawn(async move {
+                webhooks.deliver(delivery).await;
+                drop(permit);
+            });
+        }
+    }
+
+    async fn deliver(&self, delivery: Delivery) {
+        let body = match serde_json::to_string(&delivery.event) {
+            Ok(body) => body,
+            Err(e) => {
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+                self.dead_letter(delivery, 0, e.to_string());
+                return;
+            }
+        };
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match self.post(&delivery, &body).await {
+                Ok(()) => {
+                    self.pending.fetch_sub(1, Ordering::Relaxed);
+                    info!(
+                        event = "webhook_delivered",
+                        webhook_event = delivery.event.kind.as_str(),
+                        event_id = delivery.event.id,
+                        app_id = delivery.event.app_id,
+                        attempts = attempt,
+                        "Webhook delivered"
+                    );
+                    return;
+                }
+                Err(error) if attempt >= self.max_attempts => {
+                    self.pending.fetch_sub(1, Ordering::Relaxed);
+                    self.dead_letter(delivery, attempt, error);
+                    return;
+                }
+                Err(error) => {
+                    warn!(
+                        event = "webhook_retry",
+                        event_id = delivery.event.id,
+                        app_id = delivery.event.app_id,
+                        attempt = attempt,
+                        retry_in_ms = backoff.as_millis() as u64,
+                        error = error,
+                        "Webhook delivery failed, retrying"
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// One attempt; any 2xx response counts as delivered
+    async fn post(&self, delivery: &Delivery, body: &str) -> Result<(), String> {
+        // Signed afresh each attempt so receivers can reject stale timestamps
+        let timestamp = now_millis().to_string();
+        let request = self
+            .client
+            .post(&delivery.endpoint.url)
+            .header("content-type", "application/json")
+            .header("x-voca-event", delivery.event.kind.as_str())
+            .header("x-voca-delivery", &delivery.event.id)
+            .header("x-voca-timestamp", &timestamp)
+            .header("x-voca-signature", sign(&delivery.endpoint.secret, &timestamp, body));
+
+        let response = request.body(body.to_string()).send().await.map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("endpoint responded {}", response.status()))
+        }
+    }
+
+    fn dead_letter(&self, delivery: Delivery, attempts: u32, error: String) {
+        warn!(
+            event = "webhook_dead_lettered",
+            event_id = delivery.event.id,
+            app_id = delivery.event.app_id,
+            attempts = attempts,
+            error = error,
+            "Webhook undeliverable"
+        );
+        let mut dead = self.lock_dead_letters();
+        if dead.len() >= DEAD_LETTER_CAPACITY {
+            dead.pop_front();
+        }
+        dead.push_back(DeadLetter {
+            event: delivery.event,
+            url: delivery.endpoint.url,
+            attempts,
+            error,
+            failed_at: now_millis(),
+        });
+    }
+
+    fn lock_dead_letters(&self) -> std::sync::MutexGuard<'_, VecDeque<DeadLetter>> {
+        self.dead_letters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// `x-voca-signature` value: `sha256=` and the hex HMAC-SHA256 of
+/// `{timestamp}.{body}` keyed with the endpoint's secret
+pub fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
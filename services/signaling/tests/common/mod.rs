@@ -0,0 +1,72 @@
+//! Helpers shared by the integration tests here and in `voca-client`: an
+//! in-process server on a loopback port, REST calls against its router and
+//! waiting on client events. Each test binary uses some of them.
+#![allow(dead_code)]
+
+use axum::{body::Body, extract::ConnectInfo, http::request, http::Request, response::Response};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+use signaling::server::{Server, ServerBuilder};
+use voca_client::{Event, Events, Welcome};
+
+/// Build the server and serve it on a free loopback port, returning its
+/// `ws://` base URL
+pub async fn start(builder: ServerBuilder) -> (Server, String) {
+    let server = builder.build().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let app = server.router.clone().into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (server, url)
+}
+
+/// A request from a fixed loopback client, as the router sees it
+pub fn loopback(method: &str, uri: &str) -> request::Builder {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+}
+
+/// Send a request straight to the router
+pub async fn call(server: &Server, request: Request<Body>) -> Response {
+    server.router.clone().oneshot(request).await.unwrap()
+}
+
+pub async fn json(response: Response) -> serde_json::Value {
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// Create a room with the given query string and return its id
+pub async fn create_room(server: &Server, query: &str) -> String {
+    let response = call(server, loopback("POST", &format!("/api/room{}", query)).body(Body::empty()).unwrap()).await;
+    assert!(response.status().is_success(), "create room: {}", response.status());
+    json(response).await["room"].as_str().unwrap().to_string()
+}
+
+/// The next event `matches` accepts, skipping the rest
+pub async fn expect(events: &mut Events, matches: impl Fn(&Event) -> bool) -> Event {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = events.recv().await.expect("events ended");
+            if matches(&event) {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an event")
+}
+
+/// Wait for the welcome, failing if the session ends first
+pub async fn welcome(events: &mut Events) -> Welcome {
+    match expect(events, |e| matches!(e, Event::Welcome(_) | Event::Closed { .. })).await {
+        Event::Welcome(welcome) => welcome,
+        Event::Closed { error } => panic!("closed before the welcome: {:?}", error),
+        _ => unreachable!(),
+    }
+}
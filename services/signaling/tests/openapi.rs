@@ -0,0 +1,40 @@
+//! The OpenAPI document is written by hand in `codegen.rs`; check it lists
+//! exactly the routes the server serves, with the same methods.
+
+use axum::{body::Body, extract::ConnectInfo, http::Request, http::StatusCode};
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+use signaling::codegen::openapi;
+use signaling::server::{route_paths, ServerBuilder, ServerConfig};
+
+const METHODS: &[&str] = &["get", "post", "put", "patch", "delete"];
+
+#[tokio::test]
+async fn openapi_matches_router() {
+    let doc = openapi();
+    let documented = doc["paths"].as_object().unwrap();
+
+    let paths: BTreeSet<String> = route_paths().into_iter().map(String::from).collect();
+    let documented_paths: BTreeSet<String> = documented.keys().cloned().collect();
+    assert_eq!(paths, documented_paths, "routes and OpenAPI paths differ");
+
+    let server = ServerBuilder::new(ServerConfig::default()).build().unwrap();
+    let addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+    for path in &paths {
+        for method in METHODS {
+            let request = Request::builder()
+                .method(method.to_uppercase().as_str())
+                .uri(path.as_str())
+                .extension(ConnectInfo(addr))
+                .body(Body::empty())
+                .unwrap();
+            let response = server.router.clone().oneshot(request).await.unwrap();
+            let routed = response.status() != StatusCode::METHOD_NOT_ALLOWED;
+            let listed = documented[path].get(*method).is_some();
+            assert_eq!(routed, listed, "{} {} routed: {}, documented: {}", method, path, routed, listed);
+        }
+    }
+    server.shutdown();
+}
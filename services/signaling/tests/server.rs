@@ -0,0 +1,633 @@
+//! A server built the way hosts embed it, exercised over HTTP and WebSocket.
+
+mod common;
+
+use axum::{body::Body, http::StatusCode, response::Response};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use signaling::bots::MAX_STAGE_BOTS;
+use signaling::origin::OriginPolicy;
+use signaling::schedule::SchedulePolicy;
+use signaling::server::{Server, ServerBuilder, ServerConfig};
+use signaling::storage::{AppRecord, Ban, DailyUsage, MemoryStorage, RoomRecord, Storage, StorageResult, Usage};
+use voca_client::{Client, Config, ErrorCode, Event, Events, NegotiationRole, PeerState, SignalMessage, SignalPayload};
+
+use common::{call, create_room, expect, json, loopback, start, welcome};
+
+async fn request(server: &Server, method: &str, uri: &str, token: Option<&str>) -> Response {
+    send(server, method, uri, token, Body::empty()).await
+}
+
+async fn send(server: &Server, method: &str, uri: &str, token: Option<&str>, body: Body) -> Response {
+    let mut request = loopback(method, uri).header("content-type", "application/json");
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    call(server, request.body(body).unwrap()).await
+}
+
+#[tokio::test]
+async fn create_and_join_a_room() {
+    let storage = Arc::new(MemoryStorage::default());
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default()).storage(storage.clone())).await;
+
+    let response = request(&server, "POST", "/api/room?persistent=true", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let created = json(response).await;
+    let room = created["room"].as_str().unwrap().to_string();
+    assert!(created["host_key"].is_string());
+    // Reserved rooms are kept in the builder's storage
+    server.state.room_writes.flush();
+    let stored = storage.rooms().unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].room_id, room);
+
+    let (first, mut first_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let first_welcome = welcome(&mut first_events).await;
+    assert!(first_welcome.peers.is_empty());
+
+    let (second, mut second_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let second_welcome = welcome(&mut second_events).await;
+    assert!(second_welcome.peers.contains_key(&first_welcome.peer_id));
+
+    let check = json(request(&server, "GET", &format!("/api/room/{}", room), None).await).await;
+    assert_eq!(check["exists"], true);
+    assert_eq!(check["peers"], 2);
+
+    first.close();
+    second.close();
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn room_ids_are_validated_and_unique() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let own = format!("http://{}", url.trim_start_matches("ws://"));
+
+    // Creation is rate limited, so this stays within the burst
+    for (id, code) in [("abc", "invalid_room_id_length"), ("bad_room", "invalid_room_id_chars")] {
+        let response = request(&server, "POST", &format!("/api/room?room={}", id), None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", id);
+        assert_eq!(json(response).await["code"], code, "{}", id);
+        let response = request(&server, "GET", &format!("/api/room/{}", id), None).await;
+        assert_eq!(json(response).await["code"], code, "{}", id);
+        assert!(upgrade_status(&url, &format!("/ws/{}", id), &own).await.contains("400"), "{}", id);
+    }
+    let response = request(&server, "GET", &format!("/api/room/{}", "a".repeat(33)), None).await;
+    assert_eq!(json(response).await["code"], "invalid_room_id_length");
+
+    assert_eq!(create_room(&server, "?room=team-sync").await, "team-sync");
+    let response = request(&server, "POST", "/api/room?room=team-sync", None).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(json(response).await["code"], "room_id_taken");
+    // IDs are unique per app, not across apps
+    assert_eq!(create_room(&server, "?room=team-sync&appId=other").await, "team-sync");
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn deleting_a_room_takes_its_host_key() {
+    let (server, _) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let created = json(request(&server, "POST", "/api/room?persistent=true", None).await).await;
+    let room = format!("/api/room/{}", created["room"].as_str().unwrap());
+    let host_key = created["host_key"].as_str().unwrap();
+
+    let delete = |key: Option<&str>| {
+        let mut request = loopback("DELETE", &room);
+        if let Some(key) = key {
+            request = request.header("x-host-key", key);
+        }
+        call(&server, request.body(Body::empty()).unwrap())
+    };
+    for key in [None, Some("wrong"), Some(&host_key[1..])] {
+        let response = delete(key).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{:?}", key);
+        assert_eq!(json(response).await["code"], "unauthorized");
+    }
+    assert_eq!(delete(Some(host_key)).await.status(), StatusCode::NO_CONTENT);
+    let response = request(&server, "GET", &room, None).await;
+    assert_eq!(json(response).await["code"], "room_not_found");
+
+    // Rooms nobody reserved close on their own and have no key
+    let room = format!("/api/room/{}", create_room(&server, "").await);
+    let response = call(&server, loopback("DELETE", &room).header("x-host-key", host_key).body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(json(response).await["code"], "not_permitted");
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn admin_api_needs_a_configured_token() {
+    let (server, _) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let response = request(&server, "GET", "/api/admin/rooms", Some("changeme")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    server.shutdown();
+
+    let config = ServerConfig {
+        admin_token: Some("secret".to_string()),
+        ..ServerConfig::default()
+    };
+    let (server, _) = start(ServerBuilder::new(config)).await;
+    let response = request(&server, "GET", "/api/admin/rooms", Some("wrong")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = request(&server, "GET", "/api/admin/rooms", Some("secret")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn stage_rooms_cap_bots() {
+    let (server, _) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let created = json(request(&server, "POST", "/api/room?stage=true", None).await).await;
+    let bots = format!("/api/room/{}/bots", created["room"].as_str().unwrap());
+
+    for _ in 0..MAX_STAGE_BOTS {
+        let response = send(&server, "POST", &bots, None, Body::from("{}")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    let response = send(&server, "POST", &bots, None, Body::from("{}")).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(json(response).await["code"], "room_full");
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn duration_limits_end_sessions_not_reservations() {
+    let storage = Arc::new(MemoryStorage::default());
+    let config = ServerConfig {
+        schedule: SchedulePolicy::default().with_max_duration(1),
+        ..ServerConfig::default()
+    };
+    let (server, url) = start(ServerBuilder::new(config).storage(storage.clone())).await;
+    let created = json(request(&server, "POST", "/api/room?persistent=true", None).await).await;
+    let room = created["room"].as_str().unwrap().to_string();
+    // Nobody is in it, so nothing is timed yet
+    assert!(created["closes_at"].is_null());
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let (_client, mut events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let first = welcome(&mut events).await.closes_at.expect("a session end");
+    let closed = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Event::Closed { error } = events.recv().await.expect("events ended") {
+                return error;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the session to end");
+    assert_eq!(closed.map(|e| e.code), Some(ErrorCode::RoomClosed));
+
+    // The reservation outlives the session
+    let check = json(request(&server, "GET", &format!("/api/room/{}", room), None).await).await;
+    assert_eq!((check["exists"].as_bool(), check["peers"].as_u64()), (Some(true), Some(0)));
+    server.state.room_writes.flush();
+    assert_eq!(storage.rooms().unwrap().len(), 1);
+
+    let (client, mut events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let second = welcome(&mut events).await.closes_at.expect("a session end");
+    assert!(second > first, "{} then {}", first, second);
+
+    client.close();
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn forged_joins_and_leaves_are_dropped() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let room = create_room(&server, "").await;
+
+    let (alice, mut alice_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let alice_id = welcome(&mut alice_events).await.peer_id;
+    let (mallory, mut mallory_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    welcome(&mut mallory_events).await;
+
+    let ghost = PeerState {
+        bot: true,
+        ..PeerState::default()
+    };
+    mallory
+        .send(SignalPayload::Join {
+            peer_id: "ghost".to_string(),
+            state: Some(ghost),
+            role: Some(NegotiationRole::Polite),
+        })
+        .unwrap();
+    mallory.send(SignalPayload::Leave { peer_id: alice_id }).unwrap();
+    mallory.chat("done").unwrap();
+
+    // Mallory's real join, then the chat sent after the forgeries
+    let seen = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut seen = Vec::new();
+        loop {
+            match alice_events.recv().await.expect("events ended") {
+                Event::Chat(_) => return seen,
+                Event::PeerJoined { peer_id, .. } => seen.push(format!("joined {}", peer_id)),
+                Event::PeerLeft { peer_id } => seen.push(format!("left {}", peer_id)),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the chat");
+    assert_eq!(seen.len(), 1, "{:?}", seen);
+    assert!(!seen[0].contains("ghost"), "{:?}", seen);
+
+    alice.close();
+    mallory.close();
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn bot_event_streams_end_when_the_bot_leaves() {
+    let (server, _) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let created = json(request(&server, "POST", "/api/room", None).await).await;
+    let bots = format!("/api/room/{}/bots", created["room"].as_str().unwrap());
+    let joined = json(send(&server, "POST", &bots, None, Body::from("{}")).await).await;
+    let bot = format!("{}/{}", bots, joined["peer_id"].as_str().unwrap());
+    // Another bot keeps the room open
+    let response = send(&server, "POST", &bots, None, Body::from("{}")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let subscribe = loopback("GET", &format!("{}/events", bot))
+        .header("accept", "text/event-stream")
+        .body(Body::empty())
+        .unwrap();
+    let events = call(&server, subscribe).await;
+    assert_eq!(events.status(), StatusCode::OK);
+
+    let response = request(&server, "DELETE", &bot, None).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    // The room is still open, but the stream isn't
+    let body = tokio::time::timeout(Duration::from_secs(5), axum::body::to_bytes(events.into_body(), usize::MAX));
+    assert!(body.await.expect("stream still open after leaving").is_ok());
+
+    server.shutdown();
+}
+
+fn waiting(url: &str, room: &str) -> Config {
+    let mut config = Config::new(url, room);
+    config.waitlist = true;
+    config
+}
+
+#[tokio::test]
+async fn waitlist_admits_in_order() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let room = create_room(&server, "?max_peers=1").await;
+
+    let (first, mut first_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    welcome(&mut first_events).await;
+    let (second, mut second_events) = Client::connect(waiting(&url, &room)).await.unwrap();
+    let event = expect(&mut second_events, |e| matches!(e, Event::Waiting { .. })).await;
+    assert!(matches!(event, Event::Waiting { position: 1 }), "{:?}", event);
+    let (_third, mut third_events) = Client::connect(waiting(&url, &room)).await.unwrap();
+    let event = expect(&mut third_events, |e| matches!(e, Event::Waiting { .. })).await;
+    assert!(matches!(event, Event::Waiting { position: 2 }), "{:?}", event);
+
+    // Without waiting, a full room turns the client away
+    let (_refused, mut refused_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let Event::Closed { error } = expect(&mut refused_events, |e| matches!(e, Event::Closed { .. })).await else {
+        unreachable!()
+    };
+    assert_eq!(error.map(|e| e.code), Some(ErrorCode::RoomFull));
+
+    // Each leaver lets in the next in line, and the rest move up
+    first.close();
+    welcome(&mut second_events).await;
+    let event = expect(&mut third_events, |e| matches!(e, Event::Waiting { .. } | Event::Welcome(_))).await;
+    assert!(matches!(event, Event::Waiting { position: 1 }), "{:?}", event);
+    second.close();
+    welcome(&mut third_events).await;
+
+    server.shutdown();
+}
+
+/// Status line of a WebSocket upgrade sent from a browser on `origin`
+async fn upgrade_status(url: &str, path: &str, origin: &str) -> String {
+    let addr = url.trim_start_matches("ws://");
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nOrigin: {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+         Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        path, addr, origin
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = vec![0; 1024];
+    let read = stream.read(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response[..read]);
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[tokio::test]
+async fn disallowed_origins_are_refused() {
+    let config = ServerConfig {
+        origins: OriginPolicy::allow(&["https://app.example"]),
+        ..ServerConfig::default()
+    };
+    let (server, url) = start(ServerBuilder::new(config)).await;
+
+    let from = |method: &str, uri: &str, origin: &str| loopback(method, uri).header("origin", origin);
+
+    // Preflights only name allowed origins
+    let preflight = |origin: &str| {
+        from("OPTIONS", "/api/room", origin)
+            .header("access-control-request-method", "POST")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let allowed = call(&server, preflight("https://app.example")).await;
+    assert_eq!(allowed.headers()["access-control-allow-origin"], "https://app.example");
+    let refused = call(&server, preflight("https://evil.example")).await;
+    assert!(refused.headers().get("access-control-allow-origin").is_none());
+
+    // The handlers refuse the request itself, not just the browser's read of it
+    let create = from("POST", "/api/room", "https://evil.example").body(Body::empty()).unwrap();
+    let response = call(&server, create).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(json(response).await["code"], "origin_not_allowed");
+
+    let create = from("POST", "/api/room", "https://app.example").body(Body::empty()).unwrap();
+    let response = call(&server, create).await;
+    assert!(response.status().is_success());
+    let room = json(response).await["room"].as_str().unwrap().to_string();
+
+    let path = format!("/ws/{}", room);
+    assert!(upgrade_status(&url, &path, "https://evil.example").await.contains("403"));
+    assert!(upgrade_status(&url, &path, "https://app.example").await.contains("101"));
+    // The server's own pages are always allowed
+    let own = format!("http://{}", url.trim_start_matches("ws://"));
+    assert!(upgrade_status(&url, &path, &own).await.contains("101"));
+
+    server.shutdown();
+}
+
+/// The next protocol message `matches` accepts
+async fn message(events: &mut Events, matches: impl Fn(&SignalPayload) -> bool) -> SignalMessage {
+    match expect(events, |e| matches!(e, Event::Message(m) if matches(&m.payload))).await {
+        Event::Message(message) => message,
+        _ => unreachable!(),
+    }
+}
+
+/// The code of the next message the server turned down
+async fn refused(events: &mut Events) -> ErrorCode {
+    match expect(events, |e| matches!(e, Event::Warning(_))).await {
+        Event::Warning(error) => error.code,
+        _ => unreachable!(),
+    }
+}
+
+#[tokio::test]
+async fn single_speaker_mode_lets_only_the_holder_unmute() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let room = create_room(&server, "").await;
+
+    let (host, mut host_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let host_id = welcome(&mut host_events).await.peer_id;
+    let (alice, mut alice_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let alice_id = welcome(&mut alice_events).await.peer_id;
+    let (bob, mut bob_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let bob_id = welcome(&mut bob_events).await.peer_id;
+
+    // Turning the mode on mutes everyone without the floor
+    host.send(SignalPayload::SingleSpeaker { enabled: true }).unwrap();
+    let mut muted = Vec::new();
+    for _ in 0..3 {
+        let message = message(&mut bob_events, |p| matches!(p, SignalPayload::ForceMute { .. })).await;
+        let SignalPayload::ForceMute { peer_id } = message.payload else { unreachable!() };
+        muted.push(peer_id);
+    }
+    muted.sort_unstable();
+    let mut everyone = vec![host_id, alice_id.clone(), bob_id];
+    everyone.sort_unstable();
+    assert_eq!(muted, everyone);
+    alice.send(SignalPayload::Mute { muted: false }).unwrap();
+    assert_eq!(refused(&mut alice_events).await, ErrorCode::NotPermitted);
+
+    // The holder may unmute; nobody else may
+    host.send(SignalPayload::GrantFloor { peer_id: Some(alice_id.clone()) }).unwrap();
+    message(&mut bob_events, |p| matches!(p, SignalPayload::Floor(f) if f.holder == Some(alice_id.clone()))).await;
+    alice.send(SignalPayload::Mute { muted: false }).unwrap();
+    let unmuted = message(&mut bob_events, |p| matches!(p, SignalPayload::Mute { muted: false })).await;
+    assert_eq!(unmuted.from, alice_id);
+    bob.send(SignalPayload::Mute { muted: false }).unwrap();
+    assert_eq!(refused(&mut bob_events).await, ErrorCode::NotPermitted);
+
+    // A holder who leaves gives the floor up
+    alice.close();
+    message(&mut bob_events, |p| matches!(p, SignalPayload::Floor(f) if f.holder.is_none())).await;
+    bob.send(SignalPayload::Mute { muted: false }).unwrap();
+    assert_eq!(refused(&mut bob_events).await, ErrorCode::NotPermitted);
+
+    host.close();
+    bob.close();
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn key_packages_follow_the_room_epoch() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let room = create_room(&server, "").await;
+
+    let (host, mut host_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let host_id = welcome(&mut host_events).await.peer_id;
+    let (alice, mut alice_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let alice_id = welcome(&mut alice_events).await.peer_id;
+    let (bob, mut bob_events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+    let bob_id = welcome(&mut bob_events).await.peer_id;
+    let package = |to: &str, epoch: u64, ciphertext: &str| SignalPayload::KeyPackage {
+        to: to.to_string(),
+        epoch,
+        ciphertext: ciphertext.to_string(),
+    };
+    let epoch = async |events: &mut Events| {
+        match message(events, |p| matches!(p, SignalPayload::KeyEpoch { .. })).await.payload {
+            SignalPayload::KeyEpoch { epoch, distributor } => (epoch, distributor),
+            _ => unreachable!(),
+        }
+    };
+
+    host.send(SignalPayload::Rekey).unwrap();
+    let (current, distributor) = epoch(&mut alice_events).await;
+    assert_eq!(distributor.as_deref(), Some(host_id.as_str()));
+
+    // Packages only reach the peer they're for
+    host.send(package(&alice_id, current, "for alice")).unwrap();
+    host.send(package(&bob_id, current, "for bob")).unwrap();
+    let delivered = message(&mut bob_events, |p| matches!(p, SignalPayload::KeyPackage { .. })).await;
+    assert!(matches!(delivered.payload, SignalPayload::KeyPackage { ref ciphertext, .. } if ciphertext == "for bob"));
+    let delivered = message(&mut alice_events, |p| matches!(p, SignalPayload::KeyPackage { .. })).await;
+    assert!(matches!(delivered.payload, SignalPayload::KeyPackage { ref ciphertext, .. } if ciphertext == "for alice"));
+
+    // A package for any other epoch is turned away
+    host.send(package(&alice_id, current + 1, "early")).unwrap();
+    assert_eq!(refused(&mut host_events).await, ErrorCode::StaleKeyEpoch);
+
+    // Someone leaving moves the room on, so their key stops working
+    bob.close();
+    let (next, distributor) = epoch(&mut alice_events).await;
+    assert_eq!(next, current + 1);
+    assert_eq!(distributor.as_deref(), Some(host_id.as_str()));
+    host.send(package(&alice_id, current, "stale")).unwrap();
+    assert_eq!(refused(&mut host_events).await, ErrorCode::StaleKeyEpoch);
+    host.send(package(&alice_id, next, "")).unwrap();
+    assert_eq!(refused(&mut host_events).await, ErrorCode::InvalidMessage);
+
+    host.close();
+    alice.close();
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn each_pair_has_one_polite_peer() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let room = create_room(&server, "").await;
+
+    // Every peer's role toward every other, from its welcome and the joins after it
+    let mut clients = Vec::new();
+    let mut roles: Vec<(String, BTreeMap<String, NegotiationRole>)> = Vec::new();
+    for joined in 0..3 {
+        let (client, mut events) = Client::connect(Config::new(&url, &room)).await.unwrap();
+        let welcome = welcome(&mut events).await;
+        assert_eq!(welcome.roles.len(), joined);
+        roles.push((welcome.peer_id, welcome.roles));
+        clients.push((client, events));
+    }
+    for (i, (client_roles, (_, events))) in roles.iter_mut().zip(clients.iter_mut()).enumerate() {
+        for _ in i + 1..3 {
+            let Event::PeerJoined { peer_id, role, .. } = expect(events, |e| matches!(e, Event::PeerJoined { .. })).await
+            else {
+                unreachable!()
+            };
+            client_roles.1.insert(peer_id, role.expect("join without a role"));
+        }
+    }
+
+    for (id, toward) in &roles {
+        for (other, other_toward) in &roles {
+            if id == other {
+                continue;
+            }
+            let (mine, theirs) = (toward[other], other_toward[id]);
+            assert_ne!(mine, theirs, "{} and {} are both {:?}", id, other, mine);
+        }
+    }
+    // Whoever joins later is the polite one
+    assert!(roles[2].1.values().all(|role| *role == NegotiationRole::Polite));
+    assert!(roles[0].1.values().all(|role| *role == NegotiationRole::Impolite));
+
+    for (client, _) in clients {
+        client.close();
+    }
+    server.shutdown();
+}
+
+/// Memory storage that stops answering pings on request
+#[derive(Default)]
+struct Flaky {
+    down: AtomicBool,
+    inner: MemoryStorage,
+}
+
+impl Storage for Flaky {
+    fn rooms(&self) -> StorageResult<Vec<RoomRecord>> {
+        self.inner.rooms()
+    }
+    fn save_room(&self, room: &RoomRecord) -> StorageResult<()> {
+        self.inner.save_room(room)
+    }
+    fn delete_room(&self, app_id: &str, room_id: &str) -> StorageResult<()> {
+        self.inner.delete_room(app_id, room_id)
+    }
+    fn apps(&self) -> StorageResult<Vec<AppRecord>> {
+        self.inner.apps()
+    }
+    fn record_room_created(&self, app_id: &str, at: u64) -> StorageResult<()> {
+        self.inner.record_room_created(app_id, at)
+    }
+    fn bans(&self) -> StorageResult<Vec<Ban>> {
+        self.inner.bans()
+    }
+    fn save_ban(&self, ban: &Ban) -> StorageResult<()> {
+        self.inner.save_ban(ban)
+    }
+    fn delete_ban(&self, ip: &str) -> StorageResult<bool> {
+        self.inner.delete_ban(ip)
+    }
+    fn usage(&self, since: &str) -> StorageResult<Vec<DailyUsage>> {
+        self.inner.usage(since)
+    }
+    fn set_usage(&self, day: &str, usage: Usage) -> StorageResult<()> {
+        self.inner.set_usage(day, usage)
+    }
+    fn ping(&self) -> StorageResult<()> {
+        match self.down.load(Ordering::Relaxed) {
+            true => Err("database is locked".into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn probes_report_why_the_server_is_not_ready() {
+    let storage = Arc::new(Flaky::default());
+    let config = ServerConfig {
+        max_global_rooms: 1,
+        ..ServerConfig::default()
+    };
+    let (server, _) = start(ServerBuilder::new(config).storage(storage.clone())).await;
+
+    let response = request(&server, "GET", "/healthz", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let health = json(response).await;
+    assert_eq!(health["status"], "ok");
+    assert_eq!(health["version"], env!("CARGO_PKG_VERSION"));
+
+    let response = request(&server, "GET", "/readyz", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let ready = json(response).await;
+    assert_eq!(ready["status"], "ready");
+    assert_eq!(ready["accepting_rooms"], true);
+    assert_eq!(ready["storage_ok"], true);
+
+    // Storage that doesn't answer takes the server out of rotation, and
+    // only readiness cares
+    storage.down.store(true, Ordering::Relaxed);
+    let response = request(&server, "GET", "/readyz", None).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let ready = json(response).await;
+    assert_eq!(ready["status"], "not_ready");
+    assert_eq!(ready["storage_ok"], false);
+    assert_eq!(ready["draining"], false);
+    assert_eq!(request(&server, "GET", "/healthz", None).await.status(), StatusCode::OK);
+    storage.down.store(false, Ordering::Relaxed);
+
+    server.state.draining.store(true, Ordering::Relaxed);
+    let response = request(&server, "GET", "/readyz", None).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let ready = json(response).await;
+    assert_eq!(ready["draining"], true);
+    assert_eq!(ready["storage_ok"], true);
+    assert_eq!(ready["accepting_rooms"], false);
+    server.state.draining.store(false, Ordering::Relaxed);
+
+    // A full server isn't ready for more rooms either
+    create_room(&server, "").await;
+    let response = request(&server, "GET", "/readyz", None).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let ready = json(response).await;
+    assert_eq!(ready["active_rooms"], 1);
+    assert_eq!(ready["max_rooms"], 1);
+    assert_eq!(ready["storage_ok"], true);
+
+    server.shutdown();
+}
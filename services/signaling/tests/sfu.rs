@@ -0,0 +1,239 @@
+//! Audio between clients through the forwarder, and recordings of it.
+
+mod common;
+
+use axum::body::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use webrtc::api::media_engine::MIME_TYPE_OPUS;
+use webrtc::media::Sample;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use signaling::recording::RecordingConfig;
+use signaling::server::{ServerBuilder, ServerConfig};
+use signaling::sfu::Sfu;
+use voca_client::media::Media;
+use voca_client::{Client, Config, Event, Events, SignalMessage, SignalPayload, Topology};
+
+use common::{create_room, expect, start};
+
+/// One 20 ms Opus frame of silence
+const SILENCE: &[u8] = &[0xf8, 0xff, 0xfe];
+
+/// Peers each one has had audio from
+type Heard = Arc<Mutex<HashMap<String, HashSet<String>>>>;
+
+/// A server with a forwarder, recording when given somewhere to put it
+fn with_sfu(recording: Option<RecordingConfig>) -> ServerBuilder {
+    ServerBuilder::new(ServerConfig {
+        sfu: Some(Arc::new(Sfu::from_env().unwrap())),
+        recording,
+        ..ServerConfig::default()
+    })
+}
+
+/// Join with a track sending silence, noting whose audio arrives. Events
+/// after the welcome are passed on once media has seen them.
+async fn join(url: &str, room: &str, heard: Heard) -> (Client, String, Events) {
+    let (client, mut events) = Client::connect(Config::new(url, room)).await.unwrap();
+    let (mut media, mut tracks) = Media::new(client.clone(), Vec::new()).unwrap();
+    let opus = RTCRtpCodecCapability {
+        mime_type: MIME_TYPE_OPUS.to_string(),
+        clock_rate: 48000,
+        channels: 2,
+        ..Default::default()
+    };
+    let track = Arc::new(TrackLocalStaticSample::new(opus, "audio".to_string(), "test".to_string()));
+    media.set_track(track.clone());
+
+    let welcome = tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out waiting for the welcome")
+        .expect("events ended");
+    let Event::Welcome(ref joined) = welcome else {
+        panic!("expected a welcome, got {:?}", welcome);
+    };
+    assert_eq!(joined.topology, Topology::Sfu);
+    let peer_id = joined.peer_id.clone();
+    media.handle(&welcome).await.unwrap();
+
+    let (forward, forwarded) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let _ = media.handle(&event).await;
+            let _ = forward.send(event);
+        }
+        media.close().await;
+    });
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_millis(20));
+        loop {
+            tick.tick().await;
+            let sample = Sample {
+                data: Bytes::from_static(SILENCE),
+                duration: Duration::from_millis(20),
+                ..Default::default()
+            };
+            if track.write_sample(&sample).await.is_err() {
+                return;
+            }
+        }
+    });
+    let listener = peer_id.clone();
+    tokio::spawn(async move {
+        while let Some(remote) = tracks.recv().await {
+            let heard = heard.clone();
+            let listener = listener.clone();
+            tokio::spawn(async move {
+                if remote.track.read_rtp().await.is_ok() {
+                    heard.lock().unwrap().entry(listener).or_default().insert(remote.peer_id);
+                }
+            });
+        }
+    });
+    (client, peer_id, forwarded)
+}
+
+#[tokio::test]
+async fn peers_hear_each_other_through_the_sfu() {
+    let (server, url) = start(with_sfu(None)).await;
+    let room = create_room(&server, "?topology=sfu").await;
+
+    let heard = Heard::default();
+    let mut peers = Vec::new();
+    for _ in 0..3 {
+        peers.push(join(&url, &room, heard.clone()).await);
+    }
+
+    let everyone: Vec<String> = peers.iter().map(|(_, id, _)| id.clone()).collect();
+    let all_heard = || {
+        let heard = heard.lock().unwrap();
+        everyone.iter().all(|listener| {
+            let from = heard.get(listener).cloned().unwrap_or_default();
+            everyone.iter().filter(|id| *id != listener).all(|id| from.contains(id))
+        })
+    };
+    let done = tokio::time::timeout(Duration::from_secs(20), async {
+        while !all_heard() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(done.is_ok(), "not everyone was heard: {:?}", heard.lock().unwrap());
+    // The forwarder never sends anyone their own audio
+    for (listener, from) in heard.lock().unwrap().iter() {
+        assert!(!from.contains(listener), "{} heard itself", listener);
+    }
+
+    for (client, _, _) in peers {
+        client.close();
+    }
+    server.shutdown();
+}
+
+/// Audio files in a recording's directory, by the peer they capture
+fn recorded(dir: &Path) -> HashMap<String, usize> {
+    let mut files = HashMap::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let name = entry.unwrap().file_name().to_string_lossy().to_string();
+        if let Some((peer_id, _)) = name.strip_suffix(".ogg").and_then(|stem| stem.rsplit_once('-')) {
+            *files.entry(peer_id.to_string()).or_default() += 1;
+        }
+    }
+    files
+}
+
+fn consent(client: &Client, consent: bool) {
+    client
+        .send(SignalPayload::RecordingConsent {
+            peer_id: String::new(),
+            consent,
+        })
+        .unwrap();
+}
+
+#[tokio::test]
+async fn recordings_capture_only_consenting_peers() {
+    let base: PathBuf = std::env::temp_dir().join(format!("voca-recording-test-{}", std::process::id()));
+    let (server, url) = start(with_sfu(Some(RecordingConfig::new(&base)))).await;
+    let room = create_room(&server, "?topology=sfu").await;
+
+    let heard = Heard::default();
+    let (host, host_id, mut host_events) = join(&url, &room, heard.clone()).await;
+    let (early, early_id, mut early_events) = join(&url, &room, heard.clone()).await;
+    let (late, late_id, mut late_events) = join(&url, &room, heard.clone()).await;
+
+    host.send(SignalPayload::RecordingStart).unwrap();
+    let started = |event: &Event| {
+        matches!(event, Event::Message(SignalMessage { payload: SignalPayload::RecordingStarted { .. }, .. }))
+    };
+    let Event::Message(SignalMessage {
+        payload: SignalPayload::RecordingStarted { id, by },
+        ..
+    }) = expect(&mut host_events, started).await
+    else {
+        unreachable!()
+    };
+    assert_eq!(by, host_id);
+    expect(&mut late_events, started).await;
+    expect(&mut early_events, started).await;
+    let dir = base.join("public").join(&room).join(&id);
+
+    // Only the peer that agreed gets a file, however long the others talk
+    consent(&early, true);
+    let wait_for = |peer_id: &str| {
+        let dir = dir.clone();
+        let peer_id = peer_id.to_string();
+        tokio::time::timeout(Duration::from_secs(10), async move {
+            while !recorded(&dir).contains_key(&peer_id) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+    };
+    wait_for(&early_id).await.expect("the consenting peer was not recorded");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let files = recorded(&dir);
+    assert!(!files.contains_key(&host_id), "recorded the host without consent: {:?}", files);
+    assert!(!files.contains_key(&late_id), "recorded a peer without consent: {:?}", files);
+
+    // Consent given partway through starts that peer's file from then on
+    consent(&late, true);
+    wait_for(&late_id).await.expect("the peer that consented later was not recorded");
+
+    host.send(SignalPayload::RecordingStop).unwrap();
+    expect(&mut early_events, |event| {
+        matches!(event, Event::Message(SignalMessage { payload: SignalPayload::RecordingStopped { .. }, .. }))
+    })
+    .await;
+    let manifest = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Ok(json) = std::fs::read(dir.join("manifest.json")) {
+                return serde_json::from_slice::<serde_json::Value>(&json).unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("no manifest was written");
+    let mut tracks: Vec<&str> = manifest["tracks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|track| track["peer_id"].as_str().unwrap())
+        .collect();
+    tracks.sort_unstable();
+    let mut expected = vec![early_id.as_str(), late_id.as_str()];
+    expected.sort_unstable();
+    assert_eq!(tracks, expected);
+    assert_eq!(recorded(&dir).get(&host_id), None);
+
+    for client in [host, early, late] {
+        client.close();
+    }
+    server.shutdown();
+    let _ = std::fs::remove_dir_all(&base);
+}
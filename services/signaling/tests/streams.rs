@@ -0,0 +1,123 @@
+//! WHIP and WHEP sessions and what they do to the room's topology.
+
+mod common;
+
+use axum::{body::Body, http::header, http::StatusCode, response::Response};
+use std::sync::Arc;
+use std::time::Duration;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::APIBuilder;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+
+use signaling::server::{Server, ServerBuilder, ServerConfig};
+use signaling::sfu::Sfu;
+use voca_client::{Client, Config, Event, Events, SignalMessage, SignalPayload, Topology};
+
+use common::{call, json, loopback, start};
+
+async fn send(server: &Server, method: &str, uri: &str, body: String) -> Response {
+    let request = loopback(method, uri)
+        .header(header::CONTENT_TYPE, "application/sdp")
+        .body(Body::from(body))
+        .unwrap();
+    call(server, request).await
+}
+
+async fn topology(server: &Server, room: &str, app_id: &str) -> String {
+    let uri = format!("/api/room/{}?appId={}", room, app_id);
+    let check = json(send(server, "GET", &uri, String::new()).await).await;
+    check["topology"].as_str().unwrap().to_string()
+}
+
+/// A WHEP viewer's offer, with every candidate gathered
+async fn viewer_offer() -> (Arc<RTCPeerConnection>, String) {
+    let mut media = MediaEngine::default();
+    media.register_default_codecs().unwrap();
+    let api = APIBuilder::new().with_media_engine(media).build();
+    let pc = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await.unwrap());
+    pc.add_transceiver_from_kind(
+        RTPCodecType::Audio,
+        &[RTCRtpTransceiverInit {
+            direction: RTCRtpTransceiverDirection::Recvonly,
+            send_encodings: Vec::new(),
+        }],
+    )
+    .await
+    .unwrap();
+    let offer = pc.create_offer(None).await.unwrap();
+    let mut gathered = pc.gathering_complete_promise().await;
+    pc.set_local_description(offer).await.unwrap();
+    let _ = gathered.recv().await;
+    let sdp = pc.local_description().await.unwrap().sdp;
+    (pc, sdp)
+}
+
+/// The topology the room announces next, or joined with
+async fn next_topology(events: &mut Events) -> Topology {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match events.recv().await.expect("events ended") {
+                Event::Welcome(welcome) => return welcome.topology,
+                Event::Message(SignalMessage {
+                    payload: SignalPayload::TopologyChange { topology, .. },
+                    ..
+                }) => return topology,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for a topology")
+}
+
+#[tokio::test]
+async fn viewers_hold_an_adaptive_room_on_the_sfu_until_they_leave() {
+    let config = ServerConfig {
+        sfu: Some(Arc::new(Sfu::from_env().unwrap())),
+        ..ServerConfig::default()
+    };
+    let (server, url) = start(ServerBuilder::new(config)).await;
+    let created = json(send(&server, "POST", "/api/room?appId=team+a%26b", String::new()).await).await;
+    let room = created["room"].as_str().unwrap().to_string();
+    let app_id = "team+a%26b";
+    assert_eq!(topology(&server, &room, app_id).await, "mesh");
+
+    let mut config = Config::new(&url, &room);
+    config.app_id = Some("team a&b".to_string());
+    let (client, mut events) = Client::connect(config).await.unwrap();
+    assert_eq!(next_topology(&mut events).await, Topology::Mesh);
+
+    // An offer that can't be answered leaves the room as it was
+    let whep = format!("/api/room/{}/whep?appId={}", room, app_id);
+    let whip = format!("/api/room/{}/whip?appId={}", room, app_id);
+    for uri in [&whep, &whip] {
+        let response = send(&server, "POST", uri, "not sdp".to_string()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    assert_eq!(topology(&server, &room, app_id).await, "mesh");
+
+    let (viewer, offer) = viewer_offer().await;
+    let response = send(&server, "POST", &whep, offer).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let location = response.headers()[header::LOCATION].to_str().unwrap().to_string();
+    let (path, query) = location.split_once('?').unwrap();
+    assert!(path.starts_with("whep/") && !path.contains("/api/"), "{}", location);
+    assert_eq!(query, "appId=team+a%26b");
+    assert_eq!(next_topology(&mut events).await, Topology::Sfu);
+    assert_eq!(topology(&server, &room, app_id).await, "sfu");
+
+    // Resolved against the request URL, as a WHEP client would
+    let session = format!("/api/room/{}/{}", room, location);
+    assert_eq!(send(&server, "DELETE", &session, String::new()).await.status(), StatusCode::OK);
+    assert_eq!(next_topology(&mut events).await, Topology::Mesh);
+    assert_eq!(topology(&server, &room, app_id).await, "mesh");
+    assert_eq!(send(&server, "DELETE", &session, String::new()).await.status(), StatusCode::NOT_FOUND);
+
+    let _ = viewer.close().await;
+    client.close();
+    server.shutdown();
+}
@@ -0,0 +1,130 @@
+//! Webhook delivery against a local endpoint.
+
+use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+use signaling::state::RoomKey;
+use signaling::webhooks::{sign, Endpoint, WebhookConfig, WebhookEvent, WebhookEventType, Webhooks};
+
+/// Requests the endpoint received, as headers and body
+#[derive(Clone, Default)]
+struct Received(Arc<Mutex<Vec<(HeaderMap, String)>>>);
+
+impl Received {
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+async fn accept(State((ok, _)): State<(Received, Received)>, headers: HeaderMap, body: String) -> StatusCode {
+    ok.0.lock().unwrap().push((headers, body));
+    StatusCode::NO_CONTENT
+}
+
+async fn fail(State((_, failed)): State<(Received, Received)>, headers: HeaderMap, body: String) -> StatusCode {
+    failed.0.lock().unwrap().push((headers, body));
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Serve `/ok`, which accepts everything, and `/fail`, which never does
+async fn endpoint() -> (String, Received, Received) {
+    let (ok, failed) = (Received::default(), Received::default());
+    let app = Router::new()
+        .route("/ok", post(accept))
+        .route("/fail", post(fail))
+        .with_state((ok.clone(), failed.clone()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (base, ok, failed)
+}
+
+async fn wait_until(what: &str, done: impl Fn() -> bool) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for {}", what));
+}
+
+fn key(app_id: &str) -> RoomKey {
+    RoomKey {
+        app_id: app_id.to_string(),
+        room_id: "room-1".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn delivers_signed_events_and_dead_letters_failures() {
+    let (base, ok, failed) = endpoint().await;
+    let config = WebhookConfig {
+        global: Some(Endpoint::new(format!("{}/ok", base), "s3cret")),
+        per_app: HashMap::from([("broken".to_string(), Endpoint::new(format!("{}/fail", base), "other"))]),
+        max_attempts: 2,
+    };
+    let (webhooks, queue) = Webhooks::new(config);
+    let webhooks = Arc::new(webhooks);
+    let task = tokio::spawn(webhooks.clone().run(queue));
+
+    // Delivered once, signed with the global secret
+    let event = WebhookEvent::peer(WebhookEventType::PeerJoined, &key("default"), "peer-1");
+    let id = event.id.clone();
+    webhooks.emit(event);
+    wait_until("the delivery", || ok.len() == 1).await;
+    let (headers, body) = ok.0.lock().unwrap()[0].clone();
+    let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+    assert_eq!(header("x-voca-event"), "peer.joined");
+    assert_eq!(header("x-voca-delivery"), id);
+    assert_eq!(header("x-voca-signature"), sign("s3cret", &header("x-voca-timestamp"), &body));
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!((json["id"].as_str(), json["peer_id"].as_str()), (Some(id.as_str()), Some("peer-1")));
+
+    // Retried, then dead-lettered; signed with the app's own secret
+    webhooks.emit(WebhookEvent::new(WebhookEventType::RoomCreated, &key("broken")));
+    wait_until("the dead letter", || !webhooks.dead_letters().is_empty()).await;
+    assert_eq!(failed.len(), 2);
+    for (headers, body) in failed.0.lock().unwrap().iter() {
+        let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+        assert_eq!(header("x-voca-signature"), sign("other", &header("x-voca-timestamp"), body));
+    }
+    let dead = webhooks.dead_letters();
+    assert_eq!(dead[0].attempts, 2);
+    assert!(dead[0].error.contains("500"), "{}", dead[0].error);
+    assert_eq!(webhooks.pending(), 0);
+
+    // Replayed for another full round
+    assert_eq!(webhooks.retry_dead_letters(), 1);
+    assert!(webhooks.dead_letters().is_empty());
+    wait_until("the second dead letter", || !webhooks.dead_letters().is_empty()).await;
+    assert_eq!(failed.len(), 4);
+    assert_eq!(ok.len(), 1);
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn apps_without_an_endpoint_send_nothing() {
+    let (base, ok, _) = endpoint().await;
+    let config = WebhookConfig {
+        per_app: HashMap::from([("hooked".to_string(), Endpoint::new(format!("{}/ok", base), "s3cret"))]),
+        ..WebhookConfig::default()
+    };
+    let (webhooks, queue) = Webhooks::new(config);
+    let webhooks = Arc::new(webhooks);
+    let task = tokio::spawn(webhooks.clone().run(queue));
+
+    webhooks.emit(WebhookEvent::new(WebhookEventType::RoomCreated, &key("other")));
+    webhooks.emit(WebhookEvent::new(WebhookEventType::RoomCreated, &key("hooked")));
+    wait_until("the delivery", || ok.len() == 1).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(ok.len(), 1);
+    assert_eq!(webhooks.pending(), 0);
+    assert!(webhooks.dead_letters().is_empty());
+
+    task.abort();
+}
@@ -0,0 +1,99 @@
+//! Join a room with audio: send Opus silence and report how many packets
+//! arrive from each peer. Run with
+//! `cargo run -p voca-client --features webrtc --example audio_bot -- ws://localhost:3001 <room>`.
+
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use voca_client::media::Media;
+use voca_client::{Client, Config, Event};
+use webrtc::api::media_engine::MIME_TYPE_OPUS;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::media::Sample;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+/// One 20 ms Opus frame of silence
+const SILENCE: &[u8] = &[0xf8, 0xff, 0xfe];
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let (Some(server), Some(room)) = (args.next(), args.next()) else {
+        eprintln!("usage: audio_bot <server-url> <room>");
+        std::process::exit(2);
+    };
+
+    let mut config = Config::new(server, room);
+    config.api_key = std::env::var("VOCA_API_KEY").ok();
+    config.state.name = Some("audio_bot".to_string());
+    let (client, mut events) = Client::connect(config).await?;
+
+    let ice_servers = vec![RTCIceServer {
+        urls: vec!["stun:stun.l.google.com:19302".to_string()],
+        ..Default::default()
+    }];
+    let (mut media, mut tracks) = Media::new(client, ice_servers)?;
+    let opus = RTCRtpCodecCapability {
+        mime_type: MIME_TYPE_OPUS.to_string(),
+        clock_rate: 48000,
+        channels: 2,
+        ..Default::default()
+    };
+    let track = Arc::new(TrackLocalStaticSample::new(opus, "audio".to_string(), "audio_bot".to_string()));
+    media.set_track(track.clone());
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_millis(20));
+        loop {
+            tick.tick().await;
+            let sample = Sample {
+                data: Bytes::from_static(SILENCE),
+                duration: Duration::from_millis(20),
+                ..Default::default()
+            };
+            let _ = track.write_sample(&sample).await;
+        }
+    });
+
+    // Count packets per peer and print the totals every few seconds
+    let received: Arc<Mutex<BTreeMap<String, u64>>> = Arc::default();
+    let counts = received.clone();
+    tokio::spawn(async move {
+        while let Some(remote) = tracks.recv().await {
+            let counts = counts.clone();
+            tokio::spawn(async move {
+                while remote.track.read_rtp().await.is_ok() {
+                    *counts.lock().unwrap().entry(remote.peer_id.clone()).or_default() += 1;
+                }
+            });
+        }
+    });
+    let report = received.clone();
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(5));
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            println!("packets received: {:?}", report.lock().unwrap());
+        }
+    });
+
+    while let Some(event) = events.recv().await {
+        if let Err(e) = media.handle(&event).await {
+            eprintln!("media error: {}", e);
+        }
+        match event {
+            Event::Welcome(welcome) => println!("joined as {} ({:?})", welcome.peer_id, welcome.topology),
+            Event::PeerJoined { peer_id, .. } => println!("{} joined", peer_id),
+            Event::PeerLeft { peer_id } => println!("{} left", peer_id),
+            Event::Closed { error } => {
+                println!("closed: {:?}", error.map(|e| e.message));
+                break;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
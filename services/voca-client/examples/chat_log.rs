@@ -0,0 +1,51 @@
+//! Join a room and print its roster and chat. Answers `!ping` in chat.
+//! Run with `cargo run -p voca-client --example chat_log -- ws://localhost:3001 <room>`.
+
+use voca_client::{Client, Config, Event};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let (Some(server), Some(room)) = (args.next(), args.next()) else {
+        eprintln!("usage: chat_log <server-url> <room>");
+        std::process::exit(2);
+    };
+
+    let mut config = Config::new(server, room);
+    config.api_key = std::env::var("VOCA_API_KEY").ok();
+    config.state.name = Some("chat_log".to_string());
+    let (client, mut events) = Client::connect(config).await?;
+
+    while let Some(event) = events.recv().await {
+        match event {
+            Event::Welcome(welcome) => {
+                println!("joined as {} with {} others", welcome.peer_id, welcome.peers.len());
+                for message in welcome.chat {
+                    println!("[{}] {}", message.from, message.text);
+                }
+            }
+            Event::PeerJoined { peer_id, state, .. } => {
+                let name = state.and_then(|s| s.name).unwrap_or_default();
+                println!("{} joined {}", peer_id, name);
+            }
+            Event::PeerLeft { peer_id } => println!("{} left", peer_id),
+            Event::Chat(message) => {
+                println!("[{}] {}", message.from, message.text);
+                if message.text == "!ping" {
+                    client.chat("pong")?;
+                }
+            }
+            Event::Announcement { from, text } => println!("** {} ({})", text, from),
+            Event::Warning(error) => println!("warning: {}", error.message),
+            Event::Reconnecting { attempt, delay } => println!("reconnecting (attempt {}) in {:?}", attempt, delay),
+            Event::Closed { error } => {
+                if let Some(error) = error {
+                    println!("closed: {}", error.message);
+                }
+                break;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
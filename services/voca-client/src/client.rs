@@ -0,0 +1,284 @@
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, warn};
+
+use crate::{ApiError, Config, ErrorCode, Event, PeerUpdate, SignalMessage, SignalPayload};
+use voca_protocol::protocol::{Capability, PROTOCOL_VERSION_MAX};
+
+/// Longest backoff between reconnect attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// The server pings every 15 seconds; this long without hearing from it
+/// means the connection is gone even if the socket hasn't noticed
+const READ_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Errors that turn down a single message without ending the session, as in
+/// the TypeScript client
+const NON_FATAL_ERRORS: &[ErrorCode] = &[
+    ErrorCode::InvalidMessage,
+    ErrorCode::PayloadTooLarge,
+    ErrorCode::MessageKindNotAllowed,
+    ErrorCode::ChatMessageNotFound,
+    ErrorCode::NotPermitted,
+    ErrorCode::StaleKeyEpoch,
+    ErrorCode::RecordingUnavailable,
+    ErrorCode::InvalidTopology,
+    ErrorCode::StageRoleFull,
+];
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The room's events, ending with [`Event::Closed`]
+pub type Events = mpsc::UnboundedReceiver<Event>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The first connection failed
+    Connect(tungstenite::Error),
+    /// The server turned the connection down, e.g. because the room doesn't exist
+    Rejected(ApiError),
+    /// The session is over
+    Closed,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Connect(e) => write!(f, "failed to connect: {}", e),
+            Error::Rejected(error) => write!(f, "connection rejected: {}", error.message),
+            Error::Closed => f.write_str("session closed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+enum Command {
+    Send(Message),
+    Close,
+}
+
+/// Handle to a session running in the background. Clones share it.
+#[derive(Clone)]
+pub struct Client {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Client {
+    /// Open the first connection and start the session. Later connections
+    /// are retried as `config.reconnect` allows; their failures only show up
+    /// as events.
+    pub async fn connect(config: Config) -> Result<(Client, Events), Error> {
+        let socket = open(&config).await?;
+        let (commands, rx) = mpsc::unbounded_channel();
+        let (events, events_rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(config, socket, rx, events));
+        Ok((Client { commands }, events_rx))
+    }
+
+    /// Send a protocol message. Messages sent while reconnecting are dropped.
+    pub fn send(&self, payload: SignalPayload) -> Result<(), Error> {
+        self.commands.send(Command::Send(encode(payload))).map_err(|_| Error::Closed)
+    }
+
+    /// Post to the room's chat; the server echoes it back with its id
+    pub fn chat(&self, text: impl Into<String>) -> Result<(), Error> {
+        self.send(SignalPayload::Chat {
+            id: String::new(),
+            text: text.into(),
+            ts: 0,
+        })
+    }
+
+    /// Send an application event to one peer, or to the room if `to` is `None`
+    pub fn app(&self, kind: impl Into<String>, data: serde_json::Value, to: Option<String>) -> Result<(), Error> {
+        self.send(SignalPayload::App {
+            kind: kind.into(),
+            data,
+            to,
+        })
+    }
+
+    /// Change our name, avatar, mute or metadata
+    pub fn update(&self, changes: PeerUpdate) -> Result<(), Error> {
+        self.send(SignalPayload::PeerUpdate {
+            peer_id: String::new(),
+            changes,
+        })
+    }
+
+    /// Leave the room; the events end with [`Event::Closed`]
+    pub fn close(&self) {
+        let _ = self.commands.send(Command::Close);
+    }
+}
+
+async fn open(config: &Config) -> Result<Socket, Error> {
+    match tokio_tungstenite::connect_async(config.socket_url()).await {
+        Ok((socket, _)) => Ok(socket),
+        // Checks made before the upgrade answer with the REST error envelope
+        Err(tungstenite::Error::Http(response)) => {
+            let error = response.body().as_deref().and_then(|body| serde_json::from_slice(body).ok());
+            match error {
+                Some(error) => Err(Error::Rejected(error)),
+                None => Err(Error::Connect(tungstenite::Error::Http(response))),
+            }
+        }
+        Err(e) => Err(Error::Connect(e)),
+    }
+}
+
+/// How one connection ended
+enum Ended {
+    /// We closed it, or every handle was dropped
+    Closed,
+    /// The server ended the session with an error
+    Fatal(ApiError),
+    /// The connection was lost
+    Dropped { welcomed: bool },
+}
+
+/// Keep the session going across connections until it ends for good
+async fn run(
+    config: Config,
+    mut socket: Socket,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    events: mpsc::UnboundedSender<Event>,
+) {
+    let mut attempt = 0;
+    loop {
+        match session(&config, socket, &mut commands, &events).await {
+            Ended::Closed => {
+                let _ = events.send(Event::Closed { error: None });
+                return;
+            }
+            Ended::Fatal(error) => {
+                let _ = events.send(Event::Closed { error: Some(error) });
+                return;
+            }
+            Ended::Dropped { welcomed: true } => attempt = 0,
+            Ended::Dropped { welcomed: false } => {}
+        }
+
+        socket = loop {
+            if !config.reconnect.enabled || attempt >= config.reconnect.max_attempts {
+                let _ = events.send(Event::Closed { error: None });
+                return;
+            }
+            // Exponential backoff: 1s, 2s, 4s, 8s, 16s (capped at 30s)
+            let delay = config.reconnect.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+            let delay = delay.min(MAX_RECONNECT_DELAY);
+            attempt += 1;
+            let _ = events.send(Event::Reconnecting { attempt, delay });
+
+            let sleep = tokio::time::sleep(delay);
+            tokio::pin!(sleep);
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    command = commands.recv() => match command {
+                        Some(Command::Send(_)) => debug!("Dropping message sent while reconnecting"),
+                        Some(Command::Close) | None => {
+                            let _ = events.send(Event::Closed { error: None });
+                            return;
+                        }
+                    },
+                }
+            }
+
+            match open(&config).await {
+                Ok(socket) => break socket,
+                Err(Error::Rejected(error)) if !error.retryable => {
+                    let _ = events.send(Event::Closed { error: Some(error) });
+                    return;
+                }
+                Err(e) => warn!(attempt, error = %e, "Reconnect failed"),
+            }
+        };
+    }
+}
+
+/// Say hello and relay traffic until the connection ends
+async fn session(
+    config: &Config,
+    socket: Socket,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+    events: &mpsc::UnboundedSender<Event>,
+) -> Ended {
+    let (mut sink, mut stream) = socket.split();
+    let hello = SignalPayload::Hello {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        client: env!("CARGO_PKG_NAME").to_string(),
+        protocol: PROTOCOL_VERSION_MAX,
+        capabilities: vec![Capability::Roster],
+        state: (!config.state.is_empty()).then(|| config.state.clone()),
+    };
+    if sink.send(encode(hello)).await.is_err() {
+        return Ended::Dropped { welcomed: false };
+    }
+
+    let mut welcomed = false;
+    // The server closes the socket right after a fatal error
+    let mut fatal = None;
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Send(message)) => {
+                    if sink.send(message).await.is_err() {
+                        return Ended::Dropped { welcomed };
+                    }
+                }
+                Some(Command::Close) | None => {
+                    let _ = sink.close().await;
+                    return Ended::Closed;
+                }
+            },
+            frame = tokio::time::timeout(READ_TIMEOUT, stream.next()) => {
+                let text = match frame {
+                    Ok(Some(Ok(Message::Text(text)))) => text,
+                    // Protocol-level pings are answered by tungstenite
+                    Ok(Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Binary(_) | Message::Frame(_)))) => continue,
+                    Ok(Some(Ok(Message::Close(_)) | Err(_)) | None) | Err(_) => {
+                        return match fatal {
+                            Some(error) => Ended::Fatal(error),
+                            None => Ended::Dropped { welcomed },
+                        };
+                    }
+                };
+                let msg: SignalMessage = match serde_json::from_str(&text) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        warn!(error = %e, "Ignoring unreadable message");
+                        continue;
+                    }
+                };
+                match msg.payload {
+                    SignalPayload::Ping => {
+                        if sink.send(encode(SignalPayload::Pong)).await.is_err() {
+                            return Ended::Dropped { welcomed };
+                        }
+                        continue;
+                    }
+                    SignalPayload::Error(ref error) if !NON_FATAL_ERRORS.contains(&error.code) => {
+                        fatal = Some(error.clone());
+                        continue;
+                    }
+                    SignalPayload::Welcome { .. } => welcomed = true,
+                    _ => {}
+                }
+                let _ = events.send(Event::from(msg));
+            }
+        }
+    }
+}
+
+fn encode(payload: SignalPayload) -> Message {
+    let msg = SignalMessage {
+        from: String::new(),
+        payload,
+    };
+    Message::text(serde_json::to_string(&msg).unwrap_or_default())
+}
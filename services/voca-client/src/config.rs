@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use crate::PeerUpdate;
+
+/// Where to connect and as whom
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Server base URL, e.g. `ws://localhost:3001`; `http://` works too
+    pub server_url: String,
+    pub room: String,
+    /// Application namespace, for servers that use API keys
+    pub app_id: Option<String>,
+    pub api_key: Option<String>,
+    pub password: Option<String>,
+    /// Host key of a reserved room, to join as its host
+    pub host_key: Option<String>,
+    /// Queue for a slot when the room is full instead of failing
+    pub waitlist: bool,
+    /// Name, avatar, mute and metadata sent with every hello
+    pub state: PeerUpdate,
+    pub reconnect: Reconnect,
+}
+
+/// How to get back into the room after the connection drops. A new
+/// connection joins as a new peer, so it gets a fresh welcome.
+#[derive(Clone, Debug)]
+pub struct Reconnect {
+    pub enabled: bool,
+    /// Attempts in a row before giving up; reset by every welcome
+    pub max_attempts: u32,
+    /// First delay, doubled after every failed attempt up to 30 seconds
+    pub base_delay: Duration,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Config {
+    pub fn new(server_url: impl Into<String>, room: impl Into<String>) -> Self {
+        Self {
+            server_url: server_url.into(),
+            room: room.into(),
+            app_id: None,
+            api_key: None,
+            password: None,
+            host_key: None,
+            waitlist: false,
+            state: PeerUpdate::default(),
+            reconnect: Reconnect::default(),
+        }
+    }
+
+    /// WebSocket URL for the room, with the options as query parameters
+    pub(crate) fn socket_url(&self) -> String {
+        let base = self.server_url.trim_end_matches('/');
+        let base = match base.split_once("://") {
+            Some(("http", rest)) => format!("ws://{}", rest),
+            Some(("https", rest)) => format!("wss://{}", rest),
+            Some(_) => base.to_string(),
+            None => format!("ws://{}", base),
+        };
+
+        let mut params = Vec::new();
+        let options = [
+            ("appId", &self.app_id),
+            ("apiKey", &self.api_key),
+            ("password", &self.password),
+            ("hostKey", &self.host_key),
+        ];
+        for (name, value) in options {
+            if let Some(value) = value {
+                params.push(format!("{}={}", name, encode(value)));
+            }
+        }
+        if self.waitlist {
+            params.push("wait=true".to_string());
+        }
+
+        let mut url = format!("{}/ws/{}", base, encode(&self.room));
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&params.join("&"));
+        }
+        url
+    }
+}
+
+/// Percent-encode everything but unreserved characters
+fn encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
@@ -0,0 +1,93 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::{ApiError, ChatMessage, NegotiationRole, PeerState, SignalMessage, SignalPayload, StageRole, Topology};
+
+/// Something that happened in the room or to the connection
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// Joined the room, on the first connection and after every reconnect
+    Welcome(Welcome),
+    /// Queued for a slot in a full room; `position` is 1-based
+    Waiting { position: usize },
+    PeerJoined {
+        peer_id: String,
+        state: Option<PeerState>,
+        /// Our negotiation role toward the new peer
+        role: Option<NegotiationRole>,
+    },
+    PeerLeft { peer_id: String },
+    /// Chat from anyone, including our own messages with their server id
+    Chat(ChatMessage),
+    App { from: String, kind: String, data: Value },
+    Announcement { from: String, text: String },
+    /// The server turned down one message; the session goes on
+    Warning(ApiError),
+    /// The connection dropped and the next attempt starts after `delay`
+    Reconnecting { attempt: u32, delay: Duration },
+    /// The session is over: closed by us, ended by the server with `error`,
+    /// or out of reconnect attempts. No events follow.
+    Closed { error: Option<ApiError> },
+    /// Any other protocol message
+    Message(SignalMessage),
+}
+
+/// The room as it was when we joined
+#[derive(Clone, Debug)]
+pub struct Welcome {
+    pub peer_id: String,
+    /// Everyone else in the room
+    pub peers: BTreeMap<String, PeerState>,
+    /// Our negotiation role toward each peer
+    pub roles: BTreeMap<String, NegotiationRole>,
+    pub host: Option<String>,
+    /// Recent chat, oldest first
+    pub chat: Vec<ChatMessage>,
+    pub topology: Topology,
+    pub stage_role: Option<StageRole>,
+    /// Unix milliseconds when the room closes, if it has an end
+    pub closes_at: Option<u64>,
+}
+
+impl From<SignalMessage> for Event {
+    fn from(msg: SignalMessage) -> Self {
+        let from = msg.from;
+        match msg.payload {
+            SignalPayload::Welcome {
+                peer_id,
+                states,
+                roles,
+                host,
+                chat,
+                topology,
+                stage_role,
+                closes_at,
+                ..
+            } => Event::Welcome(Welcome {
+                peer_id,
+                peers: states.unwrap_or_default(),
+                roles: roles.unwrap_or_default(),
+                host,
+                chat,
+                topology,
+                stage_role,
+                closes_at,
+            }),
+            SignalPayload::Waiting { position } => Event::Waiting { position },
+            SignalPayload::Join { peer_id, state, role } => Event::PeerJoined { peer_id, state, role },
+            SignalPayload::Leave { peer_id } => Event::PeerLeft { peer_id },
+            SignalPayload::Chat { id, text, ts } => Event::Chat(ChatMessage {
+                id,
+                from,
+                text,
+                ts,
+                edited: false,
+            }),
+            SignalPayload::App { kind, data, .. } => Event::App { from, kind, data },
+            SignalPayload::Announcement { text } => Event::Announcement { from, text },
+            SignalPayload::Error(error) => Event::Warning(error),
+            payload => Event::Message(SignalMessage { from, payload }),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Rust client for the Voca signaling protocol, for audio bots, load tests
+//! and integration tests.
+//!
+//! [`Client::connect`] opens the WebSocket, says hello and keeps the session
+//! going in the background: it answers the server's heartbeat pings and, if
+//! the connection drops, reconnects with exponential backoff. The room's
+//! traffic comes back as typed [`Event`]s; anything without an event of its
+//! own arrives as the raw [`SignalMessage`]. The wire types come from the
+//! `voca-protocol` crate the server is built on, so the two can't drift.
+//!
+//! Only plain `ws://` URLs are supported; put a TLS proxy in front to reach a
+//! `wss://` server. With the `webrtc` feature, [`media::Media`] turns the
+//! events into webrtc-rs peer connections.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), voca_client::Error> {
+//! use voca_client::{Client, Config, Event};
+//!
+//! let mut config = Config::new("ws://localhost:3001", "k3x9qa");
+//! config.state.name = Some("Notetaker".into());
+//! let (client, mut events) = Client::connect(config).await?;
+//! while let Some(event) = events.recv().await {
+//!     match event {
+//!         Event::Chat(message) if message.text == "!ping" => client.chat("pong")?,
+//!         Event::Closed { .. } => break,
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+mod config;
+mod event;
+#[cfg(feature = "webrtc")]
+pub mod media;
+
+pub use client::{Client, Error, Events};
+pub use config::{Config, Reconnect};
+pub use event::{Event, Welcome};
+
+pub use voca_protocol::chat::ChatMessage;
+pub use voca_protocol::error::{ApiError, ErrorCode};
+pub use voca_protocol::message::{SignalMessage, SignalPayload};
+pub use voca_protocol::peer::{PeerState, PeerUpdate};
+pub use voca_protocol::protocol::{NegotiationRole, Topology};
+pub use voca_protocol::stage::StageRole;
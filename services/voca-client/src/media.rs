@@ -0,0 +1,253 @@
+//! Audio over webrtc-rs, driven by the client's events.
+//!
+//! Feed every [`Event`] to [`Media::handle`] and it keeps one peer connection
+//! per peer in mesh rooms, or a single connection to the forwarder in SFU
+//! rooms, doing the offers, answers and ICE the protocol asks for. Audio from
+//! others arrives as [`RemoteTrack`]s; audio to send is a local track given
+//! to [`Media::set_track`]. Stage rooms on the fan-out topology aren't
+//! handled.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use webrtc::{
+    api::{interceptor_registry::register_default_interceptors, media_engine::MediaEngine, APIBuilder, API},
+    ice_transport::{ice_candidate::RTCIceCandidateInit, ice_server::RTCIceServer},
+    interceptor::registry::Registry,
+    peer_connection::{
+        configuration::RTCConfiguration, sdp::session_description::RTCSessionDescription, RTCPeerConnection,
+    },
+    rtp_transceiver::rtp_codec::RTPCodecType,
+    track::{track_local::TrackLocal, track_remote::TrackRemote},
+};
+
+use crate::{Client, Event, NegotiationRole, SignalPayload, Topology};
+use voca_protocol::protocol::SFU_PEER_ID;
+
+/// Audio from one peer in the room
+pub struct RemoteTrack {
+    pub peer_id: String,
+    pub track: Arc<TrackRemote>,
+}
+
+pub struct Media {
+    client: Client,
+    api: API,
+    config: RTCConfiguration,
+    track: Option<Arc<dyn TrackLocal + Send + Sync>>,
+    peer_id: String,
+    topology: Topology,
+    /// By remote peer id, or [`SFU_PEER_ID`] for the forwarder
+    connections: HashMap<String, Arc<RTCPeerConnection>>,
+    tracks: mpsc::UnboundedSender<RemoteTrack>,
+}
+
+impl Media {
+    /// Media for `client`'s session, with the remote tracks as they arrive
+    pub fn new(
+        client: Client,
+        ice_servers: Vec<RTCIceServer>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<RemoteTrack>), webrtc::Error> {
+        let mut media = MediaEngine::default();
+        media.register_default_codecs()?;
+        let registry = register_default_interceptors(Registry::new(), &mut media)?;
+        let api = APIBuilder::new()
+            .with_media_engine(media)
+            .with_interceptor_registry(registry)
+            .build();
+
+        let (tracks, tracks_rx) = mpsc::unbounded_channel();
+        let media = Self {
+            client,
+            api,
+            config: RTCConfiguration {
+                ice_servers,
+                ..Default::default()
+            },
+            track: None,
+            peer_id: String::new(),
+            topology: Topology::Mesh,
+            connections: HashMap::new(),
+            tracks,
+        };
+        Ok((media, tracks_rx))
+    }
+
+    /// Audio to send, usually an Opus `TrackLocalStaticSample`. Without one
+    /// the connections only receive. Applies to connections made afterwards.
+    pub fn set_track(&mut self, track: Arc<dyn TrackLocal + Send + Sync>) {
+        self.track = Some(track);
+    }
+
+    /// Act on one of the client's events
+    pub async fn handle(&mut self, event: &Event) -> Result<(), webrtc::Error> {
+        match event {
+            Event::Welcome(welcome) => {
+                // A new session; whoever was linked before is gone
+                self.close().await;
+                self.peer_id = welcome.peer_id.clone();
+                self.topology = welcome.topology;
+            }
+            Event::PeerJoined { peer_id, state, role } => {
+                // Bots have no audio, and the forwarder offers in SFU rooms
+                let bot = state.as_ref().is_some_and(|s| s.bot);
+                // Servers without roles expect existing peers to make the offer
+                let offer = role.unwrap_or(NegotiationRole::Impolite) == NegotiationRole::Impolite;
+                if self.topology == Topology::Mesh && !bot && offer {
+                    self.offer(peer_id).await?;
+                }
+            }
+            Event::PeerLeft { peer_id } => {
+                if let Some(pc) = self.connections.remove(peer_id) {
+                    pc.close().await?;
+                }
+            }
+            Event::Message(msg) => match &msg.payload {
+                SignalPayload::Offer { sdp, .. } => self.answer(&msg.from, sdp).await?,
+                SignalPayload::Answer { sdp, .. } => {
+                    if let Some(pc) = self.connections.get(&msg.from) {
+                        pc.set_remote_description(RTCSessionDescription::answer(sdp.clone())?)
+                            .await?;
+                    }
+                }
+                SignalPayload::Ice { candidate, .. } => {
+                    let Some(pc) = self.connections.get(&msg.from) else {
+                        return Ok(());
+                    };
+                    match serde_json::from_str::<RTCIceCandidateInit>(candidate) {
+                        Ok(candidate) => pc.add_ice_candidate(candidate).await?,
+                        Err(e) => warn!(peer_id = msg.from, error = %e, "Ignoring unreadable ICE candidate"),
+                    }
+                }
+                SignalPayload::TopologyChange { topology, peers } => {
+                    self.close().await;
+                    self.topology = *topology;
+                    // In a mesh each peer offers to everyone after it in join order
+                    if *topology == Topology::Mesh {
+                        let later: Vec<String> = peers
+                            .iter()
+                            .skip_while(|id| **id != self.peer_id)
+                            .skip(1)
+                            .cloned()
+                            .collect();
+                        for peer_id in later {
+                            self.offer(&peer_id).await?;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::Reconnecting { .. } | Event::Closed { .. } => self.close().await,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Close every connection
+    pub async fn close(&mut self) {
+        for (_, pc) in self.connections.drain() {
+            let _ = pc.close().await;
+        }
+    }
+
+    async fn offer(&mut self, peer_id: &str) -> Result<(), webrtc::Error> {
+        if self.topology == Topology::Fanout {
+            return Ok(());
+        }
+        let pc = self.connect(peer_id).await?;
+        self.add_audio(&pc).await?;
+
+        let offer = pc.create_offer(None).await?;
+        pc.set_local_description(offer.clone()).await?;
+        self.send(SignalPayload::Offer {
+            to: peer_id.to_string(),
+            sdp: offer.sdp,
+        });
+        Ok(())
+    }
+
+    async fn answer(&mut self, peer_id: &str, sdp: &str) -> Result<(), webrtc::Error> {
+        if self.topology == Topology::Fanout {
+            return Ok(());
+        }
+        // The forwarder renegotiates on the same connection as streams come and go
+        let pc = match self.connections.get(peer_id) {
+            Some(pc) => pc.clone(),
+            None => self.connect(peer_id).await?,
+        };
+        if pc.remote_description().await.is_none() {
+            self.add_audio(&pc).await?;
+        }
+        pc.set_remote_description(RTCSessionDescription::offer(sdp.to_string())?)
+            .await?;
+
+        let answer = pc.create_answer(None).await?;
+        pc.set_local_description(answer.clone()).await?;
+        self.send(SignalPayload::Answer {
+            to: peer_id.to_string(),
+            sdp: answer.sdp,
+        });
+        Ok(())
+    }
+
+    /// Our audio section. It sends and receives even without a local track:
+    /// the forwarder later adds the room's streams to the section it first
+    /// offered to receive on, and webrtc-rs only starts receivers on
+    /// transceivers that were created able to receive.
+    async fn add_audio(&self, pc: &RTCPeerConnection) -> Result<(), webrtc::Error> {
+        match &self.track {
+            Some(track) => pc.add_transceiver_from_track(track.clone(), &[]).await?,
+            // Sends a silent placeholder track
+            None => pc.add_transceiver_from_kind(RTPCodecType::Audio, &[]).await?,
+        };
+        Ok(())
+    }
+
+    /// New connection to `peer_id`, trickling ICE to it and passing on its audio
+    async fn connect(&mut self, peer_id: &str) -> Result<Arc<RTCPeerConnection>, webrtc::Error> {
+        let pc = Arc::new(self.api.new_peer_connection(self.config.clone()).await?);
+
+        let client = self.client.clone();
+        let to = peer_id.to_string();
+        pc.on_ice_candidate(Box::new(move |candidate| {
+            let candidate = candidate.and_then(|c| c.to_json().ok());
+            if let Some(candidate) = candidate.and_then(|c| serde_json::to_string(&c).ok()) {
+                let _ = client.send(SignalPayload::Ice {
+                    to: to.clone(),
+                    candidate,
+                });
+            }
+            Box::pin(async {})
+        }));
+
+        let tracks = self.tracks.clone();
+        let from = peer_id.to_string();
+        pc.on_track(Box::new(move |track, _receiver| {
+            let tracks = tracks.clone();
+            let from = from.clone();
+            Box::pin(async move {
+                let Some(track) = track else {
+                    return;
+                };
+                // The forwarder labels each stream with the peer it came from
+                let peer_id = if from == SFU_PEER_ID {
+                    track.stream_id().await
+                } else {
+                    from
+                };
+                debug!(peer_id, "Remote track");
+                let _ = tracks.send(RemoteTrack { peer_id, track });
+            })
+        }));
+
+        if let Some(previous) = self.connections.insert(peer_id.to_string(), pc.clone()) {
+            let _ = previous.close().await;
+        }
+        Ok(pc)
+    }
+
+    fn send(&self, payload: SignalPayload) {
+        let _ = self.client.send(payload);
+    }
+}
@@ -0,0 +1,97 @@
+//! Sessions against an in-process signaling server.
+
+// Shared with the signaling crate's own integration tests
+#[path = "../../signaling/tests/common/mod.rs"]
+mod common;
+
+use signaling::server::{ServerBuilder, ServerConfig};
+use voca_client::{Client, Config, ErrorCode, Error, Event, Events};
+
+use common::{create_room, expect, start, welcome};
+
+async fn join(url: &str, room: &str, name: &str) -> (Client, Events, String) {
+    let mut config = Config::new(url, room);
+    config.state.name = Some(name.to_string());
+    let (client, mut events) = Client::connect(config).await.unwrap();
+    let peer_id = welcome(&mut events).await.peer_id;
+    (client, events, peer_id)
+}
+
+#[tokio::test]
+async fn peers_see_each_other_and_chat() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let room = create_room(&server, "").await;
+
+    let (alice, mut alice_events, alice_id) = join(&url, &room, "alice").await;
+    let mut config = Config::new(&url, &room);
+    config.state.name = Some("bob".to_string());
+    let (bob, mut bob_events) = Client::connect(config).await.unwrap();
+    let welcome = welcome(&mut bob_events).await;
+    assert_eq!(welcome.peers[&alice_id].name.as_deref(), Some("alice"));
+
+    let Event::PeerJoined { peer_id, state, .. } =
+        expect(&mut alice_events, |e| matches!(e, Event::PeerJoined { .. })).await
+    else {
+        unreachable!()
+    };
+    assert_eq!(peer_id, welcome.peer_id);
+    assert_eq!(state.and_then(|s| s.name).as_deref(), Some("bob"));
+
+    alice.chat("hello").unwrap();
+    let Event::Chat(message) = expect(&mut bob_events, |e| matches!(e, Event::Chat(_))).await else {
+        unreachable!()
+    };
+    assert_eq!(message.from, alice_id);
+    assert_eq!(message.text, "hello");
+    assert!(!message.id.is_empty());
+
+    bob.close();
+    expect(&mut bob_events, |e| matches!(e, Event::Closed { error: None })).await;
+    let Event::PeerLeft { peer_id } = expect(&mut alice_events, |e| matches!(e, Event::PeerLeft { .. })).await else {
+        unreachable!()
+    };
+    assert_eq!(peer_id, welcome.peer_id);
+
+    alice.close();
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn app_events_reach_only_the_addressee() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+    let room = create_room(&server, "").await;
+
+    let (alice, _alice_events, alice_id) = join(&url, &room, "alice").await;
+    let (_bob, mut bob_events, bob_id) = join(&url, &room, "bob").await;
+    let (_carol, mut carol_events, _) = join(&url, &room, "carol").await;
+
+    alice.app("poke", serde_json::json!({ "n": 1 }), Some(bob_id)).unwrap();
+    alice.app("wave", serde_json::Value::Null, None).unwrap();
+
+    let Event::App { from, kind, data } = expect(&mut bob_events, |e| matches!(e, Event::App { .. })).await else {
+        unreachable!()
+    };
+    assert_eq!((from.as_str(), kind.as_str()), (alice_id.as_str(), "poke"));
+    assert_eq!(data["n"], 1);
+    // Carol's first app event is the broadcast, not the direct one
+    let Event::App { kind, .. } = expect(&mut carol_events, |e| matches!(e, Event::App { .. })).await else {
+        unreachable!()
+    };
+    assert_eq!(kind, "wave");
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn unknown_room_is_rejected() {
+    let (server, url) = start(ServerBuilder::new(ServerConfig::default())).await;
+
+    let result = Client::connect(Config::new(&url, "no-such-room")).await;
+    match result {
+        Err(Error::Rejected(error)) => assert_eq!(error.code, ErrorCode::RoomNotFound),
+        Err(e) => panic!("expected a rejection, got {}", e),
+        Ok(_) => panic!("expected a rejection, got a session"),
+    }
+
+    server.shutdown();
+}
@@ -0,0 +1,115 @@
+use nanoid::nanoid;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::error::{ApiError, ErrorCode};
+use crate::now_millis;
+
+// === CHAT LIMITS ===
+/// Messages kept per room for late joiners
+pub const CHAT_HISTORY_LIMIT: usize = 50;
+/// Longest chat message accepted, in characters
+pub const MAX_CHAT_LENGTH: usize = 2000;
+
+/// One chat message as stored in room history and delivered in Welcome
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ChatMessage {
+    /// Server-assigned id, used to edit or delete the message
+    pub id: String,
+    /// Author peer_id
+    pub from: String,
+    pub text: String,
+    /// Server time the message was posted, in Unix milliseconds
+    pub ts: u64,
+    #[serde(default)]
+    pub edited: bool,
+}
+
+/// Recent chat for one room. Lives in memory only and is dropped with the room.
+#[derive(Debug, Default)]
+pub struct ChatHistory {
+    messages: VecDeque<ChatMessage>,
+}
+
+impl ChatHistory {
+    /// Store a new message, evicting the oldest once the buffer is full
+    pub fn post(&mut self, from: &str, text: &str) -> Result<ChatMessage, ApiError> {
+        let text = validate_text(text)?;
+        let message = ChatMessage {
+            id: nanoid!(12),
+            from: from.to_string(),
+            text,
+            ts: now_millis(),
+            edited: false,
+        };
+
+        if self.messages.len() >= CHAT_HISTORY_LIMIT {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message.clone());
+        Ok(message)
+    }
+
+    /// Replace the text of a message; only its author may edit it
+    pub fn edit(&mut self, id: &str, by: &str, text: &str) -> Result<String, ApiError> {
+        let text = validate_text(text)?;
+        let message = self
+            .messages
+            .iter_mut()
+            .find(|m| m.id == id)
+            .ok_or(ErrorCode::ChatMessageNotFound)?;
+
+        if message.from != by {
+            return Err(ApiError::with_message(
+                ErrorCode::NotPermitted,
+                "Only the author can edit a message",
+            ));
+        }
+
+        message.text = text.clone();
+        message.edited = true;
+        Ok(text)
+    }
+
+    /// Remove a message; allowed for its author and for moderators
+    pub fn delete(&mut self, id: &str, by: &str, moderator: bool) -> Result<(), ApiError> {
+        let index = self
+            .messages
+            .iter()
+            .position(|m| m.id == id)
+            .ok_or(ErrorCode::ChatMessageNotFound)?;
+
+        if self.messages[index].from != by && !moderator {
+            return Err(ApiError::with_message(
+                ErrorCode::NotPermitted,
+                "Only the author or the room host can delete a message",
+            ));
+        }
+
+        self.messages.remove(index);
+        Ok(())
+    }
+
+    /// Copy of the current history, oldest first
+    pub fn snapshot(&self) -> Vec<ChatMessage> {
+        self.messages.iter().cloned().collect()
+    }
+}
+
+fn validate_text(text: &str) -> Result<String, ApiError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(ApiError::with_message(
+            ErrorCode::InvalidMessage,
+            "Chat message must not be empty",
+        ));
+    }
+    if text.chars().count() > MAX_CHAT_LENGTH {
+        return Err(ApiError::with_message(
+            ErrorCode::PayloadTooLarge,
+            format!("Chat message exceeds {} characters", MAX_CHAT_LENGTH),
+        ));
+    }
+    Ok(text.to_string())
+}
@@ -0,0 +1,249 @@
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Declares `ErrorCode` from one list of variants and their wire names, so
+/// serde, `as_str` and `ALL` can't disagree
+macro_rules! error_codes {
+    ($($(#[$meta:meta])* $variant:ident = $name:literal,)*) => {
+        /// Stable error codes shared by the REST API, the WebSocket protocol and the SDKs.
+        ///
+        /// The serialized names are part of the public protocol: never rename a
+        /// variant, only add new ones.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+        pub enum ErrorCode {
+            $($(#[$meta])* #[serde(rename = $name)] $variant,)*
+            /// A code this build doesn't know about, only produced when deserializing
+            #[serde(other, rename = "unknown")]
+            Unknown,
+        }
+
+        impl ErrorCode {
+            /// Every code the server can emit, in a stable order for exports
+            pub const ALL: &'static [ErrorCode] = &[$(ErrorCode::$variant,)*];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $name,)*
+                    ErrorCode::Unknown => "unknown",
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    // Room errors
+    RoomNotFound = "room_not_found",
+    RoomFull = "room_full",
+    RoomIdTaken = "room_id_taken",
+    RoomNotOpen = "room_not_open",
+    RoomClosed = "room_closed",
+    InvalidSchedule = "invalid_schedule",
+    MaxRoomsReached = "max_rooms_reached",
+    InvalidRoomIdLength = "invalid_room_id_length",
+    InvalidRoomIdChars = "invalid_room_id_chars",
+    SlugGenerationFailed = "slug_generation_failed",
+    // Auth errors
+    InvalidApiKey = "invalid_api_key",
+    Unauthorized = "unauthorized",
+    OriginNotAllowed = "origin_not_allowed",
+    Banned = "banned",
+    InvalidPassword = "invalid_password",
+    PasswordRequired = "password_required",
+    InvalidTopology = "invalid_topology",
+    RateLimited = "rate_limited",
+    // Server state
+    ServerDraining = "server_draining",
+    SfuUnavailable = "sfu_unavailable",
+    RecordingUnavailable = "recording_unavailable",
+    // Protocol errors
+    HandshakeRequired = "handshake_required",
+    HandshakeTimeout = "handshake_timeout",
+    WaitlistTimeout = "waitlist_timeout",
+    UnsupportedVersion = "unsupported_version",
+    InvalidMessage = "invalid_message",
+    PayloadTooLarge = "payload_too_large",
+    MessageKindNotAllowed = "message_kind_not_allowed",
+    // Room features
+    ChatMessageNotFound = "chat_message_not_found",
+    NotPermitted = "not_permitted",
+    StaleKeyEpoch = "stale_key_epoch",
+    StreamNotFound = "stream_not_found",
+    StageRoleFull = "stage_role_full",
+    BotNotFound = "bot_not_found",
+}
+
+impl ErrorCode {
+    /// HTTP status used when this error is returned from a REST endpoint
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::RoomNotFound
+            | ErrorCode::ChatMessageNotFound
+            | ErrorCode::StreamNotFound
+            | ErrorCode::BotNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::RoomFull | ErrorCode::RoomIdTaken | ErrorCode::StageRoleFull => StatusCode::CONFLICT,
+            ErrorCode::MaxRoomsReached | ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RoomNotOpen => StatusCode::FORBIDDEN,
+            ErrorCode::RoomClosed => StatusCode::GONE,
+            ErrorCode::InvalidRoomIdLength
+            | ErrorCode::InvalidRoomIdChars
+            | ErrorCode::InvalidSchedule
+            | ErrorCode::InvalidPassword
+            | ErrorCode::InvalidTopology
+            | ErrorCode::HandshakeRequired
+            | ErrorCode::InvalidMessage => StatusCode::BAD_REQUEST,
+            ErrorCode::StaleKeyEpoch => StatusCode::CONFLICT,
+            ErrorCode::InvalidApiKey | ErrorCode::Unauthorized | ErrorCode::PasswordRequired => {
+                StatusCode::UNAUTHORIZED
+            }
+            ErrorCode::OriginNotAllowed
+            | ErrorCode::Banned
+            | ErrorCode::MessageKindNotAllowed
+            | ErrorCode::NotPermitted => StatusCode::FORBIDDEN,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::ServerDraining | ErrorCode::SfuUnavailable | ErrorCode::RecordingUnavailable => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ErrorCode::HandshakeTimeout | ErrorCode::WaitlistTimeout => StatusCode::REQUEST_TIMEOUT,
+            ErrorCode::UnsupportedVersion => StatusCode::UPGRADE_REQUIRED,
+            ErrorCode::SlugGenerationFailed | ErrorCode::Unknown => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Whether retrying the same request later may succeed
+    pub fn retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::RoomFull
+                | ErrorCode::RoomNotOpen
+                | ErrorCode::StageRoleFull
+                | ErrorCode::MaxRoomsReached
+                | ErrorCode::RateLimited
+                | ErrorCode::SlugGenerationFailed
+                | ErrorCode::ServerDraining
+                | ErrorCode::HandshakeTimeout
+                | ErrorCode::WaitlistTimeout
+        )
+    }
+
+    pub fn default_message(self) -> &'static str {
+        match self {
+            ErrorCode::RoomNotFound => "Room not found",
+            ErrorCode::RoomFull => "Room is at maximum capacity",
+            ErrorCode::RoomIdTaken => "Room ID is already in use",
+            ErrorCode::RoomNotOpen => "Room is not open yet",
+            ErrorCode::RoomClosed => "Room has closed",
+            ErrorCode::InvalidSchedule => "ends_at must be in the future and after starts_at",
+            ErrorCode::MaxRoomsReached => "Maximum number of global rooms reached",
+            ErrorCode::InvalidRoomIdLength => "Room ID must be 4-32 characters",
+            ErrorCode::InvalidRoomIdChars => "Room ID may only contain letters, numbers and hyphens",
+            ErrorCode::SlugGenerationFailed => "Failed to generate unique room ID",
+            ErrorCode::InvalidApiKey => "Invalid API key",
+            ErrorCode::Unauthorized => "Invalid token",
+            ErrorCode::OriginNotAllowed => "Origin is not allowed for this app",
+            ErrorCode::Banned => "This address has been banned",
+            ErrorCode::InvalidPassword => "Incorrect password",
+            ErrorCode::PasswordRequired => "This room requires a password",
+            ErrorCode::InvalidTopology => "Topology must be mesh or sfu",
+            ErrorCode::RateLimited => "Too many requests, try again later",
+            ErrorCode::ServerDraining => "Server is shutting down and not accepting new rooms",
+            ErrorCode::SfuUnavailable => "SFU rooms are not enabled on this server",
+            ErrorCode::RecordingUnavailable => "Recording is not enabled on this server",
+            ErrorCode::HandshakeRequired => "First message must be hello",
+            ErrorCode::HandshakeTimeout => "No hello received before timeout",
+            ErrorCode::WaitlistTimeout => "No slot freed up before the waitlist timeout",
+            ErrorCode::UnsupportedVersion => "Protocol version is not supported",
+            ErrorCode::InvalidMessage => "Invalid signaling message",
+            ErrorCode::PayloadTooLarge => "Message payload exceeds the size limit",
+            ErrorCode::MessageKindNotAllowed => "Message kind is not allowed for this app",
+            ErrorCode::ChatMessageNotFound => "Chat message not found",
+            ErrorCode::NotPermitted => "Not permitted in this room",
+            ErrorCode::StaleKeyEpoch => "Key package is not for the current key epoch",
+            ErrorCode::StreamNotFound => "Stream session not found",
+            ErrorCode::StageRoleFull => "No free slot for that stage role",
+            ErrorCode::BotNotFound => "Bot not found in this room",
+            ErrorCode::Unknown => "Unknown error",
+        }
+    }
+}
+
+/// Machine-readable list of every error code, used to keep SDKs in sync
+pub fn catalogue() -> serde_json::Value {
+    let codes: Vec<serde_json::Value> = ErrorCode::ALL
+        .iter()
+        .map(|code| {
+            serde_json::json!({
+                "code": code.as_str(),
+                "status": code.status().as_u16(),
+                "retryable": code.retryable(),
+                "message": code.default_message(),
+            })
+        })
+        .collect();
+    serde_json::json!({ "codes": codes })
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error envelope used for WebSocket `error` messages, flattened, and for
+/// REST responses as [`RestError`]: `{ "code": "...", "message": "...", "retryable": false }`
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl ApiError {
+    pub fn with_message(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retryable: code.retryable(),
+        }
+    }
+}
+
+impl From<ErrorCode> for ApiError {
+    fn from(code: ErrorCode) -> Self {
+        Self::with_message(code, code.default_message())
+    }
+}
+
+/// REST form of [`ApiError`]. `error` repeats `code` under the name REST
+/// responses used before the envelope had `code`; it will be removed in the
+/// next release.
+#[derive(Serialize, JsonSchema)]
+pub struct RestError {
+    /// Deprecated alias of `code`
+    pub error: ErrorCode,
+    #[serde(flatten)]
+    pub inner: ApiError,
+}
+
+#[cfg(feature = "axum")]
+impl axum_core::response::IntoResponse for ApiError {
+    fn into_response(self) -> axum_core::response::Response {
+        let status = self.code.status();
+        let body = RestError {
+            error: self.code,
+            inner: self,
+        };
+        let body = serde_json::to_vec(&body).unwrap_or_default();
+        (status, [(http::header::CONTENT_TYPE, "application/json")], body).into_response()
+    }
+}
+
+#[cfg(feature = "axum")]
+impl axum_core::response::IntoResponse for ErrorCode {
+    fn into_response(self) -> axum_core::response::Response {
+        ApiError::from(self).into_response()
+    }
+}
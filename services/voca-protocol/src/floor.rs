@@ -0,0 +1,98 @@
+//! Floor control for moderated rooms.
+//!
+//! Peers raise and lower their hands; the server keeps the queue in the
+//! order hands went up and broadcasts the whole floor after every change.
+//! The host grants the floor to one peer at a time. In single-speaker
+//! (push-to-talk) mode only the floor holder may be unmuted: everyone else
+//! is force-muted and their requests to unmute are refused.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Raised hands and the current floor holder
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Floor {
+    /// Peers with a raised hand, earliest first
+    #[serde(default)]
+    pub queue: Vec<String>,
+    /// Peer the host gave the floor to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holder: Option<String>,
+    /// Only the holder may be unmuted
+    #[serde(default)]
+    pub single_speaker: bool,
+}
+
+impl Floor {
+    /// Nothing queued, nobody holding the floor and no single-speaker mode
+    pub fn is_idle(&self) -> bool {
+        *self == Floor::default()
+    }
+
+    /// Add a peer to the end of the queue. Returns false if their hand is
+    /// already up or they hold the floor.
+    pub fn raise(&mut self, peer_id: &str) -> bool {
+        if self.holder.as_deref() == Some(peer_id) || self.queue.iter().any(|p| p == peer_id) {
+            return false;
+        }
+        self.queue.push(peer_id.to_string());
+        true
+    }
+
+    /// Take a peer's hand down, giving up the floor if they hold it.
+    /// Returns false if nothing changed.
+    pub fn lower(&mut self, peer_id: &str) -> bool {
+        let queued = self.queue.len();
+        self.queue.retain(|p| p != peer_id);
+        let released = self.holder.as_deref() == Some(peer_id);
+        if released {
+            self.holder = None;
+        }
+        released || self.queue.len() != queued
+    }
+
+    /// Give the floor to a peer, taking them out of the queue, or take it
+    /// back with `None`
+    pub fn grant(&mut self, peer_id: Option<&str>) {
+        if let Some(peer_id) = peer_id {
+            self.queue.retain(|p| p != peer_id);
+        }
+        self.holder = peer_id.map(str::to_string);
+    }
+
+    /// Whether a peer may unmute under the current mode
+    pub fn may_unmute(&self, peer_id: &str) -> bool {
+        !self.single_speaker || self.holder.as_deref() == Some(peer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_holder_may_unmute_in_single_speaker_mode() {
+        let mut floor = Floor {
+            single_speaker: true,
+            ..Floor::default()
+        };
+        assert!(floor.raise("a"));
+        assert!(floor.raise("b"));
+        assert!(!floor.raise("a"));
+        assert!(!floor.may_unmute("a"));
+
+        floor.grant(Some("a"));
+        assert_eq!(floor.queue, ["b"]);
+        assert!(floor.may_unmute("a"));
+        assert!(!floor.may_unmute("b"));
+
+        // Lowering the holder's hand gives the floor up
+        assert!(floor.lower("a"));
+        assert_eq!(floor.holder, None);
+        assert!(!floor.may_unmute("a"));
+        assert!(!floor.lower("a"));
+
+        floor.single_speaker = false;
+        assert!(floor.may_unmute("b"));
+    }
+}
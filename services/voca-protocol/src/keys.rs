@@ -0,0 +1,48 @@
+//! Key distribution for end-to-end encrypted media. The server tracks which
+//! key epoch a room is in and relays encrypted key packages between peers;
+//! it never sees a key in plaintext.
+
+use crate::error::{ApiError, ErrorCode};
+
+/// Largest ciphertext accepted in one key package
+pub const MAX_KEY_PACKAGE_BYTES: usize = 4096;
+/// Largest public key a peer may publish in its state
+pub const MAX_PUBLIC_KEY_BYTES: usize = 1024;
+
+/// Check a key package against the room's current epoch before relaying it
+pub fn check_package(room_epoch: u64, epoch: u64, ciphertext: &str) -> Result<(), ApiError> {
+    if ciphertext.is_empty() {
+        return Err(ApiError::with_message(
+            ErrorCode::InvalidMessage,
+            "Key package ciphertext must not be empty",
+        ));
+    }
+    if ciphertext.len() > MAX_KEY_PACKAGE_BYTES {
+        return Err(ApiError::with_message(
+            ErrorCode::PayloadTooLarge,
+            format!("Key package exceeds {} bytes", MAX_KEY_PACKAGE_BYTES),
+        ));
+    }
+    if epoch != room_epoch {
+        return Err(ApiError::with_message(
+            ErrorCode::StaleKeyEpoch,
+            format!("Key package is for epoch {}, room is at epoch {}", epoch, room_epoch),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_must_match_the_room_epoch() {
+        assert!(check_package(3, 3, "sealed").is_ok());
+        assert_eq!(check_package(3, 2, "sealed").unwrap_err().code, ErrorCode::StaleKeyEpoch);
+        assert_eq!(check_package(3, 4, "sealed").unwrap_err().code, ErrorCode::StaleKeyEpoch);
+        assert_eq!(check_package(3, 3, "").unwrap_err().code, ErrorCode::InvalidMessage);
+        let oversized = "x".repeat(MAX_KEY_PACKAGE_BYTES + 1);
+        assert_eq!(check_package(3, 3, &oversized).unwrap_err().code, ErrorCode::PayloadTooLarge);
+    }
+}
@@ -0,0 +1,21 @@
+//! The Voca signaling protocol: the messages sent over the WebSocket, the
+//! error codes shared with the REST API, and the limits and rules both ends
+//! agree on. Shared by the `signaling` server and the `voca-client` crate,
+//! without pulling in either one's runtime.
+
+pub mod chat;
+pub mod error;
+pub mod floor;
+pub mod keys;
+pub mod message;
+pub mod peer;
+pub mod protocol;
+pub mod stage;
+
+/// Current server time in Unix milliseconds, used for client-visible timestamps
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,262 @@
+//! Messages exchanged over the signaling WebSocket.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::chat::ChatMessage;
+use crate::error::ApiError;
+use crate::floor::Floor;
+use crate::peer::{PeerState, PeerUpdate};
+use crate::protocol::{default_client_protocol, Capability, NegotiationRole, Topology};
+use crate::stage::StageRole;
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SignalMessage {
+    /// Sender peer_id or "server"; overwritten by the server on relay
+    #[serde(default)]
+    pub from: String,
+    #[serde(flatten)]
+    pub payload: SignalPayload,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SignalPayload {
+    /// Client must send hello on connect with version info and wanted features
+    Hello {
+        version: String,
+        client: String,
+        #[serde(default = "default_client_protocol")]
+        protocol: u32,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+        /// Initial display name, avatar, mute and metadata
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        state: Option<PeerUpdate>,
+    },
+    /// Sent instead of welcome while the client waits for a slot in a full
+    /// room, and again whenever its place in the queue changes
+    Waiting {
+        /// 1-based place in the queue
+        position: usize,
+    },
+    /// Server responds with welcome, assigned peer_id and the negotiated features
+    Welcome {
+        version: String,
+        peer_id: String,
+        protocol: u32,
+        capabilities: Vec<Capability>,
+        /// Peers already in the room, present when `roster` was negotiated
+        #[serde(skip_serializing_if = "Option::is_none")]
+        peers: Option<Vec<String>>,
+        /// State of each peer in `peers`, present when `roster` was negotiated
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        states: Option<BTreeMap<String, PeerState>>,
+        /// Your negotiation role toward each peer in `peers`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        roles: Option<BTreeMap<String, NegotiationRole>>,
+        /// Current room host, who may moderate chat
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        host: Option<String>,
+        /// Recent chat history, oldest first
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        chat: Vec<ChatMessage>,
+        /// Current end-to-end encryption key epoch
+        #[serde(default)]
+        key_epoch: u64,
+        /// With `sfu`, negotiate only with the `sfu` peer instead of each peer
+        #[serde(default)]
+        topology: Topology,
+        /// Id of the recording in progress; answer with `RecordingConsent`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        recording: Option<String>,
+        /// Your role in a stage room; listeners don't send audio
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stage_role: Option<StageRole>,
+        /// Raised hands and floor holder, omitted when nobody uses the floor
+        #[serde(default, skip_serializing_if = "Floor::is_idle")]
+        floor: Floor,
+        /// Unix milliseconds when the room closes, if it has an end
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        closes_at: Option<u64>,
+    },
+    Join {
+        peer_id: String,
+        /// The joining peer's state as set in Hello
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        state: Option<PeerState>,
+        /// The recipient's negotiation role toward the joining peer
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        role: Option<NegotiationRole>,
+    },
+    Leave { peer_id: String },
+    Offer { to: String, sdp: String },
+    Answer { to: String, sdp: String },
+    Ice { to: String, candidate: String },
+    Mute { muted: bool },
+    /// Change to a peer's display name, avatar, mute or metadata. Clients send
+    /// only `changes`; the server fills in `peer_id` and relays the diff.
+    #[serde(rename = "peer_update")]
+    PeerUpdate {
+        #[serde(default)]
+        peer_id: String,
+        changes: PeerUpdate,
+    },
+    /// Host role moved to another peer after the previous host left
+    Host { peer_id: String },
+    /// Chat message; clients send only `text`, the server fills in `id` and `ts`
+    /// and echoes it back to the author
+    Chat {
+        #[serde(default)]
+        id: String,
+        text: String,
+        #[serde(default)]
+        ts: u64,
+    },
+    /// Author replaced the text of a chat message
+    #[serde(rename = "chat_edit")]
+    ChatEdit { id: String, text: String },
+    /// Chat message removed by its author or the host
+    #[serde(rename = "chat_delete")]
+    ChatDelete { id: String },
+    /// Room key encrypted by the sender for one recipient. The server only
+    /// relays the ciphertext and checks it is for the current epoch.
+    #[serde(rename = "key_package")]
+    KeyPackage {
+        to: String,
+        epoch: u64,
+        ciphertext: String,
+    },
+    /// The room key must be replaced. `distributor` generates the new key and
+    /// sends it to every other peer as key packages for `epoch`.
+    #[serde(rename = "key_epoch")]
+    KeyEpoch {
+        epoch: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        distributor: Option<String>,
+    },
+    /// Ask the server to start a new key epoch; host only
+    Rekey,
+    /// The room switched between mesh and SFU. Peers drop their current
+    /// connections; for mesh, each peer offers to those later in `peers`,
+    /// which lists everyone in join order. For SFU the server sends offers.
+    #[serde(rename = "topology_change")]
+    TopologyChange {
+        topology: Topology,
+        peers: Vec<String>,
+    },
+    /// Ask the server to start recording the room; host only
+    #[serde(rename = "recording_start")]
+    RecordingStart,
+    /// Ask the server to stop the current recording; host only
+    #[serde(rename = "recording_stop")]
+    RecordingStop,
+    /// A recording began. Peers are not captured until they send a
+    /// positive `RecordingConsent` for it.
+    #[serde(rename = "recording_started")]
+    RecordingStarted { id: String, by: String },
+    #[serde(rename = "recording_stopped")]
+    RecordingStopped { id: String },
+    /// Give or withdraw consent to be recorded; relayed to the room with
+    /// `peer_id` filled in
+    #[serde(rename = "recording_consent")]
+    RecordingConsent {
+        #[serde(default)]
+        peer_id: String,
+        consent: bool,
+    },
+    /// Listener asks to speak; relayed to the room with `peer_id` filled in
+    #[serde(rename = "speak_request")]
+    SpeakRequest {
+        #[serde(default)]
+        peer_id: String,
+    },
+    /// Move a peer between speaker and listener in a stage room. Sent by the
+    /// host, or by a speaker stepping down, and relayed to the whole room.
+    /// Setting a listener's current role turns down their request to speak.
+    #[serde(rename = "set_role")]
+    SetRole { peer_id: String, stage_role: StageRole },
+    /// Put your hand up; answered with the updated `Floor`
+    #[serde(rename = "raise_hand")]
+    RaiseHand,
+    /// Take a hand down, giving up the floor if held. `peer_id` defaults to
+    /// yourself; only the host may lower someone else's hand.
+    #[serde(rename = "lower_hand")]
+    LowerHand {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        peer_id: Option<String>,
+    },
+    /// Give the floor to a peer, or take it back when `peer_id` is omitted;
+    /// host only
+    #[serde(rename = "grant_floor")]
+    GrantFloor {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        peer_id: Option<String>,
+    },
+    /// Turn push-to-talk mode on or off; host only
+    #[serde(rename = "single_speaker")]
+    SingleSpeaker { enabled: bool },
+    /// The floor after any change, sent to the whole room
+    Floor(Floor),
+    /// The server muted a peer because someone else holds the floor
+    #[serde(rename = "force_mute")]
+    ForceMute { peer_id: String },
+    /// The room closes soon; sent at each warning interval before it does,
+    /// then everyone gets a `room_closed` error
+    Closing {
+        /// Unix milliseconds
+        closes_at: u64,
+        seconds_left: u64,
+    },
+    /// Opaque application event relayed without interpretation, to one peer
+    /// when `to` is set or to the whole room otherwise
+    App {
+        kind: String,
+        data: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        to: Option<String>,
+    },
+    /// Notice for the whole room, shown apart from chat and not kept in its
+    /// history; only bots may send one
+    Announcement { text: String },
+    Ping,
+    Pong,
+    /// Error envelope shared with the REST API
+    Error(ApiError),
+}
+
+impl SignalMessage {
+    /// Whether a room broadcast should be delivered to `peer_id`. Senders
+    /// don't get their own messages back, except chat so the author learns
+    /// the server-assigned id and timestamp, and the state changes they asked
+    /// for (key epochs, recordings, stage roles, the floor). Messages for one
+    /// peer reach only that peer.
+    pub fn reaches(&self, peer_id: &str) -> bool {
+        let echo = matches!(
+            self.payload,
+            SignalPayload::Chat { .. }
+                | SignalPayload::ChatEdit { .. }
+                | SignalPayload::ChatDelete { .. }
+                | SignalPayload::KeyEpoch { .. }
+                | SignalPayload::RecordingStarted { .. }
+                | SignalPayload::RecordingStopped { .. }
+                | SignalPayload::RecordingConsent { .. }
+                | SignalPayload::SetRole { .. }
+                | SignalPayload::Floor(_)
+        );
+        if self.from == peer_id && !echo {
+            return false;
+        }
+
+        match &self.payload {
+            SignalPayload::Offer { to, .. }
+            | SignalPayload::Answer { to, .. }
+            | SignalPayload::Ice { to, .. }
+            | SignalPayload::KeyPackage { to, .. } => to == peer_id,
+            SignalPayload::App { to: Some(to), .. } => to == peer_id,
+            SignalPayload::Ping | SignalPayload::Pong => false,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::{ApiError, ErrorCode};
+use crate::keys::MAX_PUBLIC_KEY_BYTES;
+use crate::stage::StageRole;
+
+// === PEER STATE LIMITS ===
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 64;
+pub const MAX_AVATAR_URL_LENGTH: usize = 512;
+/// Serialized size of a peer's metadata object
+pub const MAX_PEER_METADATA_BYTES: usize = 1024;
+
+/// What the room knows about one connected peer
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PeerState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub muted: bool,
+    /// Small application-defined JSON object
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+    /// Public key other peers use to encrypt key packages for this peer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    /// Server time the peer joined, in Unix milliseconds
+    #[serde(default)]
+    pub joined_at: u64,
+    /// Media source streaming in over WHIP; it never receives room audio
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ingest: bool,
+    /// Backend service taking part over the bot API; it has no audio
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bot: bool,
+    /// Agreed to be captured by the room's current recording
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recording_consent: bool,
+    /// Speaker or listener, in stage rooms only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage_role: Option<StageRole>,
+    /// Listener waiting for the host to let them speak
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub speak_requested: bool,
+}
+
+/// Partial update to a peer's state. Omitted fields are left unchanged; an
+/// empty `name` or `avatar` clears it, and `metadata` keys are merged with
+/// `null` values removing a key.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PeerUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+impl PeerUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.avatar.is_none()
+            && self.muted.is_none()
+            && self.metadata.is_none()
+            && self.public_key.is_none()
+    }
+}
+
+impl PeerState {
+    pub fn new(joined_at: u64) -> Self {
+        Self {
+            joined_at,
+            ..Self::default()
+        }
+    }
+
+    /// Validate and apply an update, returning the normalized diff to broadcast.
+    /// Nothing is changed if any field is rejected.
+    pub fn apply(&mut self, update: PeerUpdate) -> Result<PeerUpdate, ApiError> {
+        let name = update.name.map(|n| n.trim().to_string());
+        if let Some(name) = &name
+            && name.chars().count() > MAX_DISPLAY_NAME_LENGTH
+        {
+            return Err(ApiError::with_message(
+                ErrorCode::InvalidMessage,
+                format!("Display name exceeds {} characters", MAX_DISPLAY_NAME_LENGTH),
+            ));
+        }
+
+        let avatar = update.avatar.map(|a| a.trim().to_string());
+        if let Some(avatar) = &avatar
+            && !avatar.is_empty()
+            && (avatar.len() > MAX_AVATAR_URL_LENGTH
+                || !(avatar.starts_with("https://") || avatar.starts_with("http://")))
+        {
+            return Err(ApiError::with_message(
+                ErrorCode::InvalidMessage,
+                format!("Avatar must be an http(s) URL of at most {} bytes", MAX_AVATAR_URL_LENGTH),
+            ));
+        }
+
+        if let Some(key) = &update.public_key
+            && key.len() > MAX_PUBLIC_KEY_BYTES
+        {
+            return Err(ApiError::with_message(
+                ErrorCode::PayloadTooLarge,
+                format!("Public key exceeds {} bytes", MAX_PUBLIC_KEY_BYTES),
+            ));
+        }
+
+        let metadata = match &update.metadata {
+            Some(changes) => {
+                let mut merged = self.metadata.clone();
+                for (k, v) in changes {
+                    if v.is_null() {
+                        merged.remove(k);
+                    } else {
+                        merged.insert(k.clone(), v.clone());
+                    }
+                }
+                let size = serde_json::to_vec(&merged).map(|v| v.len()).unwrap_or(usize::MAX);
+                if size > MAX_PEER_METADATA_BYTES {
+                    return Err(ApiError::with_message(
+                        ErrorCode::PayloadTooLarge,
+                        format!("Peer metadata exceeds {} bytes", MAX_PEER_METADATA_BYTES),
+                    ));
+                }
+                Some(merged)
+            }
+            None => None,
+        };
+
+        if let Some(name) = &name {
+            self.name = Some(name.clone()).filter(|n| !n.is_empty());
+        }
+        if let Some(avatar) = &avatar {
+            self.avatar = Some(avatar.clone()).filter(|a| !a.is_empty());
+        }
+        if let Some(muted) = update.muted {
+            self.muted = muted;
+        }
+        if let Some(metadata) = metadata {
+            self.metadata = metadata;
+        }
+        if let Some(key) = &update.public_key {
+            self.public_key = Some(key.clone()).filter(|k| !k.is_empty());
+        }
+
+        Ok(PeerUpdate {
+            name,
+            avatar,
+            muted: update.muted,
+            metadata: update.metadata,
+            public_key: update.public_key,
+        })
+    }
+}
@@ -0,0 +1,94 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// === PROTOCOL ===
+/// Oldest protocol accepted. Version 1 clients predate the `protocol` field.
+pub const PROTOCOL_VERSION_MIN: u32 = 1;
+/// Newest protocol this server speaks
+pub const PROTOCOL_VERSION_MAX: u32 = 2;
+
+/// How long a new connection has to send Hello before it is dropped
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Optional protocol features agreed during the handshake
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// Compact binary frames instead of JSON text
+    Binary,
+    /// Current peer list delivered in Welcome
+    Roster,
+    /// Anything this server doesn't know about; never negotiated
+    #[serde(other)]
+    Unknown,
+}
+
+/// A peer's perfect-negotiation role toward one other peer.
+///
+/// Roles follow join order: the peer that was in the room first is impolite,
+/// sends the initial offer and wins offer collisions; the newer peer is
+/// polite, waits for that offer and rolls back its own on glare.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NegotiationRole {
+    Polite,
+    Impolite,
+}
+
+/// How media flows in a room
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Topology {
+    /// Every peer connects to every other peer
+    #[default]
+    Mesh,
+    /// Every peer connects only to the server, which forwards audio
+    Sfu,
+    /// Stage rooms without the SFU: speakers connect to each other and to
+    /// every listener, listeners don't connect to each other
+    Fanout,
+}
+
+/// Peer id the server's forwarder uses in signaling messages
+pub const SFU_PEER_ID: &str = "sfu";
+
+/// Features this server is able to enable
+pub const SERVER_CAPABILITIES: &[Capability] = &[Capability::Binary, Capability::Roster];
+
+/// Outcome of a successful handshake
+#[derive(Clone, Debug)]
+pub struct Negotiated {
+    pub protocol: u32,
+    pub capabilities: Vec<Capability>,
+}
+
+impl Negotiated {
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// Agree on a protocol version and feature set with the client.
+/// Returns `None` if the client's version is outside the supported range.
+pub fn negotiate(client_protocol: u32, requested: &[Capability]) -> Option<Negotiated> {
+    if !(PROTOCOL_VERSION_MIN..=PROTOCOL_VERSION_MAX).contains(&client_protocol) {
+        return None;
+    }
+
+    let mut capabilities = Vec::new();
+    for c in requested {
+        if SERVER_CAPABILITIES.contains(c) && !capabilities.contains(c) {
+            capabilities.push(*c);
+        }
+    }
+
+    Some(Negotiated {
+        protocol: client_protocol,
+        capabilities,
+    })
+}
+
+pub fn default_client_protocol() -> u32 {
+    PROTOCOL_VERSION_MIN
+}
@@ -0,0 +1,101 @@
+//! Stage rooms: a few speakers talk while everyone else listens.
+//!
+//! The first peer in a stage room is a speaker and everyone after joins as a
+//! listener. Listeners ask to speak with `SpeakRequest`; the host moves peers
+//! between roles with `SetRole`, and a speaker may step down on their own.
+//! Each role has its own capacity.
+//!
+//! Listeners never send audio. With the SFU the forwarder doesn't pass it on;
+//! without it the room uses the fan-out topology, where speakers connect to
+//! each other and to every listener but listeners don't connect at all.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::error::{ApiError, ErrorCode};
+use crate::peer::PeerState;
+
+// === STAGE LIMITS ===
+/// Most speakers a stage room allows; in fan-out rooms they form a mesh
+pub const MAX_STAGE_SPEAKERS: usize = 6;
+/// Every speaker uploads to every listener in fan-out rooms
+pub const MAX_FANOUT_LISTENERS: usize = 24;
+
+/// What a peer may do in a stage room
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StageRole {
+    /// Sends audio to the room
+    Speaker,
+    /// Only receives audio
+    Listener,
+}
+
+/// Capacity of each role in a stage room
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Stage {
+    pub max_speakers: usize,
+    pub max_listeners: usize,
+}
+
+impl Stage {
+    /// Role for a peer joining now: the first peer speaks so the room has
+    /// someone to hear, everyone after listens
+    pub fn joining_role(&self, peers: &HashMap<String, PeerState>) -> StageRole {
+        if peers.is_empty() {
+            StageRole::Speaker
+        } else {
+            StageRole::Listener
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_speakers + self.max_listeners
+    }
+
+    /// Whether another peer can take `role`
+    pub fn has_slot(&self, peers: &HashMap<String, PeerState>, role: StageRole) -> bool {
+        let max = match role {
+            StageRole::Speaker => self.max_speakers,
+            StageRole::Listener => self.max_listeners,
+        };
+        count(peers, role) < max
+    }
+
+    /// Check that `peer_id` may move to `role`, for a move made by `by`
+    pub fn check_move(
+        &self,
+        peers: &HashMap<String, PeerState>,
+        host: Option<&str>,
+        by: &str,
+        peer_id: &str,
+        role: StageRole,
+    ) -> Result<(), ApiError> {
+        let peer = peers
+            .get(peer_id)
+            .ok_or_else(|| ApiError::with_message(ErrorCode::InvalidMessage, "No such peer in this room"))?;
+
+        // Anyone may step down; everything else is up to the host
+        let stepping_down = by == peer_id && role == StageRole::Listener;
+        if host != Some(by) && !stepping_down {
+            return Err(ApiError::with_message(ErrorCode::NotPermitted, "Only the host can change stage roles"));
+        }
+        if peer.ingest && role == StageRole::Listener {
+            return Err(ApiError::with_message(ErrorCode::InvalidMessage, "Stream sources are always speakers"));
+        }
+        if peer.stage_role != Some(role) && !self.has_slot(peers, role) {
+            return Err(ErrorCode::StageRoleFull.into());
+        }
+        Ok(())
+    }
+}
+
+/// Whether two peers of a fan-out room hold a connection
+pub fn linked(a: Option<StageRole>, b: Option<StageRole>) -> bool {
+    a == Some(StageRole::Speaker) || b == Some(StageRole::Speaker)
+}
+
+fn count(peers: &HashMap<String, PeerState>, role: StageRole) -> usize {
+    peers.values().filter(|p| p.stage_role == Some(role)).count()
+}